@@ -10,7 +10,7 @@ fn main() {
 
     for _ in 0..10 {
         sleep(Duration::from_millis(100));
-        uuids.push(queue.enqueue(vec![], 30).unwrap());
+        uuids.push(queue.enqueue_for(vec![], Duration::from_secs(30)).unwrap());
     }
 
     sleep(Duration::from_millis(10000));
@@ -3,15 +3,21 @@ extern crate rjq;
 use std::time::Duration;
 use std::thread::sleep;
 use std::error::Error;
-use rjq::Queue;
+use rjq::{Queue, WorkOptions};
 
 fn main() {
-    fn process(uuid: String, _: Vec<String>) -> Result<String, Box<Error>> {
+    fn process(uuid: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
         sleep(Duration::from_millis(1000));
         println!("{}", uuid);
         Ok(format!("hi from {}", uuid))
     }
 
     let queue = Queue::new("redis://localhost/", "rjq");
-    queue.work(process, None, Some(5), Some(10), None, Some(false), None).unwrap();
+    let options = WorkOptions {
+        timeout: Duration::from_secs(5),
+        freq: 10,
+        fall: false,
+        ..Default::default()
+    };
+    queue.work_with(process, &options).unwrap();
 }
@@ -0,0 +1,395 @@
+//! Queue backends.
+//!
+//! `Backend` covers the core of the queue API: enqueue a job, work on jobs and read back their
+//! outcome. It is implemented by the Redis backed [`Queue`](../struct.Queue.html) and by
+//! `MemoryQueue`, which keeps jobs in process memory, so that code written against `Backend` can
+//! be tested without a Redis server.
+//!
+//! ```rust,ignore
+//! extern crate rjq;
+//!
+//! use std::error::Error;
+//! use std::time::Duration;
+//! use rjq::{Backend, MemoryQueue, Queue};
+//!
+//! fn submit<B: Backend>(queue: &B) -> Result<String, Box<dyn Error>> {
+//!     queue.enqueue(vec!["report".to_string()], Duration::from_secs(30))
+//! }
+//!
+//! // Production code runs on Redis, tests on memory
+//! submit(&Queue::new("redis://localhost/", "rjq"))?;
+//! submit(&MemoryQueue::new())?;
+//! ```
+//!
+//! `MemoryQueue` follows the Redis backend for job statuses, expiration, timeouts, `LostPolicy`
+//! and `FallPolicy`. Jobs aren't shared between processes and are gone once the queue is dropped,
+//! queue options such as shards and kinds don't apply to it. `MemoryQueue::with_clock` runs it on
+//! a `MockClock`, so that job timeouts and expiration are checked without waiting for them.
+//!
+//! `Backend` is the point to inject a fake of a whole queue into code using it. `Queue` relies on
+//! Lua scripts, transactions and pipelines to keep its keys consistent, a custom
+//! [`Store`](../store/trait.Store.html) only serves the reads of job statuses and results. A
+//! custom backend implements the five methods, e.g. a fake which only records enqueued jobs:
+//!
+//! ```rust,ignore
+//! struct Recorder {
+//!     enqueued: Mutex<Vec<Vec<String>>>,
+//! }
+//!
+//! impl Backend for Recorder {
+//!     fn enqueue(&self, args: Vec<String>, _: Duration) -> Result<String, Box<dyn Error>> {
+//!         let mut enqueued = self.enqueued.lock().unwrap();
+//!         enqueued.push(args);
+//!         Ok(enqueued.len().to_string())
+//!     }
+//!
+//!     fn status(&self, _: &str) -> Result<Status, Box<dyn Error>> {
+//!         Ok(Status::QUEUED)
+//!     }
+//!
+//!     // result, cancel and work the same way
+//! }
+//! ```
+//!
+//! References, `Box` and `Arc` of a backend are backends too, so a backend can be shared by the
+//! code under test and the test itself.
+
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::marker::{Send, Sync};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+use super::clock::{Clock, SystemClock};
+use super::{check_freq, execute, stop_after, FallPolicy, LostPolicy, Queue, RjqError,
+            Status, WorkOptions, WorkSummary, CLAIM_POLL};
+
+/// Core queue operations shared by queue backends
+pub trait Backend {
+    /// Enqueue new job expiring in `expire` if it hasn't started, returns its identifier
+    fn enqueue(&self, args: Vec<String>, expire: Duration) -> Result<String, Box<dyn Error>>;
+
+    /// Get job status
+    fn status(&self, uuid: &str) -> Result<Status, Box<dyn Error>>;
+
+    /// Get job result
+    fn result(&self, uuid: &str) -> Result<Option<String>, Box<dyn Error>>;
+
+    /// Cancel queued job, returns false if the job doesn't exist or isn't queued anymore
+    fn cancel(&self, uuid: &str) -> Result<bool, Box<dyn Error>>;
+
+    /// Work on queue, process enqueued jobs, see `Queue::work_with`
+    fn work<F>(&self, fun: F, options: &WorkOptions) -> Result<WorkSummary, Box<dyn Error>>
+        where F: Fn(String, Vec<String>) -> Result<String, Box<dyn Error>> + Send + Sync + 'static,
+              Self: Sized;
+}
+
+impl Backend for Queue {
+    fn enqueue(&self, args: Vec<String>, expire: Duration) -> Result<String, Box<dyn Error>> {
+        Queue::enqueue_for(self, args, expire)
+    }
+
+    fn status(&self, uuid: &str) -> Result<Status, Box<dyn Error>> {
+        Queue::status(self, uuid)
+    }
+
+    fn result(&self, uuid: &str) -> Result<Option<String>, Box<dyn Error>> {
+        Queue::result(self, uuid)
+    }
+
+    fn cancel(&self, uuid: &str) -> Result<bool, Box<dyn Error>> {
+        Queue::cancel(self, uuid)
+    }
+
+    fn work<F>(&self, fun: F, options: &WorkOptions) -> Result<WorkSummary, Box<dyn Error>>
+        where F: Fn(String, Vec<String>) -> Result<String, Box<dyn Error>> + Send + Sync + 'static
+    {
+        Queue::work_with(self, fun, options)
+    }
+}
+
+/// Implement `Backend` for a pointer type by forwarding to the backend it points to
+macro_rules! forward_backend {
+    ($($pointer:ty),*) => {
+        $(
+        impl<'a, B: Backend> Backend for $pointer {
+            fn enqueue(&self,
+                       args: Vec<String>,
+                       expire: Duration)
+                       -> Result<String, Box<dyn Error>> {
+                (**self).enqueue(args, expire)
+            }
+
+            fn status(&self, uuid: &str) -> Result<Status, Box<dyn Error>> {
+                (**self).status(uuid)
+            }
+
+            fn result(&self, uuid: &str) -> Result<Option<String>, Box<dyn Error>> {
+                (**self).result(uuid)
+            }
+
+            fn cancel(&self, uuid: &str) -> Result<bool, Box<dyn Error>> {
+                (**self).cancel(uuid)
+            }
+
+            fn work<F>(&self, fun: F, options: &WorkOptions) -> Result<WorkSummary, Box<dyn Error>>
+                where F: Fn(String, Vec<String>) -> Result<String, Box<dyn Error>>
+                             + Send
+                             + Sync
+                             + 'static
+            {
+                (**self).work(fun, options)
+            }
+        }
+        )*
+    };
+}
+
+forward_backend!(&'a B, Box<B>, Arc<B>);
+
+/// Job kept in memory
+struct Entry {
+    status: Status,
+    args: Vec<String>,
+    result: Option<String>,
+    attempts: usize,
+    /// Time the job is removed unless it is running, on the elapsed time of the clock
+    expires: Duration,
+}
+
+/// Jobs and the ready list of a memory queue
+#[derive(Default)]
+struct State {
+    jobs: HashMap<String, Entry>,
+    ready: VecDeque<String>,
+}
+
+impl State {
+    /// Get job unless it has expired by `now`, removing expired jobs on the way
+    fn job(&mut self, uuid: &str, now: Duration) -> Option<&mut Entry> {
+        let expired = self.jobs
+            .get(uuid)
+            .is_some_and(|o| o.status != Status::RUNNING && o.expires <= now);
+        if expired {
+            self.jobs.remove(uuid);
+        }
+
+        self.jobs.get_mut(uuid)
+    }
+
+    /// Pop next queued job and mark it as running, counting cancelled jobs popped on the way
+    fn claim(&mut self, now: Duration, cancelled: &mut usize) -> Option<(String, Vec<String>)> {
+        while let Some(uuid) = self.ready.pop_front() {
+            match self.job(&uuid, now) {
+                Some(job) if job.status == Status::QUEUED => {
+                    job.status = Status::RUNNING;
+                    job.attempts += 1;
+                    return Some((uuid, job.args.clone()));
+                }
+                Some(ref job) if job.status == Status::CANCELLED => *cancelled += 1,
+                _ => {}
+            }
+        }
+
+        None
+    }
+}
+
+/// Queue keeping jobs in process memory, for tests of code using `Backend` without Redis
+pub struct MemoryQueue {
+    state: Mutex<State>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for MemoryQueue {
+    fn default() -> MemoryQueue {
+        MemoryQueue::with_clock(Arc::new(SystemClock))
+    }
+}
+
+impl MemoryQueue {
+    /// Init new empty memory queue
+    pub fn new() -> MemoryQueue {
+        MemoryQueue::default()
+    }
+
+    /// Init new empty memory queue reading time from `clock`, e.g. `MockClock`
+    pub fn with_clock(clock: Arc<dyn Clock>) -> MemoryQueue {
+        MemoryQueue {
+            state: Mutex::new(State::default()),
+            clock,
+        }
+    }
+
+    /// Delete all jobs
+    pub fn drop(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        state.jobs.clear();
+        state.ready.clear();
+    }
+
+    /// Number of jobs waiting on the ready list, expired and cancelled ones included until a
+    /// worker reaches them
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().ready.len()
+    }
+
+    /// Whether no jobs are waiting on the ready list
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Claim next queued job, waiting for one at most `wait`, forever if `wait` is 0
+    fn claim(&self, wait: Duration, cancelled: &mut usize) -> Option<(String, Vec<String>)> {
+        let deadline = self.clock.elapsed() + wait;
+        loop {
+            if let Some(o) = self.state.lock().unwrap().claim(self.clock.elapsed(), cancelled) {
+                return Some(o);
+            }
+            if !wait.is_zero() && self.clock.elapsed() >= deadline {
+                return None;
+            }
+            self.clock.sleep(CLAIM_POLL);
+        }
+    }
+}
+
+impl Backend for MemoryQueue {
+    fn enqueue(&self, args: Vec<String>, expire: Duration) -> Result<String, Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+
+        let uuid = Uuid::new_v4().to_string();
+        state.jobs.insert(uuid.clone(),
+                          Entry {
+                              status: Status::QUEUED,
+                              args,
+                              result: None,
+                              attempts: 0,
+                              expires: self.clock.elapsed() + expire,
+                          });
+        state.ready.push_back(uuid.clone());
+
+        Ok(uuid)
+    }
+
+    fn status(&self, uuid: &str) -> Result<Status, Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+
+        match state.job(uuid, self.clock.elapsed()) {
+            Some(o) => Ok(o.status.clone()),
+            None => Err(format!("job {} doesn't exist", uuid).into()),
+        }
+    }
+
+    fn result(&self, uuid: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+
+        match state.job(uuid, self.clock.elapsed()) {
+            Some(o) => Ok(o.result.clone()),
+            None => Err(format!("job {} doesn't exist", uuid).into()),
+        }
+    }
+
+    fn cancel(&self, uuid: &str) -> Result<bool, Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+
+        match state.job(uuid, self.clock.elapsed()) {
+            Some(ref mut o) if o.status == Status::QUEUED => {
+                o.status = Status::CANCELLED;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn work<F>(&self, fun: F, options: &WorkOptions) -> Result<WorkSummary, Box<dyn Error>>
+        where F: Fn(String, Vec<String>) -> Result<String, Box<dyn Error>> + Send + Sync + 'static
+    {
+        check_freq(options.freq)?;
+        let afun = Arc::new(fun);
+        let wait = options.wait;
+        let mut summary = WorkSummary::default();
+        loop {
+            let (uuid, args) = match self.claim(wait, &mut summary.cancelled) {
+                Some(o) => o,
+                None => {
+                    if !options.infinite {
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            let (status, result) = execute(&afun,
+                                           options.executor.as_deref(),
+                                           &*self.clock,
+                                           &uuid,
+                                           None,
+                                           args.clone(),
+                                           options.timeout,
+                                           options.freq,
+                                           &mut || {})
+                .outcome();
+            let requeued = {
+                let mut state = self.state.lock().unwrap();
+                let now = self.clock.elapsed();
+                let expires = now + options.expire;
+                let requeued = match state.job(&uuid, now) {
+                    Some(job) => {
+                        let requeued = match options.lost {
+                            LostPolicy::Requeue { max_attempts } => {
+                                status == Status::LOST && job.attempts < max_attempts
+                            }
+                            LostPolicy::Lost => false,
+                        };
+                        job.status = if requeued { Status::QUEUED } else { status.clone() };
+                        job.result = result;
+                        job.expires = expires;
+                        requeued
+                    }
+                    None => false,
+                };
+                if requeued {
+                    state.ready.push_back(uuid.clone());
+                }
+                requeued
+            };
+
+            summary.processed += 1;
+            if requeued {
+                let exit = stop_after(summary.processed, options.max_jobs, options.infinite);
+                if let Some(exit) = exit {
+                    summary.exit = exit;
+                    break;
+                }
+                continue;
+            }
+            match status {
+                Status::FINISHED => summary.finished += 1,
+                Status::FAILED => summary.failed += 1,
+                Status::LOST => summary.lost += 1,
+                _ => {}
+            }
+            if options.fall && status == Status::LOST {
+                let lost = RjqError::JobLost {
+                    uuid: uuid.clone(),
+                    args,
+                };
+                match options.on_fall {
+                    FallPolicy::Panic => panic!("{}", lost),
+                    FallPolicy::Error => return Err(lost.into()),
+                    FallPolicy::Callback(ref callback) => callback(&uuid),
+                }
+            }
+            let exit = stop_after(summary.processed, options.max_jobs, options.infinite);
+            if let Some(exit) = exit {
+                summary.exit = exit;
+                break;
+            }
+        }
+
+        Ok(summary)
+    }
+}
@@ -0,0 +1,110 @@
+//! Time source.
+//!
+//! Queues read the time and sleep through a `Clock`, set with `QueueOptions::clock` or
+//! `MemoryQueue::with_clock`: job timeouts, worker polls and backoff, delayed and recurring job
+//! schedules and job timestamps. `SystemClock` is the default, `MockClock` only moves when told
+//! to, so that tests can check timeouts and schedules without waiting for them.
+//!
+//! Timeouts and waits are measured with `elapsed`, which `SystemClock` counts on a monotonic
+//! clock, so that they hold when the wall clock is set back or forward. Timestamps and schedules
+//! shared through Redis take `now`, the wall clock time.
+//!
+//! ```rust,ignore
+//! let clock = Arc::new(MockClock::new(Duration::from_secs(1_000_000)));
+//! let queue = Queue::with_options("redis://localhost/",
+//!                                 "rjq",
+//!                                 QueueOptions { clock: clock.clone(), ..Default::default() });
+//! let uuid = queue.enqueue_delayed(vec![], Duration::from_secs(30), Duration::from_secs(60))?;
+//! clock.advance(Duration::from_secs(60));
+//! assert!(queue.promote()? == vec![uuid]);
+//! ```
+//!
+//! Expiration of keys is counted by Redis on its own clock, as are connection retries and callback
+//! deliveries, which always take real time.
+
+use std::fmt;
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Source of current time and sleep
+pub trait Clock: Send + Sync {
+    /// Current time since unix epoch, for timestamps and schedules
+    fn now(&self) -> Duration;
+
+    /// Time since an origin fixed for the process, for timeouts and waits, `now` by default. It
+    /// must never go back, unlike wall clock time which may be set back.
+    fn elapsed(&self) -> Duration {
+        self.now()
+    }
+
+    /// Block the calling thread for `duration`
+    fn sleep(&self, duration: Duration);
+}
+
+impl fmt::Debug for dyn Clock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Clock")
+    }
+}
+
+/// Wall clock time, monotonic elapsed time and real sleep
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+/// Origin of `SystemClock::elapsed`, taken on its first call
+static ORIGIN: OnceLock<Instant> = OnceLock::new();
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+    }
+
+    fn elapsed(&self) -> Duration {
+        ORIGIN.get_or_init(Instant::now).elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        sleep(duration)
+    }
+}
+
+/// Clock which only moves by `advance`, sleeping threads wake up once it has been advanced past
+/// the end of their sleep
+#[derive(Debug, Default)]
+pub struct MockClock {
+    now: Mutex<Duration>,
+    advanced: Condvar,
+}
+
+impl MockClock {
+    /// Init new mock clock
+    ///
+    /// `now` - initial time since unix epoch
+    pub fn new(now: Duration) -> MockClock {
+        MockClock {
+            now: Mutex::new(now),
+            advanced: Condvar::new(),
+        }
+    }
+
+    /// Move the clock forward by `duration`, waking up threads whose sleep is over
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+        self.advanced.notify_all();
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        let until = *now + duration;
+        while *now < until {
+            now = self.advanced.wait(now).unwrap();
+        }
+    }
+}
@@ -0,0 +1,541 @@
+//! Connections to Redis.
+//!
+//! Every queue call opens its own connection, so a burst of concurrent calls could open enough
+//! connections to hit Redis `maxclients`. The number of connections the crate keeps open at the
+//! same time is capped process wide: once the cap is reached, idle connections kept by queues are
+//! closed and calls wait for a connection to be closed before opening a new one, at most
+//! `connection_wait`, failing with `RjqError::ConnectionsExhausted` then. A call made by a thread
+//! which already holds a connection, e.g. a worker compacting its queue, gets its connection
+//! beyond the cap instead, so that it can't wait for itself.
+//!
+//! A queue can keep its connections open between calls instead, see `ConnectionStrategy`.
+
+use std::cell::Cell;
+use std::error::Error;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread::{self, sleep, ThreadId};
+use std::time::{Duration, Instant};
+use redis::{self, Client, Commands, Connection, ConnectionLike, PubSub, RedisResult, Value};
+
+use super::RjqError;
+
+/// Default maximum number of connections open at the same time
+pub const DEFAULT_MAX_CONNECTIONS: usize = 64;
+
+/// Default longest wait for a connection to be closed once the cap is reached
+pub const DEFAULT_CONNECTION_WAIT: Duration = Duration::from_secs(30);
+
+static MAX_CONNECTIONS: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_CONNECTIONS);
+static CONNECTION_WAIT_MS: AtomicU64 =
+    AtomicU64::new(DEFAULT_CONNECTION_WAIT.as_millis() as u64);
+static SLOTS: Mutex<Slots> = Mutex::new(Slots {
+    open: 0,
+    holders: Vec::new(),
+    waiting: 0,
+});
+static CLOSED: Condvar = Condvar::new();
+/// Pools of every queue, whose idle connections are closed to make room under the cap
+static POOLS: Mutex<Vec<Weak<Pool>>> = Mutex::new(Vec::new());
+/// Counter of tokens echoed by idle connections before reuse
+static ECHOED: AtomicUsize = AtomicUsize::new(0);
+/// Time a kept connection may stay idle before it is checked again on reuse
+const IDLE_CHECK: Duration = Duration::from_secs(1);
+
+/// Set maximum number of connections the crate keeps open at the same time, 64 by default
+///
+/// `max` - connections cap, `usize::MAX` removes it. Connections already open are kept, a lower
+/// cap applies to connections opened afterwards.
+pub fn set_max_connections(max: usize) {
+    MAX_CONNECTIONS.store(max.max(1), Ordering::SeqCst);
+    CLOSED.notify_all();
+}
+
+/// Get maximum number of connections the crate keeps open at the same time
+pub fn max_connections() -> usize {
+    MAX_CONNECTIONS.load(Ordering::SeqCst)
+}
+
+/// Set longest wait for a connection to be closed once the cap is reached, 30 seconds by default
+///
+/// `wait` - wait after which a call fails with `RjqError::ConnectionsExhausted`
+pub fn set_connection_wait(wait: Duration) {
+    CONNECTION_WAIT_MS.store(wait.as_millis() as u64, Ordering::SeqCst);
+    CLOSED.notify_all();
+}
+
+/// Get longest wait for a connection to be closed once the cap is reached
+pub fn connection_wait() -> Duration {
+    Duration::from_millis(CONNECTION_WAIT_MS.load(Ordering::SeqCst))
+}
+
+/// Get number of connections the crate has open right now
+pub fn open_connections() -> usize {
+    slots().open
+}
+
+/// Connections counted against the cap
+struct Slots {
+    open: usize,
+    /// Thread of every connection in use, once per connection, idle kept connections have none
+    holders: Vec<ThreadId>,
+    /// Number of calls waiting for a connection to be closed
+    waiting: usize,
+}
+
+fn slots() -> MutexGuard<'static, Slots> {
+    SLOTS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Place under the connections cap, released on drop
+struct Slot {
+    /// Thread using the connection, `None` while it is idle in a pool
+    holder: Option<ThreadId>,
+}
+
+impl Slot {
+    /// Take a place under the cap as told by the module docs
+    fn acquire() -> Result<Slot, Box<dyn Error>> {
+        let thread = thread::current().id();
+        let deadline = Instant::now() + connection_wait();
+        let mut closed_idle = false;
+        let mut slots = slots();
+        while slots.open >= max_connections() && !slots.holders.contains(&thread) {
+            if !closed_idle {
+                drop(slots);
+                for pool in POOLS.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+                    if let Some(pool) = pool.upgrade() {
+                        pool.close_idle();
+                    }
+                }
+                closed_idle = true;
+                slots = self::slots();
+                continue;
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(RjqError::ConnectionsExhausted {
+                        max: max_connections(),
+                        wait: connection_wait(),
+                    }
+                    .into());
+            }
+            slots.waiting += 1;
+            slots = CLOSED.wait_timeout(slots, left).unwrap_or_else(|e| e.into_inner()).0;
+            slots.waiting -= 1;
+        }
+        slots.open += 1;
+        slots.holders.push(thread);
+
+        Ok(Slot { holder: Some(thread) })
+    }
+
+    /// Count the connection as used by the current thread
+    fn adopt(&mut self) {
+        self.disown();
+        let thread = thread::current().id();
+        slots().holders.push(thread);
+        self.holder = Some(thread);
+    }
+
+    /// Count the connection as used by no thread
+    fn disown(&mut self) {
+        if let Some(thread) = self.holder.take() {
+            let mut slots = slots();
+            if let Some(i) = slots.holders.iter().position(|&o| o == thread) {
+                slots.holders.swap_remove(i);
+            }
+        }
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.disown();
+        slots().open -= 1;
+        CLOSED.notify_one();
+    }
+}
+
+/// How queue methods get their connection, set with `QueueOptions::connections`
+///
+/// Kept connections count against the connections cap while idle, and are closed to make room once
+/// it is reached. `work_with` and `reserve_for` always open a connection of their own, since they
+/// hold it for as long as they run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionStrategy {
+    /// Open a connection for every call and close it before returning, the default
+    PerCall,
+    /// Keep one connection, calls of the queue and its clones take turns on it
+    Shared,
+    /// Keep up to `size` connections, calls take an idle one or wait for one to be returned
+    Pooled(usize),
+}
+
+/// Connections kept by a queue and its clones
+pub(crate) struct Pool {
+    url: String,
+    size: usize,
+    /// Read and write timeout of the connections, see `connect_with`
+    timeout: Option<Duration>,
+    /// Name of the connections, see `connect_with`
+    name: Option<String>,
+    /// Idle connections with the time they were returned, and number of connections open
+    state: Mutex<(Vec<(Conn, Instant)>, usize)>,
+    returned: Condvar,
+}
+
+impl Pool {
+    /// Init pool of up to `size` connections to `url`, opened as they are needed with `timeout`
+    /// and `name`
+    pub(crate) fn new(url: &str,
+                      size: usize,
+                      timeout: Option<Duration>,
+                      name: Option<&str>)
+                      -> Arc<Pool> {
+        let pool = Arc::new(Pool {
+            url: url.to_string(),
+            size: size.max(1),
+            timeout,
+            name: name.map(str::to_string),
+            state: Mutex::new((Vec::new(), 0)),
+            returned: Condvar::new(),
+        });
+        let mut pools = POOLS.lock().unwrap_or_else(|e| e.into_inner());
+        pools.retain(|o| o.strong_count() > 0);
+        pools.push(Arc::downgrade(&pool));
+
+        pool
+    }
+
+    /// Most connections kept open at once
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Take an idle connection, open a new one if there are fewer than `size`, or wait for one
+    /// to be returned
+    ///
+    /// A connection idle for longer than `IDLE_CHECK`, or whose last call had a command fail, is
+    /// checked with `ECHO` first and closed if it doesn't echo back. Waiting for a connection to be
+    /// returned fails with `RjqError::ConnectionsExhausted` after `connection_wait`.
+    pub(crate) fn get(pool: &Arc<Pool>) -> Result<Lease, Box<dyn Error>> {
+        let deadline = Instant::now() + connection_wait();
+        let mut state = pool.state.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some((mut conn, returned)) = state.0.pop() {
+                drop(state);
+                // The client closes the connection on any error reply, idle connections may be
+                // closed by the server. A command which timed out may still get its reply, which
+                // comes before the echo
+                let fresh = !conn.failed.get() && returned.elapsed() < IDLE_CHECK;
+                let token = ECHOED.fetch_add(1, Ordering::SeqCst).to_string();
+                if fresh || redis::cmd("ECHO").arg(&token).query(&conn.conn).ok() == Some(token) {
+                    conn.failed.set(false);
+                    conn.slot.adopt();
+                    return Ok(Lease {
+                        conn: Some(conn),
+                        pool: Some(pool.clone()),
+                    });
+                }
+                state = pool.state.lock().unwrap_or_else(|e| e.into_inner());
+                state.1 -= 1;
+                continue;
+            }
+            if state.1 < pool.size {
+                state.1 += 1;
+                drop(state);
+                return match connect_with(&pool.url, pool.timeout, pool.name.as_deref()) {
+                    Ok(conn) => {
+                        Ok(Lease {
+                            conn: Some(conn),
+                            pool: Some(pool.clone()),
+                        })
+                    }
+                    Err(e) => {
+                        pool.release(None);
+                        Err(e)
+                    }
+                };
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(RjqError::ConnectionsExhausted {
+                        max: pool.size,
+                        wait: connection_wait(),
+                    }
+                    .into());
+            }
+            state = pool.returned.wait_timeout(state, left).unwrap_or_else(|e| e.into_inner()).0;
+        }
+    }
+
+    /// Close idle connections, so that their places under the cap can be taken
+    fn close_idle(&self) {
+        let idle = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            let idle = mem::take(&mut state.0);
+            state.1 -= idle.len();
+            idle
+        };
+        if !idle.is_empty() {
+            self.returned.notify_all();
+        }
+    }
+
+    /// Put connection back, or only count it as closed if `None`
+    ///
+    /// The connection is closed instead while calls wait for room under the cap.
+    fn release(&self, conn: Option<Conn>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match conn {
+            Some(mut o) if slots().waiting == 0 => {
+                o.slot.disown();
+                state.0.push((o, Instant::now()));
+            }
+            _ => state.1 -= 1,
+        }
+        self.returned.notify_one();
+    }
+}
+
+/// Connection of a single queue call, put back to its pool when dropped, if it has one
+pub(crate) struct Lease {
+    conn: Option<Conn>,
+    pool: Option<Arc<Pool>>,
+}
+
+impl Lease {
+    /// Lease of a connection closed when dropped
+    pub(crate) fn own(conn: Conn) -> Lease {
+        Lease {
+            conn: Some(conn),
+            pool: None,
+        }
+    }
+
+    /// Record a failed command sent on the connection itself rather than on the lease, so that
+    /// the connection is checked before reuse
+    pub(crate) fn fail(&self) {
+        self.get().failed.set(true);
+    }
+
+    fn get(&self) -> &Conn {
+        match self.conn {
+            Some(ref o) => o,
+            None => unreachable!("connection is only taken on drop"),
+        }
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        if let Some(ref pool) = self.pool {
+            pool.release(self.conn.take());
+        }
+    }
+}
+
+impl Deref for Lease {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.get().conn
+    }
+}
+
+impl DerefMut for Lease {
+    fn deref_mut(&mut self) -> &mut Connection {
+        match self.conn {
+            Some(ref mut o) => &mut o.conn,
+            None => unreachable!("connection is only taken on drop"),
+        }
+    }
+}
+
+impl ConnectionLike for Lease {
+    fn req_packed_command(&self, cmd: &[u8]) -> RedisResult<Value> {
+        self.get().req_packed_command(cmd)
+    }
+
+    fn req_packed_commands(&self,
+                           cmd: &[u8],
+                           offset: usize,
+                           count: usize)
+                           -> RedisResult<Vec<Value>> {
+        self.get().req_packed_commands(cmd, offset, count)
+    }
+
+    fn get_db(&self) -> i64 {
+        self.get().get_db()
+    }
+}
+
+impl Commands for Lease {}
+
+/// Connection counted against the cap until it is dropped
+pub(crate) struct Conn {
+    conn: Connection,
+    /// Whether a command sent through `ConnectionLike` has failed since the connection was last
+    /// checked, see `Pool::get`
+    failed: Cell<bool>,
+    slot: Slot,
+}
+
+/// Open connection to `url`, waiting while the cap is reached, see the module docs
+pub(crate) fn connect(url: &str) -> Result<Conn, Box<dyn Error>> {
+    let slot = Slot::acquire()?;
+    let client = Client::open(url)?;
+    let conn = client.get_connection()?;
+
+    Ok(Conn {
+        conn,
+        failed: Cell::new(false),
+        slot,
+    })
+}
+
+/// Open connection to `url` as `connect`, whose commands fail once they have waited `timeout`
+/// to be written or for their reply, none if `None`, and which is given `name`, see `set_name`
+pub(crate) fn connect_with(url: &str,
+                           timeout: Option<Duration>,
+                           name: Option<&str>)
+                           -> Result<Conn, Box<dyn Error>> {
+    let conn = connect(url)?;
+    // Zero timeouts are refused by the socket
+    let timeout = timeout.filter(|o| !o.is_zero());
+    conn.conn.set_read_timeout(timeout)?;
+    conn.conn.set_write_timeout(timeout)?;
+    if let Some(name) = name {
+        set_name(&conn, name)?;
+    }
+
+    Ok(conn)
+}
+
+/// Name connection with `CLIENT SETNAME`, so that `CLIENT LIST` tells which connection it is
+///
+/// Redis refuses names with spaces, newlines or other characters outside of printable ASCII, they
+/// are replaced with `_`. An empty name leaves the connection unnamed.
+pub(crate) fn set_name(conn: &Connection, name: &str) -> RedisResult<()> {
+    let name: String = name.chars()
+        .map(|o| if ('!'..='~').contains(&o) { o } else { '_' })
+        .collect();
+
+    redis::cmd("CLIENT").arg("SETNAME").arg(name).query(conn)
+}
+
+/// Subscription to a channel, counted against the cap until it is dropped
+pub(crate) struct Subscription {
+    pubsub: PubSub,
+    slot: Slot,
+}
+
+impl Subscription {
+    /// Count the subscription as held by the current thread, once it has been moved to the thread
+    /// waiting on it
+    pub(crate) fn adopt(&mut self) {
+        self.slot.adopt();
+    }
+
+    /// Wait for a message at most `timeout`
+    ///
+    /// Returns whether a message has come
+    pub(crate) fn wait(&mut self, timeout: Duration) -> Result<bool, Box<dyn Error>> {
+        self.pubsub.set_read_timeout(Some(timeout))?;
+        match self.pubsub.get_message() {
+            Ok(_) => Ok(true),
+            Err(ref e) if e.is_timeout() => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Subscribe to `channels` at `url`, waiting while the cap is reached, see the module docs
+pub(crate) fn subscribe(url: &str, channels: &[String]) -> Result<Subscription, Box<dyn Error>> {
+    let slot = Slot::acquire()?;
+    let client = Client::open(url)?;
+    let mut pubsub = client.get_pubsub()?;
+    for channel in channels {
+        pubsub.subscribe(channel)?;
+    }
+
+    Ok(Subscription { pubsub, slot })
+}
+
+/// Retry of a failed connection, sleeping from `initial` growing by `multiplier` up to `max`
+/// between `attempts`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Retry {
+    /// Maximum number of attempts, including the first one
+    pub attempts: usize,
+    /// First sleep
+    pub initial: Duration,
+    /// Sleep cap
+    pub max: Duration,
+    /// Sleep growth factor
+    pub multiplier: u32,
+}
+
+/// Open connection to `url` as `connect` does, trying again as told by `retry`
+///
+/// Returns the last error once all attempts have failed
+pub(crate) fn connect_retry(url: &str, retry: Option<&Retry>) -> Result<Conn, Box<dyn Error>> {
+    let retry = match retry {
+        Some(o) => o,
+        None => return connect(url),
+    };
+
+    let mut delay = retry.initial;
+    let mut attempt = 1;
+    loop {
+        match connect(url) {
+            Ok(o) => return Ok(o),
+            Err(e) if attempt >= retry.attempts => return Err(e),
+            Err(e) => warn!("connection failed, retrying in {:?}: {}", delay, e),
+        }
+        sleep(delay);
+        delay = (delay * retry.multiplier).min(retry.max);
+        attempt += 1;
+    }
+}
+
+impl Deref for Conn {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.conn
+    }
+}
+
+impl ConnectionLike for Conn {
+    fn req_packed_command(&self, cmd: &[u8]) -> RedisResult<Value> {
+        let reply = self.conn.req_packed_command(cmd);
+        if reply.is_err() {
+            self.failed.set(true);
+        }
+
+        reply
+    }
+
+    fn req_packed_commands(&self,
+                           cmd: &[u8],
+                           offset: usize,
+                           count: usize)
+                           -> RedisResult<Vec<Value>> {
+        let replies = self.conn.req_packed_commands(cmd, offset, count);
+        if replies.is_err() {
+            self.failed.set(true);
+        }
+
+        replies
+    }
+
+    fn get_db(&self) -> i64 {
+        self.conn.get_db()
+    }
+}
+
+impl Commands for Conn {}
@@ -0,0 +1,121 @@
+//! Crate errors.
+//!
+//! Queue methods return `Box<dyn Error>`, errors raised by rjq itself rather than by Redis are
+//! `RjqError` and can be told apart with `downcast_ref::<RjqError>()`.
+
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+use serde_json;
+
+/// Error raised by rjq
+#[derive(Debug)]
+pub enum RjqError {
+    /// Job or its payload couldn't be serialized, nothing has been written to Redis
+    Serialize(serde_json::Error),
+    /// Fewer replicas than `QueueOptions::replicas` acknowledged the job in time, the job has
+    /// been written to the primary
+    Replication {
+        /// Number of replicas which acknowledged the job
+        acked: usize,
+        /// Number of replicas required
+        replicas: usize,
+    },
+    /// Redis command isn't available on the server version, nothing has been sent
+    Unsupported {
+        /// Command required by the call
+        command: &'static str,
+        /// Server version, see `Queue::server_version`
+        version: (u32, u32, u32),
+    },
+    /// Queue name can't be told apart from the keys of another queue, see `Queue::try_new`
+    InvalidName(String),
+    /// Job arguments were rejected by `QueueOptions::validator`, nothing has been written to Redis
+    Invalid(String),
+    /// Job arguments are larger than `QueueOptions::max_payload`, nothing has been sent to Redis
+    PayloadTooLarge {
+        /// Total size of the job arguments in bytes
+        size: usize,
+        /// Largest size allowed
+        limit: usize,
+    },
+    /// Queue has had no room for the job in time, see `Queue::enqueue_blocking`, nothing has been
+    /// written to Redis
+    QueueFull {
+        /// Number of jobs on the ready lists when the wait ended
+        len: usize,
+        /// Length below which the job would have been enqueued
+        max_len: usize,
+    },
+    /// Job arguments couldn't be decoded as the payload the worker function expects, the job has
+    /// failed with this message as its result
+    ArgsDecode(String),
+    /// Returned by a worker function which can't run its job yet, e.g. while a dependency isn't
+    /// ready: the job is scheduled to be run again after the delay instead of failing, and the
+    /// attempt isn't counted, see `Queue::work_with`. Jobs of a kind, and jobs of `StreamQueue` and
+    /// `MemoryQueue` workers, fail instead
+    Defer(Duration),
+    /// No connection has been closed in time to make room under the connections cap, or no
+    /// pooled connection has been returned, see `connection::set_connection_wait`
+    ConnectionsExhausted {
+        /// Connections cap, or size of the pool
+        max: usize,
+        /// Time waited for a connection
+        wait: Duration,
+    },
+    /// Job hasn't been completed in time and the worker fell, see `FallPolicy`
+    JobLost {
+        /// Lost job identifier
+        uuid: String,
+        /// Lost job arguments
+        args: Vec<String>,
+    },
+}
+
+impl fmt::Display for RjqError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RjqError::Serialize(ref e) => write!(f, "job serialization failed: {}", e),
+            RjqError::Replication { acked, replicas } => {
+                write!(f, "job replicated to {} of {} replicas", acked, replicas)
+            }
+            RjqError::Unsupported { command, version: (major, minor, patch) } => {
+                write!(f, "{} isn't supported by Redis {}.{}.{}", command, major, minor, patch)
+            }
+            RjqError::InvalidName(ref e) => write!(f, "invalid queue name: {}", e),
+            RjqError::Invalid(ref e) => write!(f, "invalid job arguments: {}", e),
+            RjqError::PayloadTooLarge { size, limit } => {
+                write!(f, "job arguments of {} bytes exceed the limit of {} bytes", size, limit)
+            }
+            RjqError::QueueFull { len, max_len } => {
+                write!(f, "queue has {} ready jobs, not below the limit of {}", len, max_len)
+            }
+            RjqError::ArgsDecode(ref e) => write!(f, "args decode error: {}", e),
+            RjqError::Defer(delay) => write!(f, "job deferred for {:?}", delay),
+            RjqError::ConnectionsExhausted { max, wait } => {
+                write!(f, "all {} connections stayed in use for {:?}", max, wait)
+            }
+            RjqError::JobLost { ref uuid, ref args } => {
+                write!(f, "job {} with arguments {:?} has been lost", uuid, args)
+            }
+        }
+    }
+}
+
+impl Error for RjqError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            RjqError::Serialize(ref e) => Some(e),
+            RjqError::Replication { .. } |
+            RjqError::Unsupported { .. } |
+            RjqError::InvalidName(_) |
+            RjqError::Invalid(_) |
+            RjqError::PayloadTooLarge { .. } |
+            RjqError::QueueFull { .. } |
+            RjqError::ArgsDecode(_) |
+            RjqError::Defer(_) |
+            RjqError::ConnectionsExhausted { .. } |
+            RjqError::JobLost { .. } => None,
+        }
+    }
+}
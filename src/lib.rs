@@ -5,7 +5,7 @@
 //! ```rust,ignore
 //! extern crate rjq;
 //!
-//! use std::time::Duration;
+//! use std::time::{Duration, SystemTime, UNIX_EPOCH};
 //! use std::thread::sleep;
 //! use rjq::{Queue, Status};
 //!
@@ -14,7 +14,7 @@
 //!
 //! for _ in 0..10 {
 //!     sleep(Duration::from_millis(100));
-//!     uuids.push(queue.enqueue(vec![], 30)?);
+//!     uuids.push(queue.enqueue_for(vec![], Duration::from_secs(30))?);
 //! }
 //!
 //! sleep(Duration::from_millis(10000));
@@ -31,19 +31,24 @@
 //! ```rust,ignore
 //! extern crate rjq;
 //!
-//! use std::time::Duration;
+//! use std::time::{Duration, SystemTime, UNIX_EPOCH};
 //! use std::thread::sleep;
 //! use std::error::Error;
-//! use rjq::Queue;
+//! use rjq::{Queue, WorkOptions};
 //!
-//! fn process(uuid: String, _: Vec<String>) -> Result<String, Box<Error>> {
+//! fn process(uuid: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
 //!     sleep(Duration::from_millis(1000));
 //!     println!("{}", uuid);
 //!     Ok(format!("hi from {}", uuid))
 //! }
 //!
 //! let queue = Queue::new("redis://localhost/", "rjq");
-//! queue.work(process, None, Some(60), None, Some(30), Some(false), None)?;
+//! let options = WorkOptions {
+//!     timeout: Duration::from_secs(60),
+//!     fall: false,
+//!     ..Default::default()
+//! };
+//! queue.work_with(process, &options)?;
 //! ```
 
 #![deny(missing_docs)]
@@ -54,19 +59,49 @@ extern crate serde;
 extern crate serde_json;
 extern crate redis;
 extern crate uuid;
+extern crate sha1;
+extern crate rand;
+#[macro_use]
+extern crate log;
+
+pub mod backend;
+pub mod clock;
+pub mod connection;
+pub mod error;
+pub mod store;
+pub mod stream;
+#[cfg(feature = "http")]
+mod webhook;
 
 use std::error::Error;
+use std::fmt;
 use std::thread;
-use std::sync::mpsc::channel;
-use std::time::Duration;
-use std::thread::sleep;
+use std::sync::mpsc::{channel, sync_channel, Receiver};
+use std::time::{Duration, Instant};
 use std::marker::{Send, Sync};
-use std::sync::Arc;
-use redis::{Commands, Client};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use redis::{Commands, PipelineCommands, Connection, Script};
 use uuid::Uuid;
+use sha1::Sha1;
+use connection::{connect, connect_retry, connect_with, set_name, subscribe, Lease, Pool};
+use serde::{Serialize, Deserialize, Deserializer};
+use serde::de::{self, DeserializeOwned};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::iter;
+use std::mem;
+use std::slice;
+
+pub use connection::{set_max_connections, max_connections, open_connections, set_connection_wait,
+                     connection_wait, ConnectionStrategy, Retry};
+pub use backend::{Backend, MemoryQueue};
+pub use clock::{Clock, MockClock, SystemClock};
+pub use error::RjqError;
+pub use store::{RedisStore, Store};
+pub use stream::StreamQueue;
 
 /// Job status
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Status {
     /// Job is queued
     QUEUED,
@@ -78,14 +113,746 @@ pub enum Status {
     FINISHED,
     /// Job failed
     FAILED,
+    /// Job was cancelled before a worker started it
+    CANCELLED,
+}
+
+impl Status {
+    /// Check if the job is done with and its status won't change anymore: `LOST`, `FINISHED`,
+    /// `FAILED` or `CANCELLED`
+    pub fn is_terminal(&self) -> bool {
+        !self.is_pending()
+    }
+
+    /// Check if the job is still to be completed: `QUEUED` or `RUNNING`
+    pub fn is_pending(&self) -> bool {
+        match *self {
+            Status::QUEUED | Status::RUNNING => true,
+            Status::LOST | Status::FINISHED | Status::FAILED | Status::CANCELLED => false,
+        }
+    }
+}
+
+/// Reason a worker returned, see `WorkSummary::exit`
+///
+/// Errors, including a lost job with `FallPolicy::Error`, are returned as such rather than with a
+/// summary, see `RjqError::JobLost`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum WorkerExit {
+    /// One iteration of a worker which isn't `infinite` is done
+    #[default]
+    Once,
+    /// `max_jobs` jobs were processed
+    MaxJobs,
+    /// Queue was empty or paused while draining, see `Queue::drain_until` and
+    /// `WorkerCommand::Drain`
+    Drained,
+    /// Deadline of `Queue::drain_until` has passed
+    Deadline,
+    /// `WorkerCommand::Stop` was taken
+    Stopped,
+}
+
+/// Totals of a `work_with` run
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct WorkSummary {
+    /// Jobs started by the worker, including attempts put back to the queue by
+    /// `LostPolicy::Requeue`
+    pub processed: usize,
+    /// Jobs finished successfully
+    pub finished: usize,
+    /// Jobs failed
+    pub failed: usize,
+    /// Jobs marked as lost
+    pub lost: usize,
+    /// Cancelled jobs skipped by the worker
+    pub cancelled: usize,
+    /// Jobs deferred by the worker function, see `RjqError::Defer`, counted as processed as well
+    pub deferred: usize,
+    /// Expired jobs found on the ready lists, only counted unless `ExpiredPolicy::Skip`
+    pub expired: usize,
+    /// Reason the worker returned
+    pub exit: WorkerExit,
+}
+
+/// Totals of a `drain_until` run
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DrainSummary {
+    /// Totals of the jobs processed
+    pub work: WorkSummary,
+    /// Job identifiers left on the ready lists the worker pops from, expired ones included until
+    /// workers or `compact` prune them
+    pub remaining: usize,
+    /// Draining stopped because the deadline passed rather than because the queue was empty
+    pub timed_out: bool,
+}
+
+/// Job result along with whether the job is done, see `Queue::result_state`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResultState {
+    /// Job is queued or running
+    Pending,
+    /// Job finished successfully, with its result
+    Done(Option<String>),
+    /// Job failed, with the error stored as its result, if any, e.g. of arguments which couldn't
+    /// be decoded or of `ReservedJob::fail`
+    Failed(Option<String>),
+    /// Job hasn't been completed in time
+    Lost,
+    /// Job was cancelled before a worker started it
+    Cancelled,
+    /// Job has expired or never existed
+    NotFound,
+}
+
+/// Job details, see `Queue::job_info`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JobInfo {
+    /// Unique job identifier
+    pub uuid: String,
+    /// Job status
+    pub status: Status,
+    /// Job arguments as `Queue::args` returns them
+    pub args: Vec<String>,
+    /// Job result
+    pub result: Option<String>,
+    /// Job label, see `Queue::enqueue_labeled`
+    pub label: Option<String>,
+    /// Job kind, see `Queue::enqueue_kind`
+    pub kind: Option<String>,
+    /// Job tags, see `Queue::enqueue_tagged`
+    pub tags: Vec<String>,
+    /// Worker which last claimed the job, its `WorkOptions::worker_id` or an identifier of the run
+    pub owner: Option<String>,
+    /// Time the job was enqueued since unix epoch on the queue clock, `None` for jobs enqueued by
+    /// older versions
+    pub enqueued_at: Option<Duration>,
+    /// Time the job was last claimed by a worker since unix epoch, `None` until it is
+    pub started_at: Option<Duration>,
+    /// Time left before the job is removed, `None` for a job which doesn't expire, see
+    /// `QueueOptions::pin`
+    pub ttl: Option<Duration>,
+    /// Number of times a worker has started the job, more than 1 if it was retried
+    pub attempts: usize,
+    /// Maximum number of attempts allowed by the worker which last processed the job, 1 unless
+    /// its `lost` policy is `LostPolicy::Requeue`, `None` until a worker has processed it
+    pub max_attempts: Option<usize>,
+    /// Bytes the job takes in Redis values: its JSON, its arguments list if stored apart and its
+    /// raw result, without Redis overhead per key, see `Queue::memory_usage` for that
+    pub stored_bytes: usize,
+    /// Total size of the job arguments in bytes as enqueued, the size `QueueOptions::max_payload`
+    /// is checked against, whatever way they are stored
+    pub payload_bytes: usize,
+}
+
+/// Job details passed to the worker function of `Queue::work_ctx`
+#[derive(Debug, Clone)]
+pub struct JobContext {
+    /// Unique job identifier
+    pub uuid: String,
+    /// Number of this attempt, 1 on the first run, more if the job was requeued or retried
+    pub attempt: usize,
+    /// Time the job was enqueued since unix epoch on the queue clock, `None` for jobs enqueued by
+    /// older versions
+    pub enqueued_at: Option<Duration>,
+    /// Job tags, see `Queue::enqueue_tagged`
+    pub tags: Vec<String>,
+    /// Set once the worker has given up on the job
+    cancelled: Arc<AtomicBool>,
+}
+
+impl JobContext {
+    /// Whether the worker has given up on the job: it wasn't completed within the worker timeout,
+    /// it is lost or requeued already and its result will be discarded, so the worker function
+    /// had better stop early
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Redis server version as major, minor and patch numbers, see `Queue::server_version`
+pub type Version = (u32, u32, u32);
+
+/// Terminal status and result of a completed job, see `Queue::enqueue_await`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    /// Terminal job status
+    pub status: Status,
+    /// Job result, if any
+    pub result: Option<String>,
+    /// Number of times the job has been claimed by workers, the completing attempt included
+    pub attempts: usize,
+}
+
+/// Progress of a job watched with `Queue::enqueue_watch`
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobEvent {
+    /// A worker has started the job, once per attempt
+    Started {
+        /// Time since unix epoch the job was enqueued at, told by the producer's clock
+        enqueued_at: Option<Duration>,
+        /// Time since unix epoch the worker started the job at, told by the worker's clock
+        started_at: Duration,
+    },
+    /// A worker has completed the job, the last event
+    Completed(Outcome),
+}
+
+/// Run duration percentiles of recently completed jobs, see `Queue::latency_percentiles`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LatencyStats {
+    /// Number of durations the percentiles are taken from, all percentiles are zero if none
+    pub count: usize,
+    /// Median run duration
+    pub p50: Duration,
+    /// 95th percentile of run duration
+    pub p95: Duration,
+    /// 99th percentile of run duration
+    pub p99: Duration,
+}
+
+/// Number of jobs of the queue by status, see `Queue::stats` and `Queue::stats_estimate`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct QueueStats {
+    /// Jobs waiting for a worker
+    pub queued: usize,
+    /// Jobs claimed by workers
+    pub running: usize,
+    /// Jobs which have finished successfully
+    pub finished: usize,
+    /// Jobs which have failed
+    pub failed: usize,
+    /// Jobs which haven't been completed in time
+    pub lost: usize,
+    /// Jobs cancelled before a worker started them
+    pub cancelled: usize,
+    /// Jobs on the dead letter list, which workers couldn't parse
+    pub dead: usize,
+}
+
+/// Worker behavior for a job which hasn't been completed in time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LostPolicy {
+    /// Mark job as lost
+    Lost,
+    /// Enqueue job again for another attempt, mark it as lost and move it to the dead letter list
+    /// once it has been attempted `max_attempts` times. Attempts are counted on the stored job by
+    /// the script claiming it, so they add up across workers and worker restarts, and a job
+    /// whose attempts other workers have used up is moved there without being run.
+    Requeue {
+        /// Maximum number of attempts, including the first one
+        max_attempts: usize,
+    },
+}
+
+/// Worker behavior for a job which has expired while its identifier was still on a ready list
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExpiredPolicy {
+    /// Drop the identifier and take the next job
+    Skip,
+    /// Drop the identifier and log a warning naming the job, e.g. to find out why jobs expire
+    /// before workers take them
+    Report,
+    /// Drop the identifier and prune the identifiers of all other expired jobs with
+    /// `Queue::compact`, so that list lengths count live jobs only
+    Strict,
+}
+
+/// Worker behavior once a job has been lost and `fall` is set
+#[derive(Clone)]
+pub enum FallPolicy {
+    /// Panic, terminating the worker thread
+    Panic,
+    /// Stop working and return `RjqError::JobLost` from `work_with`, so that a supervisor can
+    /// restart the worker
+    Error,
+    /// Call the function with the lost job identifier and go on working
+    Callback(Arc<dyn Fn(&str) + Send + Sync>),
+}
+
+impl fmt::Debug for FallPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FallPolicy::Panic => write!(f, "Panic"),
+            FallPolicy::Error => write!(f, "Error"),
+            FallPolicy::Callback(_) => write!(f, "Callback(..)"),
+        }
+    }
+}
+
+/// Command for a worker run by `Queue::work_controlled`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WorkerCommand {
+    /// Stop taking jobs until `Resume`, unlike `Queue::pause` only this worker pauses. Jobs it has
+    /// prefetched are put back to the ready lists, `Stop` and `Drain` are taken while paused.
+    Pause,
+    /// Take jobs again after `Pause`
+    Resume,
+    /// Go on until a poll finds no job, then stop, as `Queue::drain_until` without a deadline
+    Drain,
+    /// Stop before taking another job, jobs prefetched but not started are put back
+    Stop,
+}
+
+/// Runs job bodies for the worker, e.g. on a rayon or custom thread pool, instead of a new thread
+/// per job
+///
+/// Closures taking the job body implement it, so
+/// `Arc::new(|job: Box<dyn FnOnce() + Send>| rayon::spawn(job))` is an executor
+pub trait Executor: Send + Sync {
+    /// Run `job` in the background, the worker waits for its result without blocking the caller
+    fn spawn(&self, job: Box<dyn FnOnce() + Send>);
+}
+
+impl<F: Fn(Box<dyn FnOnce() + Send>) + Send + Sync> Executor for F {
+    fn spawn(&self, job: Box<dyn FnOnce() + Send>) {
+        self(job)
+    }
+}
+
+impl fmt::Debug for dyn Executor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Executor")
+    }
+}
+
+/// Checks job arguments before a job is enqueued, see `QueueOptions::validator`
+///
+/// Closures taking the arguments implement it, so
+/// `Arc::new(|args: &[String]| if args.is_empty() { Err("no args".into()) } else { Ok(()) })` is a
+/// validator
+pub trait Validator: Send + Sync {
+    /// Returns why `args` are invalid, if they are
+    fn validate(&self, args: &[String]) -> Result<(), String>;
+}
+
+impl<F: Fn(&[String]) -> Result<(), String> + Send + Sync> Validator for F {
+    fn validate(&self, args: &[String]) -> Result<(), String> {
+        self(args)
+    }
+}
+
+impl fmt::Debug for dyn Validator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Validator")
+    }
+}
+
+/// Worker idle backoff, sleep between polls of an empty queue grows from `initial` by
+/// `multiplier` up to `max` once `after` consecutive polls came back empty, and is reset as soon as
+/// a job is found
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backoff {
+    /// Number of consecutive empty polls before backing off
+    pub after: usize,
+    /// First sleep
+    pub initial: Duration,
+    /// Sleep cap
+    pub max: Duration,
+    /// Sleep growth factor
+    pub multiplier: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Backoff {
+        Backoff {
+            after: 3,
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(30),
+            multiplier: 2,
+        }
+    }
+}
+
+/// Empty polls tracker applying `Backoff`
+struct Idle {
+    backoff: Option<Backoff>,
+    polls: usize,
+    delay: Duration,
+}
+
+impl Idle {
+    fn new(backoff: Option<Backoff>) -> Idle {
+        let delay = backoff.as_ref().map(|o| o.initial).unwrap_or_default();
+        Idle {
+            backoff,
+            polls: 0,
+            delay,
+        }
+    }
+
+    /// Record an empty poll, sleeping on `clock` if backoff is due
+    fn empty(&mut self, clock: &dyn Clock) {
+        let backoff = match self.backoff {
+            Some(ref o) => o,
+            None => return,
+        };
+
+        self.polls += 1;
+        if self.polls >= backoff.after {
+            clock.sleep(self.delay);
+            self.delay = (self.delay * backoff.multiplier).min(backoff.max);
+        }
+    }
+
+    /// Record a found job
+    fn reset(&mut self) {
+        self.polls = 0;
+        if let Some(ref o) = self.backoff {
+            self.delay = o.initial;
+        }
+    }
+}
+
+/// Worker options
+#[derive(Debug, Clone)]
+pub struct WorkOptions {
+    /// Time to wait for a job in one iteration, 10 seconds by default, zero waits forever
+    pub wait: Duration,
+    /// Timeout, if job hasn't been completed during this time, it will be marked as lost, 30
+    /// seconds by default, e.g. 500ms for jobs which must complete within it. Checks are made
+    /// `freq` times per second, so it should be high enough for a short timeout.
+    pub timeout: Duration,
+    /// Frequency of checking job status while counting on timeout, number of checks per second,
+    /// 1 by default. Workers return an error for zero, and check at most once per millisecond
+    pub freq: usize,
+    /// Job result expiration time, 30 seconds by default, counted to the millisecond
+    pub expire: Duration,
+    /// Fall if job was lost as told by `on_fall`, true by default
+    pub fall: bool,
+    /// How the worker falls, `FallPolicy::Panic` by default
+    pub on_fall: FallPolicy,
+    /// Process jobs infinitely, true by default
+    pub infinite: bool,
+    /// Stop after this number of jobs has been processed, empty polls and expired jobs are not
+    /// counted, unlimited by default
+    pub max_jobs: Option<usize>,
+    /// What to do with a job which hasn't been completed in time, `LostPolicy::Lost` by default
+    pub lost: LostPolicy,
+    /// Sleep between polls of an empty queue, disabled by default
+    pub backoff: Option<Backoff>,
+    /// Retry of the worker connection if Redis can't be reached at startup, e.g. while it is still
+    /// starting, the worker fails at once by default
+    pub connect_retry: Option<Retry>,
+    /// Maximum length of stored result in bytes, longer results are cut with `...` marker at the
+    /// end and flagged as truncated, unlimited by default
+    pub max_result: Option<usize>,
+    /// Number of jobs the worker takes off the ready list ahead of the one it is processing, so
+    /// that the next job is ready without a round trip, none by default. Prefetched jobs wait on the
+    /// processing list with `QUEUED` status, jobs left by a worker which died are put back with
+    /// `Queue::recover`
+    ///
+    /// A batch of `prefetch + 1` jobs is popped in one round trip whenever the worker has none
+    /// left, blocking like a plain claim while the ready lists are empty. The batch is moved to the
+    /// processing list by the same script that pops it, rather than by pipelined `LPOP`s, so a
+    /// worker dying between the pop and the move can't lose it
+    pub prefetch: usize,
+    /// Kinds of jobs to process, earlier kinds are preferred when several have jobs waiting,
+    /// unless `fair` is set. Unkinded jobs are only processed by workers without kinds, which is
+    /// the default
+    pub kinds: Option<Vec<String>>,
+    /// Take jobs of `kinds` round-robin, starting every claim from the next kind, instead of
+    /// preferring earlier kinds, false by default
+    pub fair: bool,
+    /// Take the most recently enqueued job first, false by default. Fresh jobs are served
+    /// quickly, but as long as new jobs keep coming older ones may wait until they expire.
+    pub lifo: bool,
+    /// Worker identifier, when set the job being processed is published for
+    /// `Queue::current_job`, not published by default
+    pub worker_id: Option<String>,
+    /// Executor running job bodies, a new thread per job by default. Job timeout is counted from
+    /// the moment the job is handed to the executor, so a job waiting for a busy pool may be lost.
+    pub executor: Option<Arc<dyn Executor>>,
+    /// Run jobs without writing anything back, false by default, e.g. to debug a worker function
+    /// against production jobs. A job is popped and passed to the worker function as usual, then
+    /// put back to the end of its ready list, and its status, attempts, result and time to live are
+    /// left as they are, identifiers of expired jobs are dropped as a worker would drop them. Side
+    /// effects of the worker function itself aren't prevented. While a job runs other workers don't
+    /// see it, and an `infinite` dry run goes round the queue again once it has seen every job.
+    pub dry_run: bool,
+    /// Name the worker connection is given with `CLIENT SETNAME`, so that `CLIENT LIST` tells
+    /// which connections belong to which workers, `rjq:{name}:{worker}` by default, where worker
+    /// is `worker_id` or a random identifier of the run. An empty name leaves it unnamed.
+    pub client_name: Option<String>,
+    /// What to do with a job which has expired before a worker took it, `ExpiredPolicy::Skip`
+    /// by default. Jobs expired under a dry run are left as they are.
+    pub expired: ExpiredPolicy,
+    /// Partition `(index, count)` of the queue the worker processes, all of it by default. Jobs
+    /// are spread over `QueueOptions::shards` by their identifier, and the worker only pops from
+    /// shards whose number modulo `count` is `index`, so that it always gets the same jobs, e.g.
+    /// to keep a per-worker cache warm. `count` must divide the number of shards.
+    pub partition: Option<(usize, usize)>,
+}
+
+impl Default for WorkOptions {
+    fn default() -> WorkOptions {
+        WorkOptions {
+            wait: Duration::from_secs(10),
+            timeout: Duration::from_secs(30),
+            freq: 1,
+            expire: Duration::from_secs(30),
+            fall: true,
+            on_fall: FallPolicy::Panic,
+            infinite: true,
+            max_jobs: None,
+            lost: LostPolicy::Lost,
+            backoff: None,
+            connect_retry: None,
+            max_result: None,
+            prefetch: 0,
+            kinds: None,
+            fair: false,
+            lifo: false,
+            worker_id: None,
+            executor: None,
+            dry_run: false,
+            client_name: None,
+            expired: ExpiredPolicy::Skip,
+            partition: None,
+        }
+    }
+}
+
+/// Queue options, shared by producers and workers of the queue
+#[derive(Debug, Clone)]
+pub struct QueueOptions {
+    /// Number of lists every ready list is split into, 1 by default.
+    ///
+    /// Jobs are spread over `{name}:uuids:0` .. `{name}:uuids:{shards - 1}` by their identifier
+    /// and workers pop from all shards at once. Order is only kept within a shard: jobs are
+    /// processed roughly, not strictly, in enqueue order. All producers and workers of a queue
+    /// must use the same number of shards.
+    pub shards: usize,
+    /// Store jobs as pretty printed JSON so that they are readable with `redis-cli GET`, compact
+    /// by default. Jobs are read back in either format.
+    pub pretty: bool,
+    /// Job expiration time used by `enqueue_default` and `work_options`, 30 seconds by default
+    pub expire: Duration,
+    /// Job timeout used by `work_options`, 30 seconds by default
+    pub timeout: Duration,
+    /// Number of times a lost job is put back to the queue by `work_options`, 0 by default
+    pub retries: usize,
+    /// Number of attempts, including the first one, after which `retry_failed` leaves a failed
+    /// job as it is, 3 by default, so that a job failing for good isn't retried forever
+    pub retry_limit: usize,
+    /// Number of replicas every enqueue waits for with `WAIT` after writing the job, 0 by default
+    /// so that enqueue returns as soon as the primary has the job.
+    ///
+    /// `WAIT` only tells that replicas have received the write, not that anything is fsynced to
+    /// disk, and a failover may still promote a replica which hasn't. If fewer replicas
+    /// acknowledge the write within `replicas_timeout`, enqueue returns `RjqError::Replication`,
+    /// the job is enqueued anyway.
+    pub replicas: usize,
+    /// Time to wait for `replicas`, 1 second by default, zero waits forever
+    pub replicas_timeout: Duration,
+    /// How queue calls get their connection, `ConnectionStrategy::PerCall` by default
+    pub connections: ConnectionStrategy,
+    /// Store job arguments in a list of their own, `{name}:{uuid}:args`, rather than within the
+    /// job JSON, false by default. Arguments full of quotes and newlines aren't escaped then and
+    /// take less space, at the cost of one more key per job. Workers read jobs either way.
+    pub args_list: bool,
+    /// Time source of the queue and its workers, `SystemClock` by default, see `clock`
+    pub clock: Arc<dyn Clock>,
+    /// Check run on the arguments of every job before it is enqueued, a rejected job isn't written
+    /// and enqueue returns `RjqError::Invalid`, none by default. JSON payloads and values are
+    /// checked as their JSON text, scheduled recurring jobs once when they are scheduled.
+    pub validator: Option<Arc<dyn Validator>>,
+    /// Write keys of queued jobs without a time to live, false by default, so that a server with a
+    /// `volatile-*` eviction policy never evicts them, delayed and recurring ones included. Their
+    /// deadlines are kept in the `{name}:deadlines` sorted set instead, and jobs past it are
+    /// deleted by workers on every poll and by `compact`. Only `allkeys-*` policies evict a pinned
+    /// key, see `eviction_policy`.
+    pub pin: bool,
+    /// Largest total size of job arguments in bytes, enqueue of larger ones returns
+    /// `RjqError::PayloadTooLarge` before anything is sent, 512 MiB by default, the largest bulk
+    /// string Redis accepts unless `proto-max-bulk-len` is raised. Escaping may grow arguments
+    /// stored within the job JSON, so keep it below the server limit.
+    pub max_payload: usize,
+    /// Time a command of a queue call may wait to be written or for its reply before the call
+    /// fails with a timeout error, e.g. so that enqueue in a request handler fails fast while
+    /// Redis hangs, 5 seconds by default, `None` waits forever. Workers have connections of their
+    /// own without a timeout, `Queue::reserve_for` blocks for at most half of it at once. Keep it
+    /// above `replicas_timeout` and the blocking commands run by `with_connection`.
+    pub op_timeout: Option<Duration>,
+    /// Push a job to its ready list only if it isn't listed there already, false by default, so
+    /// that a requeue bug can't list a job twice and get it processed twice. Every push runs a Lua
+    /// script which looks the job up in the list, `LPOS` on Redis 6.0.6 or later, reading the
+    /// whole list otherwise: it takes time linear in the list length, during which Redis serves no
+    /// one else, so it only suits short lists. Lists filled by promoted delayed jobs aren't checked
+    pub dedupe: bool,
+    /// Name connections of queue calls are given with `CLIENT SETNAME`, so that producers can be
+    /// told apart in `CLIENT LIST`, unnamed by default
+    pub client_name: Option<String>,
+}
+
+impl Default for QueueOptions {
+    fn default() -> QueueOptions {
+        QueueOptions {
+            shards: 1,
+            pretty: false,
+            expire: Duration::from_secs(30),
+            timeout: Duration::from_secs(30),
+            retries: 0,
+            retry_limit: 3,
+            replicas: 0,
+            replicas_timeout: Duration::from_secs(1),
+            connections: ConnectionStrategy::PerCall,
+            args_list: false,
+            clock: Arc::new(SystemClock),
+            validator: None,
+            pin: false,
+            max_payload: MAX_PAYLOAD,
+            op_timeout: Some(Duration::from_secs(5)),
+            client_name: None,
+            dedupe: false,
+        }
+    }
+}
+
+/// Outcome of a job run by `execute`
+enum Ran {
+    /// Terminal status and result
+    Done(Status, Option<String>),
+    /// Worker function asked for the job to be run again after a delay, see `RjqError::Defer`
+    Deferred(Duration),
+}
+
+impl Ran {
+    /// Terminal status and result for workers which can't defer jobs, a deferred job has failed
+    fn outcome(self) -> (Status, Option<String>) {
+        match self {
+            Ran::Done(status, result) => (status, result),
+            Ran::Deferred(_) => (Status::FAILED, None),
+        }
+    }
+}
+
+/// Run `fun` on `executor`, a separate thread if none, and wait for it at most `timeout` of
+/// `clock`, checking `freq` times per second and calling `heartbeat` once per second while it runs
+///
+/// Returns terminal status and result, status is LOST if the job hasn't completed in time, or
+/// the delay the job has been deferred by
+///
+/// A job thread outliving the timeout keeps running, its late result is discarded: the status is
+/// already LOST and may have been requeued, so it is only logged as a warning
+#[allow(clippy::too_many_arguments)]
+fn execute<F>(fun: &Arc<F>,
+              executor: Option<&dyn Executor>,
+              clock: &dyn Clock,
+              uuid: &str,
+              label: Option<&str>,
+              args: Vec<String>,
+              timeout: Duration,
+              freq: usize,
+              heartbeat: &mut dyn FnMut())
+              -> Ran
+    where F: Fn(String, Vec<String>) -> Result<String, Box<dyn Error>> + Send + Sync + 'static
+{
+    // One slot for the only message, so the job thread never blocks on send
+    let (tx, rx) = sync_channel(1);
+    let cfun = fun.clone();
+    let cuuid = uuid.to_string();
+    let name = job_name(uuid, label);
+    let body = move || {
+        let r = match cfun(cuuid.clone(), args) {
+            Ok(o) => Ran::Done(Status::FINISHED, Some(o)),
+            // Decode errors are kept as the result, so that a job with arguments of a wrong
+            // shape can be told apart from one failed by the worker function
+            Err(e) => match e.downcast_ref::<RjqError>() {
+                Some(o @ RjqError::ArgsDecode(_)) => Ran::Done(Status::FAILED, Some(o.to_string())),
+                Some(&RjqError::Defer(delay)) => Ran::Deferred(delay),
+                _ => Ran::Done(Status::FAILED, None),
+            },
+        };
+        if let Err(e) = tx.send(r) {
+            let completed = match e.0 {
+                Ran::Done(status, _) => format!("as {:?}", status),
+                Ran::Deferred(_) => "deferred".to_string(),
+            };
+            warn!("job {} completed {} after timeout, result discarded", name, completed);
+        }
+    };
+    match executor {
+        Some(o) => o.spawn(Box::new(body)),
+        None => {
+            thread::spawn(body);
+        }
+    }
+
+    // Workers reject a zero freq, checks more frequent than a millisecond would spin
+    let check = Duration::from_millis((1000 / freq.max(1) as u64).max(1));
+    let deadline = clock.elapsed() + timeout;
+    let mut beat = clock.elapsed() + HEARTBEAT;
+    loop {
+        if let Ok(ran) = rx.try_recv() {
+            return ran;
+        }
+        let now = clock.elapsed();
+        if now >= deadline {
+            return Ran::Done(Status::LOST, None);
+        }
+        if now >= beat {
+            heartbeat();
+            beat = now + HEARTBEAT;
+        }
+        clock.sleep(check.min(deadline - now));
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Job {
     uuid: String,
     status: Status,
+    #[serde(deserialize_with = "deserialize_args")]
     args: Vec<String>,
     result: Option<String>,
+    /// Result was cut to the worker's maximum result length
+    #[serde(default, skip_serializing_if = "is_false")]
+    result_truncated: bool,
+    /// Result is kept as raw bytes in the job result key, see `Queue::work_bytes`
+    #[serde(default, skip_serializing_if = "is_false")]
+    result_bytes: bool,
+    /// Raw result on its way from the worker function to the result key, never stored in the job
+    #[serde(skip)]
+    raw_result: Option<Vec<u8>>,
+    /// Job kind, routed to its own ready list
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kind: Option<String>,
+    /// Number of times a worker has started the job
+    #[serde(default)]
+    attempts: usize,
+    /// Maximum number of attempts allowed by the worker which last processed the job
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_attempts: Option<usize>,
+    /// Uniqueness lock key held while the job is pending or running
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    unique: Option<String>,
+    /// Time the job was last claimed by a worker, milliseconds since unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    started_at: Option<u64>,
+    /// Worker which last claimed the job
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
+    /// URL notified once the job reaches a terminal status, see `webhook`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    callback: Option<String>,
+    /// Arguments are kept in the job arguments list, see `QueueOptions::args_list`
+    #[serde(default, skip_serializing_if = "is_false")]
+    args_list: bool,
+    /// Human readable job name shown in logs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    /// Job arguments as a single JSON value, passed to the worker function as its only argument.
+    /// Kept as JSON text, so that Lua scripts rewriting the job don't turn empty arrays into
+    /// objects or round numbers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    value: Option<String>,
+    /// Time the job was enqueued, milliseconds since unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    enqueued_at: Option<u64>,
+    /// Job tags passed to the worker function of `Queue::work_ctx`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
 }
 
 impl Job {
@@ -93,18 +860,709 @@ impl Job {
         Job {
             uuid: Uuid::new_v4().to_string(),
             status: Status::QUEUED,
-            args: args,
+            args,
             result: None,
+            result_truncated: false,
+            result_bytes: false,
+            raw_result: None,
+            kind: None,
+            attempts: 0,
+            max_attempts: None,
+            unique: None,
+            started_at: None,
+            owner: None,
+            callback: None,
+            args_list: false,
+            label: None,
+            value: None,
+            enqueued_at: None,
+            tags: Vec::new(),
+        }
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Job arguments, jobs rewritten by Lua scripts have no arguments as an empty object because Lua
+/// doesn't tell empty arrays from empty tables
+fn deserialize_args<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Args {
+        List(Vec<String>),
+        Table(BTreeMap<String, String>),
+    }
+
+    match Args::deserialize(deserializer)? {
+        Args::List(o) => Ok(o),
+        Args::Table(ref o) if o.is_empty() => Ok(Vec::new()),
+        Args::Table(_) => Err(de::Error::custom("job arguments must be a list")),
+    }
+}
+
+/// Lua snippet marking decoded queued `job` stored at `key` as running and encoding it back to
+/// `json`, the start time is published to `{name}:started:{uuid}`, see `Queue::enqueue_watch`
+///
+/// ARGV[2] - current time in milliseconds, ARGV[3] - worker, ARGV[4] - running job time to live
+/// in milliseconds
+macro_rules! lua_start {
+    () => {
+        r"
+            job.status = 'RUNNING'
+            job.attempts = (job.attempts or 0) + 1
+            job.started_at = tonumber(ARGV[2])
+            job.owner = ARGV[3]
+            json = cjson.encode(job)
+            redis.call('SET', key, json, 'PX', ARGV[4])
+            if job.unique then
+                redis.call('PEXPIRE', job.unique, ARGV[4])
+            end
+            local prefix = string.sub(key, 1, #key - #job.uuid)
+            redis.call('PUBLISH', prefix .. 'started:' .. job.uuid, ARGV[2])
+"
+    };
+}
+
+/// Pop first job from the ready lists, mark it as running and put it to the processing list, all
+/// at once, so that a popped job is never left unmarked
+///
+/// KEYS[1..n-1] - ready lists in pop order, KEYS[n] - processing list, ARGV[1] - queue name,
+/// ARGV[2..4] - see `lua_start`, ARGV[5] - `LPOP`, or `RPOP` to take the newest job first,
+/// ARGV[6] - `1` to return expired jobs
+///
+/// Expired jobs are dropped on the way, or popped and returned with an empty job if asked.
+/// Returns ready list, uuid, job and whether the job was claimed, jobs which aren't queued or
+/// can't be parsed are popped but returned as they are, nil if all ready lists are empty
+const CLAIM_SCRIPT: &str = concat!(r"
+for i = 1, #KEYS - 1 do
+    local uuid = redis.call(ARGV[5], KEYS[i])
+    while uuid do
+        local key = ARGV[1] .. ':' .. uuid
+        local json = redis.call('GET', key)
+        if json then
+            local ok, job = pcall(cjson.decode, json)
+            if not ok or type(job) ~= 'table' or job.status ~= 'QUEUED' then
+                return {KEYS[i], uuid, json, 0}
+            end
+",
+                                   lua_start!(),
+                                   r"
+            redis.call('RPUSH', KEYS[#KEYS], uuid)
+            return {KEYS[i], uuid, json, 1}
+        elseif ARGV[6] == '1' then
+            return {KEYS[i], uuid, '', 0}
+        end
+        uuid = redis.call(ARGV[5], KEYS[i])
+    end
+end
+return false
+");
+
+/// Push job to a ready list unless it is listed there already, see `QueueOptions::dedupe`
+///
+/// KEYS[1] - ready list, ARGV[1] - uuid, ARGV[2] - `RPUSH`, or `LPUSH` to push to the front
+///
+/// `LPOS` is used where the server has it, the list is read whole otherwise. Returns 1 if the job
+/// has been pushed, 0 if it was listed
+const PUSH_ONCE_SCRIPT: &str = r"
+local listed = redis.pcall('LPOS', KEYS[1], ARGV[1])
+if type(listed) == 'table' and listed.err then
+    listed = false
+    for _, uuid in ipairs(redis.call('LRANGE', KEYS[1], 0, -1)) do
+        if uuid == ARGV[1] then
+            listed = true
+            break
+        end
+    end
+end
+if listed then
+    return 0
+end
+redis.call(ARGV[2], KEYS[1], ARGV[1])
+return 1
+";
+
+/// Move up to ARGV[1] jobs from the ready lists to the processing list without starting them, so
+/// that jobs prefetched by a worker which died can be recovered
+///
+/// KEYS[1..n-1] - ready lists in pop order, KEYS[n] - processing list, ARGV[2] - `LPOP` or
+/// `RPOP`, see `CLAIM_SCRIPT`
+///
+/// Returns ready list and uuid of every moved job, flattened
+const PREFETCH_SCRIPT: &str = r"
+local moved = {}
+for i = 1, #KEYS - 1 do
+    while #moved < tonumber(ARGV[1]) * 2 do
+        local uuid = redis.call(ARGV[2], KEYS[i])
+        if not uuid then
+            break
+        end
+        redis.call('RPUSH', KEYS[#KEYS], uuid)
+        moved[#moved + 1] = KEYS[i]
+        moved[#moved + 1] = uuid
+    end
+end
+return moved
+";
+
+/// Mark prefetched job as running, a job which is gone or isn't queued anymore is taken off the
+/// processing list instead
+///
+/// KEYS[1] - job key, KEYS[2] - processing list, ARGV[1] - uuid, ARGV[2..4] - see `lua_start`
+///
+/// Returns job and whether it was started, nil if the job has expired
+const START_SCRIPT: &str = concat!(r"
+local key = KEYS[1]
+local json = redis.call('GET', key)
+if not json then
+    redis.call('LREM', KEYS[2], 1, ARGV[1])
+    return false
+end
+local ok, job = pcall(cjson.decode, json)
+if not ok or type(job) ~= 'table' or job.status ~= 'QUEUED' then
+    redis.call('LREM', KEYS[2], 1, ARGV[1])
+    return {json, 0}
+end
+",
+                                   lua_start!(),
+                                   r"
+return {json, 1}
+");
+
+/// Ready list, uuid, job json and whether the job was claimed, as returned by `CLAIM_SCRIPT`, json
+/// is empty for an expired job
+type Claimed = (String, String, String, bool);
+
+/// Interval between claim attempts of `MemoryQueue` while it is empty, and between checks for
+/// room by `Queue::enqueue_blocking` while the queue is full
+const CLAIM_POLL: Duration = Duration::from_millis(100);
+
+/// Interval between reads of a running job by `Queue::result_or_wait`
+const GRACE_POLL: Duration = Duration::from_millis(10);
+
+/// Move due jobs from the scheduled set to the ready list atomically, so that a job is promoted by
+/// exactly one caller
+///
+/// KEYS[1] - scheduled set, KEYS[2..] - ready list shards, ARGV[1] - current time in
+/// milliseconds, ARGV[2] - maximum number of jobs to promote
+///
+/// Returns promoted uuids
+const PROMOTE_SCRIPT: &str = r"
+local uuids = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1], 'LIMIT', 0, ARGV[2])
+for i, uuid in ipairs(uuids) do
+    redis.call('ZREM', KEYS[1], uuid)
+    redis.call('RPUSH', KEYS[2 + (i - 1) % (#KEYS - 1)], uuid)
+end
+return uuids
+";
+
+/// Schedule job unless the job holding its uniqueness lock is still queued or running, checking
+/// and scheduling at once so that concurrent producers can't both schedule the same job
+///
+/// KEYS[1] - uniqueness lock, KEYS[2] - scheduled set, KEYS[3] - job key, KEYS[4] - deadlines of
+/// pinned jobs, KEYS[5] - job arguments list, ARGV[1] - queue name, ARGV[2] - uuid, ARGV[3] - job,
+/// ARGV[4] - job and lock time to live in milliseconds, ARGV[5] - due time in milliseconds,
+/// ARGV[6] - `1` to pin the job, ARGV[7] - deadline of a pinned job in milliseconds
+///
+/// Returns uuid of the scheduled job, or of the pending one holding the lock
+const SCHEDULE_UNIQUE_SCRIPT: &str = r"
+local owner = redis.call('GET', KEYS[1])
+if owner then
+    local json = redis.call('GET', ARGV[1] .. ':' .. owner)
+    if json then
+        local ok, job = pcall(cjson.decode, json)
+        if ok and type(job) == 'table' and (job.status == 'QUEUED' or job.status == 'RUNNING') then
+            return owner
+        end
+    end
+end
+redis.call('SET', KEYS[1], ARGV[2], 'PX', ARGV[4])
+if ARGV[6] == '1' then
+    redis.call('SET', KEYS[3], ARGV[3])
+    redis.call('ZADD', KEYS[4], ARGV[7], ARGV[2])
+    redis.call('PERSIST', KEYS[5])
+else
+    redis.call('SET', KEYS[3], ARGV[3], 'PX', ARGV[4])
+end
+redis.call('ZADD', KEYS[2], ARGV[5], ARGV[2])
+return ARGV[2]
+";
+
+/// Enqueue job unless the job holding its uniqueness lock is still queued or running, taking the
+/// lock and enqueueing at once so that concurrent producers can't both enqueue the same job, nor
+/// release a stale lock another producer has just taken
+///
+/// KEYS[1] - uniqueness lock, KEYS[2] - ready list, KEYS[3] - job key, KEYS[4] - deadlines of
+/// pinned jobs, KEYS[5] - job arguments list, ARGV[1] - queue name, ARGV[2] - uuid, ARGV[3] - job,
+/// ARGV[4] - job and lock time to live in milliseconds, ARGV[5] - `1` to pin the job, ARGV[6] -
+/// deadline of a pinned job in milliseconds
+///
+/// Returns uuid of the enqueued job, or of the pending one holding the lock
+const ENQUEUE_UNIQUE_SCRIPT: &str = r"
+local owner = redis.call('GET', KEYS[1])
+if owner then
+    local json = redis.call('GET', ARGV[1] .. ':' .. owner)
+    if json then
+        local ok, job = pcall(cjson.decode, json)
+        if ok and type(job) == 'table' and (job.status == 'QUEUED' or job.status == 'RUNNING') then
+            return owner
+        end
+    end
+end
+redis.call('SET', KEYS[1], ARGV[2], 'PX', ARGV[4])
+if ARGV[5] == '1' then
+    redis.call('SET', KEYS[3], ARGV[3])
+    redis.call('ZADD', KEYS[4], ARGV[6], ARGV[2])
+    redis.call('PERSIST', KEYS[5])
+else
+    redis.call('SET', KEYS[3], ARGV[3], 'PX', ARGV[4])
+end
+redis.call('RPUSH', KEYS[2], ARGV[2])
+return ARGV[2]
+";
+
+/// Move queued job to another queue, taking it off the ready list and writing it under the target
+/// queue at once, so that it is never listed in both or neither
+///
+/// KEYS[1] - job key, KEYS[2] - ready list, KEYS[3] - target job key, KEYS[4] - target ready
+/// list, KEYS[5] - arguments list, KEYS[6] - target arguments list, ARGV[1] - uuid
+///
+/// The job JSON is copied as it is, with its time to live. Returns 1 if the job has been moved, 0
+/// if it doesn't exist, isn't queued or isn't on the ready list, e.g. a delayed job
+const MOVE_SCRIPT: &str = r"
+local json = redis.call('GET', KEYS[1])
+if not json then
+    return 0
+end
+local ok, job = pcall(cjson.decode, json)
+if not ok or type(job) ~= 'table' or job.status ~= 'QUEUED' then
+    return 0
+end
+if redis.call('LREM', KEYS[2], 1, ARGV[1]) == 0 then
+    return 0
+end
+local ttl = redis.call('PTTL', KEYS[1])
+redis.call('DEL', KEYS[1])
+if ttl > 0 then
+    redis.call('SET', KEYS[3], json, 'PX', ttl)
+else
+    redis.call('SET', KEYS[3], json)
+end
+if redis.call('EXISTS', KEYS[5]) == 1 then
+    redis.call('RENAME', KEYS[5], KEYS[6])
+end
+redis.call('RPUSH', KEYS[4], ARGV[1])
+return 1
+";
+
+/// Rename keys of a queue at once, failing before anything is renamed if a target key exists
+///
+/// KEYS[1..n] - keys of the queue, KEYS[n+1..2n] - their new names, ARGV[1] - prefix of the
+/// queue keys, ARGV[2] - prefix of the new names
+///
+/// Uniqueness locks named in jobs are renamed with them, keys expired since they were listed are
+/// skipped. Returns number of renamed keys, or `-i` if KEYS[n+i] exists.
+const RENAME_SCRIPT: &str = r"
+local n = #KEYS / 2
+for i = 1, n do
+    if redis.call('EXISTS', KEYS[n + i]) == 1 then
+        return -i
+    end
+end
+local renamed = 0
+for i = 1, n do
+    if redis.call('TYPE', KEYS[i]).ok == 'string' then
+        local ok, job = pcall(cjson.decode, redis.call('GET', KEYS[i]))
+        if ok and type(job) == 'table' and type(job.unique) == 'string'
+                and string.sub(job.unique, 1, #ARGV[1]) == ARGV[1] then
+            job.unique = ARGV[2] .. string.sub(job.unique, #ARGV[1] + 1)
+            local ttl = redis.call('PTTL', KEYS[i])
+            if ttl > 0 then
+                redis.call('SET', KEYS[i], cjson.encode(job), 'PX', ttl)
+            else
+                redis.call('SET', KEYS[i], cjson.encode(job))
+            end
+        end
+    end
+    if redis.call('EXISTS', KEYS[i]) == 1 then
+        redis.call('RENAME', KEYS[i], KEYS[n + i])
+        renamed = renamed + 1
+    end
+end
+return renamed
+";
+
+/// Glob matching job identifiers, so that job keys are told apart from other keys of the queue
+const UUID_GLOB: &str = "????????-????-????-????-????????????";
+
+/// Interval between refreshes of running job time to live
+const HEARTBEAT: Duration = Duration::from_secs(1);
+
+/// Number of keys measured in one round trip by `Queue::memory_usage`, or read by `Queue::stats`
+const MEMORY_BATCH: usize = 100;
+
+/// Number of due jobs promoted at once
+const PROMOTE_BATCH: usize = 100;
+
+/// Number of most recent run durations kept for `Queue::latency_percentiles`
+const LATENCY_WINDOW: usize = 1000;
+
+/// Closure run once a job is completed, see `Queue::enqueue_local`
+type Completion = Box<dyn FnOnce(Status, Option<String>) + Send>;
+
+/// Completion closures of jobs enqueued in this process
+struct Completions {
+    /// Closure of every job by job key, with the time after which a worker of this process can't
+    /// complete the job anymore, unless it claims the job again
+    closures: BTreeMap<String, (Completion, Instant)>,
+    /// Time of the last sweep of closures past their deadline
+    swept: Option<Instant>,
+}
+
+static COMPLETIONS: Mutex<Completions> = Mutex::new(Completions {
+    closures: BTreeMap::new(),
+    swept: None,
+});
+
+/// Interval between sweeps of completion closures past their deadline
+const COMPLETIONS_SWEEP: Duration = Duration::from_secs(1);
+
+/// Completion closures, a closure which panicked doesn't keep others from running
+fn completions() -> MutexGuard<'static, Completions> {
+    COMPLETIONS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Completions {
+    /// Take closures past their deadline out, at most once per `COMPLETIONS_SWEEP`, to be dropped
+    /// unrun once the registry is unlocked
+    fn sweep(&mut self) -> Vec<Completion> {
+        let now = Instant::now();
+        if self.swept.is_some_and(|o| now < o + COMPLETIONS_SWEEP) {
+            return Vec::new();
+        }
+        self.swept = Some(now);
+
+        let expired: Vec<String> = self.closures
+            .iter()
+            .filter(|&(_, &(_, deadline))| deadline <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        expired.iter().filter_map(|o| self.closures.remove(o)).map(|(o, _)| o).collect()
+    }
+}
+
+/// Register completion closure `on_done` for job `key`, which expires after `ttl` unless a worker
+/// of this process claims it
+fn register_local(key: &str, on_done: Completion, ttl: Duration) {
+    let expired = {
+        let mut completions = completions();
+        completions.closures.insert(key.to_string(), (on_done, Instant::now() + ttl));
+        completions.sweep()
+    };
+    drop(expired);
+}
+
+/// Keep completion closure registered for job `key`, if any, for at least `ttl` the job may run
+/// for once a worker of this process has claimed it
+fn claim_local(key: &str, ttl: Duration) {
+    let expired = {
+        let mut completions = completions();
+        if let Some(&mut (_, ref mut deadline)) = completions.closures.get_mut(key) {
+            *deadline = (*deadline).max(Instant::now() + ttl);
+        }
+        completions.sweep()
+    };
+    drop(expired);
+}
+
+/// Run completion closure registered for job `key`, if any, with its terminal `status` and
+/// `result`
+fn complete_local(key: &str, status: Status, result: Option<String>) {
+    let completion = completions().closures.remove(key);
+    if let Some((completion, _)) = completion {
+        completion(status, result);
+    }
+}
+
+/// Whole milliseconds of time to live `ttl` for `PSETEX` and `PEXPIRE`, which refuse 0
+fn millis(ttl: Duration) -> usize {
+    (ttl.as_millis() as usize).max(1)
+}
+
+/// Job identifier for logs, followed by its label if it has one
+fn job_name(uuid: &str, label: Option<&str>) -> String {
+    match label {
+        Some(o) => format!("{} ({})", uuid, o),
+        None => uuid.to_string(),
+    }
+}
+
+/// Check that a worker is given at least one status check per second, `freq` divides a second
+fn check_freq(freq: usize) -> Result<(), Box<dyn Error>> {
+    if freq == 0 {
+        return Err("freq must be at least one check per second".into());
+    }
+
+    Ok(())
+}
+
+/// Reason for a worker to stop after `processed` jobs: `max_jobs` reached, or one iteration done
+/// if not `infinite`
+fn stop_after(processed: usize, max_jobs: Option<usize>, infinite: bool) -> Option<WorkerExit> {
+    if max_jobs.is_some_and(|m| processed >= m) {
+        Some(WorkerExit::MaxJobs)
+    } else if !infinite {
+        Some(WorkerExit::Once)
+    } else {
+        None
+    }
+}
+
+/// Put job `uuid` popped by a dry run back to the end of `ready_key`, the end the worker pops
+/// from last, so that the dry run goes on with the next job
+fn put_back(conn: &impl Commands,
+            ready_key: &str,
+            uuid: &str,
+            lifo: bool)
+            -> Result<(), Box<dyn Error>> {
+    if lifo {
+        let _: () = conn.lpush(ready_key, uuid)?;
+    } else {
+        let _: () = conn.rpush(ready_key, uuid)?;
+    }
+
+    Ok(())
+}
+
+/// Reason for a worker to stop on an empty poll
+fn drained(draining: bool) -> WorkerExit {
+    if draining { WorkerExit::Drained } else { WorkerExit::Once }
+}
+
+/// Worker function of `Queue::run` passing the job identifier only to `fun`
+fn by_uuid<F>(fun: F) -> impl Fn(JobContext, Vec<String>) -> Result<String, Box<dyn Error>>
+    where F: Fn(String, Vec<String>) -> Result<String, Box<dyn Error>> + Send + Sync + 'static
+{
+    move |ctx, args| fun(ctx.uuid, args)
+}
+
+/// Refresh time to live of running job `key` and its uniqueness `lock`, so that they don't expire
+/// while the job is running
+///
+/// Failures are only reported, the job keeps running and its terminal status is written anyway
+fn keepalive(conn: &impl Commands, key: &str, lock: Option<&str>, ttl: Duration) {
+    let mut pipe = redis::pipe();
+    pipe.pexpire(key, millis(ttl)).ignore();
+    if let Some(lock) = lock {
+        pipe.pexpire(lock, millis(ttl)).ignore();
+    }
+    if let Err(e) = pipe.query::<()>(conn) {
+        warn!("job {} keepalive failed: {}", key, e);
+    }
+}
+
+/// Read and delete key at once on servers without GETDEL
+///
+/// KEYS[1] - key
+const GETDEL_SCRIPT: &str = r"
+local value = redis.call('GET', KEYS[1])
+redis.call('DEL', KEYS[1])
+return value
+";
+
+/// Delete queued and cancelled jobs past their deadline, see `QueueOptions::pin`, jobs which
+/// have started since have a time to live again and are only taken off the deadlines
+///
+/// KEYS[1] - deadlines set, ARGV[1] - current time in milliseconds, ARGV[2] - queue name
+///
+/// Returns number of deleted jobs
+const EXPIRE_PINNED_SCRIPT: &str = r"
+local expired = 0
+for _, uuid in ipairs(redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1])) do
+    redis.call('ZREM', KEYS[1], uuid)
+    local key = ARGV[2] .. ':' .. uuid
+    local json = redis.call('GET', key)
+    if json then
+        local ok, job = pcall(cjson.decode, json)
+        if ok and type(job) == 'table' and (job.status == 'QUEUED' or job.status == 'CANCELLED')
+        then
+            redis.call('DEL', key, key .. ':args')
+            expired = expired + 1
+        end
+    end
+end
+return expired
+";
+
+/// Redis server version as major, minor and patch numbers, told by `INFO server`
+fn probe_version(conn: &impl Commands) -> Result<Version, Box<dyn Error>> {
+    let info: String = redis::cmd("INFO").arg("server").query(conn)?;
+    let version = info.lines()
+        .find_map(|o| o.strip_prefix("redis_version:"))
+        .unwrap_or_default();
+    let mut numbers = version.trim().split('.').map(|o| o.parse().unwrap_or(0));
+
+    Ok((numbers.next().unwrap_or(0), numbers.next().unwrap_or(0), numbers.next().unwrap_or(0)))
+}
+
+/// Redis command popping the next job off a ready list
+fn pop_command(lifo: bool) -> &'static str {
+    if lifo { "RPOP" } else { "LPOP" }
+}
+
+/// Default `QueueOptions::max_payload`, `proto-max-bulk-len` of Redis
+const MAX_PAYLOAD: usize = 512 * 1024 * 1024;
+
+/// Least number of jobs read by one MGET of `statuses` and `results`, larger batches are split
+/// over pooled connections
+const MGET_BATCH: usize = 500;
+
+/// Number of jobs written by one pipeline of `enqueue_stream`
+const STREAM_BATCH: usize = 100;
+
+/// Marker appended to truncated results
+const ELLIPSIS: &str = "...";
+
+/// Cut `result` to at most `max` bytes including the ellipsis marker, on a char boundary, the
+/// marker itself is cut if `max` can't hold it
+///
+/// Returns result and whether it was truncated
+fn truncate(mut result: String, max: usize) -> (String, bool) {
+    if result.len() <= max {
+        return (result, false);
+    }
+
+    let marker = &ELLIPSIS[..ELLIPSIS.len().min(max)];
+    let mut end = max - marker.len();
+    while !result.is_char_boundary(end) {
+        end -= 1;
+    }
+    result.truncate(end);
+    result.push_str(marker);
+
+    (result, true)
+}
+
+/// Shard of `uuid` among `shards` lists
+fn shard(uuid: &str, shards: usize) -> usize {
+    let hash = uuid.bytes().fold(0usize, |h, b| h.wrapping_mul(31).wrapping_add(b as usize));
+
+    hash % shards
+}
+
+/// Indices of `weights` in sampled order: every next index is drawn among those left with
+/// probability proportional to its weight, zero weights come last in their order
+fn weighted_order(weights: &[f64]) -> Vec<usize> {
+    let mut left: Vec<usize> = (0..weights.len()).collect();
+    let mut order = Vec::with_capacity(left.len());
+    loop {
+        let total: f64 = left.iter().map(|&i| weights[i]).sum();
+        if total <= 0.0 {
+            break;
+        }
+        let mut point = rand::random::<f64>() * total;
+        let drawn = left.iter()
+            .position(|&i| {
+                point -= weights[i];
+                point < 0.0
+            })
+            // Rounding may leave the point past the last weight
+            .or_else(|| left.iter().rposition(|&i| weights[i] > 0.0))
+            .unwrap_or(0);
+        order.push(left.remove(drawn));
+    }
+    order.extend(left);
+
+    order
+}
+
+/// Ready list shards in pop order, each group of shards is rotated by `offset` so that no
+/// shard is always preferred, and so are the groups themselves if `fair`
+fn rotated(groups: &[Vec<String>], offset: usize, fair: bool) -> Vec<String> {
+    let first = if fair && !groups.is_empty() {
+        offset % groups.len()
+    } else {
+        0
+    };
+    groups[first..]
+        .iter()
+        .chain(groups[..first].iter())
+        .flat_map(|o| {
+            let start = offset % o.len();
+            o[start..].iter().chain(o[..start].iter()).cloned()
+        })
+        .collect()
+}
+
+/// Check that queue `name` is neither empty nor contains `:`, see `Queue::try_new`
+fn check_name(name: &str) -> Result<(), RjqError> {
+    if name.is_empty() {
+        return Err(RjqError::InvalidName("queue name is empty".to_string()));
+    }
+    if name.contains(':') {
+        return Err(RjqError::InvalidName(format!("{:?} contains the key separator ':'", name)));
+    }
+
+    Ok(())
+}
+
+/// Escape glob special characters of `value` for SCAN MATCH
+fn escape_glob(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for o in value.chars() {
+        if "*?[]\\".contains(o) {
+            escaped.push('\\');
         }
+        escaped.push(o);
     }
+
+    escaped
+}
+
+/// Recurring job schedule, stored in the `{name}:recurring` hash by schedule identifier
+#[derive(Debug, Serialize, Deserialize)]
+struct Recurring {
+    args: Vec<String>,
+    /// Expiration time of every run in milliseconds
+    expire_ms: u64,
+    /// Interval between runs in milliseconds
+    interval_ms: u64,
+    /// Time the next run is due, milliseconds since unix epoch
+    next: u64,
+    /// Identifier of the last enqueued run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last: Option<String>,
+}
+
+/// Hash of job arguments identifying jobs with the same payload
+fn args_hash(args: &[String]) -> Result<String, Box<dyn Error>> {
+    let mut sha = Sha1::new();
+    sha.update(serde_json::to_string(args).map_err(RjqError::Serialize)?.as_bytes());
+
+    Ok(sha.digest().to_string())
 }
 
 /// Queue
+///
+/// Queue is `Send` and `Sync`, a single instance can be shared between threads with `Arc`. By
+/// default every call opens its own connection and closes it before returning, see
+/// `QueueOptions::connections`.
+#[derive(Clone)]
 pub struct Queue {
     /// Redis url
     url: String,
     /// Queue name
     name: String,
+    /// Queue options
+    options: QueueOptions,
+    /// Connections kept between calls, shared by clones of the queue
+    pool: Option<Arc<Pool>>,
+    /// Redis server version, probed once and shared by clones of the queue
+    version: Arc<OnceLock<Version>>,
+    /// Store serving plain reads instead of the queue connection, see `with_store`
+    store: Option<Arc<dyn Store + Send + Sync>>,
 }
 
 impl Queue {
@@ -114,174 +1572,3712 @@ impl Queue {
     ///
     /// `name` - queue name
     pub fn new(url: &str, name: &str) -> Queue {
+        Queue::with_options(url, name, QueueOptions::default())
+    }
+
+    /// Init new queue object with options
+    ///
+    /// `url` - redis url to connect
+    ///
+    /// `name` - queue name
+    ///
+    /// `options` - queue options, see `QueueOptions` for defaults
+    pub fn with_options(url: &str, name: &str, options: QueueOptions) -> Queue {
+        let size = match options.connections {
+            ConnectionStrategy::PerCall => None,
+            ConnectionStrategy::Shared => Some(1),
+            ConnectionStrategy::Pooled(size) => Some(size),
+        };
+        let pool = size.map(|size| {
+            Pool::new(url, size, options.op_timeout, options.client_name.as_deref())
+        });
+
         Queue {
             url: url.to_string(),
             name: name.to_string(),
+            options,
+            pool,
+            version: Arc::new(OnceLock::new()),
+            store: None,
         }
     }
 
-    /// Delete enqueued jobs
-    pub fn drop(&self) -> Result<(), Box<Error>> {
-        let client = Client::open(self.url.as_str())?;
-        let conn = client.get_connection()?;
-
-        let _: () = conn.del(format!("{}:uuids", self.name))?;
-
-        Ok(())
+    /// Init new queue object reading jobs from a custom store
+    ///
+    /// `url` - redis url to connect for everything else
+    ///
+    /// `name` - queue name
+    ///
+    /// `options` - queue options, see `QueueOptions` for defaults
+    ///
+    /// `store` - store taking the `GET` commands of the calls which only read a job or a worker,
+    /// see the `store` module for which they are
+    pub fn with_store(url: &str,
+                      name: &str,
+                      options: QueueOptions,
+                      store: Arc<dyn Store + Send + Sync>)
+                      -> Queue {
+        Queue { store: Some(store), ..Queue::with_options(url, name, options) }
     }
 
-    /// Enqueue new job
+    /// Init new queue object with options, checking the name first
     ///
-    /// `args` - job arguments
+    /// `url` - redis url to connect
     ///
-    /// `expire` - job expiration time in seconds, if hasn't started during this time it will be
-    /// removed
+    /// `name` - queue name, it must not be empty or contain `:`, the separator of its keys
     ///
-    /// Returns unique job identifier
-    pub fn enqueue(&self, args: Vec<String>, expire: usize) -> Result<String, Box<Error>> {
-        let client = Client::open(self.url.as_str())?;
-        let conn = client.get_connection()?;
+    /// `options` - queue options, see `QueueOptions` for defaults
+    ///
+    /// Keys of a queue are `{name}:` followed by a job identifier or a suffix such as `uuids`, so
+    /// with a separator in the name the keys of queue `a:uuids` would overlap with those of queue
+    /// `a`. `new` and `with_options` take nested names as they are, e.g. for existing queues.
+    ///
+    /// Returns `RjqError::InvalidName` if the name is rejected
+    pub fn try_new(url: &str, name: &str, options: QueueOptions) -> Result<Queue, RjqError> {
+        check_name(name)?;
 
-        let job = Job::new(args);
+        Ok(Queue::with_options(url, name, options))
+    }
 
-        let _: () = conn.set_ex(format!("{}:{}", self.name, job.uuid),
-                                serde_json::to_string(&job)?,
-                                expire)?;
-        let _: () = conn.rpush(format!("{}:uuids", self.name), &job.uuid)?;
+    /// Milliseconds since unix epoch told by `QueueOptions::clock`
+    fn now_ms(&self) -> u64 {
+        let now = self.options.clock.now();
+        now.as_secs() * 1000 + u64::from(now.subsec_millis())
+    }
 
-        Ok(job.uuid)
+    /// Get connection for a single call as told by `QueueOptions::connections`
+    fn conn(&self) -> Result<Lease, Box<dyn Error>> {
+        match self.pool {
+            Some(ref pool) => Pool::get(pool),
+            None => {
+                Ok(Lease::own(connect_with(&self.url,
+                                           self.options.op_timeout,
+                                           self.options.client_name.as_deref())?))
+            }
+        }
+    }
+
+    /// Run `f` on the store for plain reads, connecting to Redis only without a custom one
+    fn on_store<T, F>(&self, f: F) -> Result<T, Box<dyn Error>>
+        where F: FnOnce(&dyn Store) -> Result<T, Box<dyn Error>>
+    {
+        let store = match self.store {
+            Some(ref store) => return f(&**store),
+            None => self.conn()?,
+        };
+
+        let result = f(&RedisStore::new(&store));
+        if result.is_err() {
+            store.fail();
+        }
+
+        result
+    }
+
+    /// Get encoded job `uuid` from `store`
+    fn job_json(&self, store: &dyn Store, uuid: &str) -> Result<String, Box<dyn Error>> {
+        match store.get(&format!("{}:{}", self.name, uuid))? {
+            Some(o) => Ok(o),
+            None => Err(format!("job {} doesn't exist", uuid).into()),
+        }
+    }
+
+    /// Redis server version told by `INFO server` on `conn`, probed on the first call only
+    fn version(&self, conn: &impl Commands) -> Result<Version, Box<dyn Error>> {
+        if let Some(version) = self.version.get() {
+            return Ok(*version);
+        }
+
+        let version = probe_version(conn)?;
+        // Another clone may have probed it meanwhile, both got the same version
+        let _ = self.version.set(version);
+
+        Ok(version)
+    }
+
+    /// Get Redis server version
+    ///
+    /// Returns major, minor and patch numbers of `redis_version` told by `INFO server`, probed once
+    /// per queue and its clones. Commands missing on older servers are replaced with Lua scripts
+    /// or refused with `RjqError::Unsupported` by the version, rather than sent to fail.
+    pub fn server_version(&self) -> Result<Version, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        self.version(&conn)
+    }
+
+    /// Get Redis eviction policy
+    ///
+    /// Returns `maxmemory-policy` told by `CONFIG GET`. With `noeviction` the server refuses
+    /// writes once out of memory, with `volatile-*` it may evict keys with a time to live, queued
+    /// jobs included unless `QueueOptions::pin` is set, with `allkeys-*` any key, ready lists
+    /// included.
+    pub fn eviction_policy(&self) -> Result<String, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        let (_, policy): (String, String) = redis::cmd("CONFIG")
+            .arg("GET")
+            .arg("maxmemory-policy")
+            .query(&conn)?;
+
+        Ok(policy)
+    }
+
+    /// Log a warning if the server may evict job keys before they expire, workers check it once
+    /// on start
+    fn check_eviction(&self) {
+        // CONFIG is often disabled on managed servers, the policy is unknown then
+        let policy = match self.eviction_policy() {
+            Ok(o) => o,
+            Err(_) => return,
+        };
+        if policy.starts_with("allkeys-") {
+            warn!("maxmemory-policy is {}, Redis may evict any key of queue {}, jobs and \
+                   ready lists included",
+                  policy,
+                  self.name);
+        } else if policy.starts_with("volatile-") && !self.options.pin {
+            warn!("maxmemory-policy is {}, Redis may evict queued jobs of queue {} \
+                   before they expire, see QueueOptions::pin",
+                  policy,
+                  self.name);
+        }
+    }
+
+    /// Run `f` on a connection to the queue Redis, taken as told by `QueueOptions::connections`,
+    /// e.g. for a custom command next to the queue keys
+    ///
+    /// `f` - function running commands on the connection, a pooled connection is returned to the
+    /// pool once it is done, so it must leave the connection as it got it, e.g. not `SELECT`
+    /// another database or `SUBSCRIBE`
+    ///
+    /// Returns result of `f`
+    pub fn with_connection<T, F>(&self, f: F) -> Result<T, Box<dyn Error>>
+        where F: FnOnce(&mut Connection) -> redis::RedisResult<T>
+    {
+        let mut conn = self.conn()?;
+
+        let result = f(&mut conn);
+        if result.is_err() {
+            conn.fail();
+        }
+
+        Ok(result?)
+    }
+
+    /// Find names of queues starting with `prefix` which have jobs waiting
+    ///
+    /// `url` - redis url to connect
+    ///
+    /// `prefix` - queue name prefix, empty for all queues
+    ///
+    /// Ready lists are looked up with `SCAN`, so Redis isn't blocked. A queue whose ready lists
+    /// are empty has no keys to find and is not listed.
+    ///
+    /// Returns sorted queue names
+    pub fn discover(url: &str, prefix: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let conn = connect(url)?;
+
+        let pattern = format!("{}*:uuids*", escape_glob(prefix));
+        let keys: Vec<String> = conn.scan_match(pattern)?.collect();
+        let mut names = BTreeSet::new();
+        for key in keys {
+            // Shards are suffixed with their index
+            let list = match key.rfind(':') {
+                Some(i) if !key.ends_with(":uuids") &&
+                           key[i + 1..].bytes().all(|o| o.is_ascii_digit()) => &key[..i],
+                _ => &key[..],
+            };
+            let name = match list.strip_suffix(":uuids") {
+                Some(o) if o.starts_with(prefix) => o,
+                _ => continue,
+            };
+            // Ready lists of kinds are named after their queue and kind
+            let name = match name.rfind(':') {
+                Some(i) => {
+                    let kinded: bool = conn.sismember(format!("{}:kinds", &name[..i]),
+                                                      &name[i + 1..])?;
+                    if kinded { &name[..i] } else { name }
+                }
+                None => name,
+            };
+            names.insert(name.to_string());
+        }
+
+        Ok(names.into_iter().collect())
+    }
+
+    /// Worker options with the queue defaults: `timeout`, `expire`, and requeueing lost jobs
+    /// `retries` times, other options are `WorkOptions` defaults
+    pub fn work_options(&self) -> WorkOptions {
+        let lost = match self.options.retries {
+            0 => LostPolicy::Lost,
+            retries => LostPolicy::Requeue { max_attempts: retries + 1 },
+        };
+
+        WorkOptions {
+            timeout: self.options.timeout,
+            expire: self.options.expire,
+            lost,
+            ..Default::default()
+        }
+    }
+
+    /// Ready list shard keys for jobs of `kind`, unkinded jobs have their own list
+    fn ready_shards(&self, kind: Option<&str>) -> Vec<String> {
+        let key = match kind {
+            Some(kind) => format!("{}:{}:uuids", self.name, kind),
+            None => format!("{}:uuids", self.name),
+        };
+        if self.options.shards <= 1 {
+            return vec![key];
+        }
+
+        (0..self.options.shards).map(|o| format!("{}:{}", key, o)).collect()
+    }
+
+    /// Ready list key for job `uuid` of `kind`
+    fn ready_key(&self, kind: Option<&str>, uuid: &str) -> String {
+        let mut shards = self.ready_shards(kind);
+        let index = shard(uuid, shards.len());
+
+        shards.swap_remove(index)
+    }
+
+    /// Keys of all ready lists: unkinded one and one per kind ever enqueued, with their shards
+    fn ready_keys(&self, conn: &impl Commands) -> Result<Vec<String>, Box<dyn Error>> {
+        let kinds: Vec<String> = conn.smembers(format!("{}:kinds", self.name))?;
+        let mut keys = self.ready_shards(None);
+        for kind in kinds.iter() {
+            keys.extend(self.ready_shards(Some(kind)));
+        }
+
+        Ok(keys)
+    }
+
+    /// Serialize job for storage
+    fn encode(&self, job: &Job) -> Result<String, RjqError> {
+        let json = if self.options.pretty {
+            serde_json::to_string_pretty(job)
+        } else {
+            serde_json::to_string(job)
+        };
+
+        json.map_err(RjqError::Serialize)
+    }
+
+    /// Delete enqueued jobs
+    ///
+    /// This is a destructive admin operation: ready lists and the scheduled set are detached
+    /// atomically, so jobs enqueued concurrently are kept, and all jobs on them are deleted
+    /// whatever their status.
+    /// Jobs already popped by workers are not on the lists, they keep running and their keys
+    /// expire as usual. Use `drop_safe` to remove queued jobs only.
+    pub fn drop(&self) -> Result<(), Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        let scheduled = format!("{}:scheduled", self.name);
+        let mut sources = self.ready_keys(&conn)?;
+        sources.push(scheduled.clone());
+        for source in sources {
+            // RENAME fails on a missing key, and a failed command closes the connection
+            let dropping = format!("{}:dropping:{}", source, Uuid::new_v4());
+            let renamed = redis::transaction(&conn, &[&source], |pipe| {
+                let exists: bool = conn.exists(&source)?;
+                if !exists {
+                    return Ok(Some(false));
+                }
+                pipe.rename(&source, &dropping).ignore();
+                let done: Option<()> = pipe.query(&conn)?;
+                Ok(done.map(|_| true))
+            })?;
+            if !renamed {
+                continue;
+            }
+
+            let uuids: Vec<String> = if source == scheduled {
+                conn.zrange(&dropping, 0, -1)?
+            } else {
+                conn.lrange(&dropping, 0, -1)?
+            };
+            let mut keys: Vec<String> = uuids.iter()
+                .flat_map(|o| {
+                    vec![format!("{}:{}", self.name, o), self.args_key(o), self.result_key(o)]
+                })
+                .collect();
+            keys.push(dropping);
+            let _: () = conn.del(keys)?;
+        }
+        let _: () = conn.del(self.deadlines_key())?;
+
+        Ok(())
+    }
+
+    /// Delete enqueued jobs which are still queued, jobs with any other status are kept
+    ///
+    /// Returns number of deleted jobs
+    pub fn drop_safe(&self) -> Result<usize, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        let mut dropped = 0;
+        for ready in self.ready_keys(&conn)? {
+            let uuids: Vec<String> = conn.lrange(&ready, 0, -1)?;
+            for uuid in uuids.iter() {
+                let key = format!("{}:{}", self.name, uuid);
+                let json: Option<String> = conn.get(&key)?;
+                let queued = json.and_then(|o| serde_json::from_str::<Job>(&o).ok())
+                    .is_some_and(|o| o.status == Status::QUEUED);
+                if !queued {
+                    continue;
+                }
+
+                // Job is deleted only if no worker has popped it meanwhile
+                let removed: usize = conn.lrem(&ready, 1, uuid)?;
+                if removed > 0 {
+                    let _: () = conn.del(&key)?;
+                    dropped += 1;
+                }
+            }
+        }
+
+        Ok(dropped)
+    }
+
+    /// Rename the queue with all its jobs, e.g. for a migration, so that workers and producers of
+    /// `new_name` take over the jobs left
+    ///
+    /// `new_name` - new queue name, checked as by `try_new`
+    ///
+    /// Keys of the queue are found with `SCAN`, so that Redis isn't blocked, then renamed by a Lua
+    /// script at once, along with the uniqueness locks named in jobs. The script fails before
+    /// renaming anything if a key of `new_name` exists already. Keys written between the scan and
+    /// the script are left, so stop producers and workers of the queue first. Keys of nested
+    /// queues such as `{name}:a` would be renamed too. This queue object keeps its name, use a new
+    /// one for `new_name`.
+    ///
+    /// Returns number of renamed keys
+    pub fn rename(&self, new_name: &str) -> Result<usize, Box<dyn Error>> {
+        check_name(new_name)?;
+        if new_name == self.name {
+            return Err(format!("queue {} is already named so", self.name).into());
+        }
+
+        let conn = self.conn()?;
+
+        let prefix = format!("{}:", self.name);
+        let keys: Vec<String> = conn.scan_match(format!("{}*", escape_glob(&prefix)))?.collect();
+        if keys.is_empty() {
+            return Ok(0);
+        }
+        let renamed: Vec<String> =
+            keys.iter().map(|o| format!("{}:{}", new_name, &o[prefix.len()..])).collect();
+
+        let script = Script::new(RENAME_SCRIPT);
+        let mut invocation = script.prepare_invoke();
+        for key in keys.iter().chain(renamed.iter()) {
+            invocation.key(key);
+        }
+        let count: i64 = invocation.arg(&prefix).arg(format!("{}:", new_name)).invoke(&conn)?;
+        if count < 0 {
+            let conflict = &renamed[(-count - 1) as usize];
+            return Err(format!("key {} of queue {} exists already", conflict, new_name).into());
+        }
+
+        Ok(count as usize)
+    }
+
+    /// Remove identifiers of expired jobs from the ready lists and the scheduled set
+    ///
+    /// A job key expires while its identifier stays listed. Workers drop such identifiers as they
+    /// reach them, `compact` prunes them all at once, e.g. so that list lengths count live jobs
+    /// only. A job key is written together with its identifier and never comes back once expired,
+    /// so live jobs are never removed. Pinned jobs past their deadline are deleted first, see
+    /// `QueueOptions::pin`.
+    ///
+    /// Returns number of removed identifiers
+    pub fn compact(&self) -> Result<usize, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        self.expire_pinned(&conn)?;
+
+        let scheduled = format!("{}:scheduled", self.name);
+        let mut sources = self.ready_keys(&conn)?;
+        sources.push(scheduled.clone());
+        let mut removed = 0;
+        for source in sources {
+            let uuids: Vec<String> = if source == scheduled {
+                conn.zrange(&source, 0, -1)?
+            } else {
+                conn.lrange(&source, 0, -1)?
+            };
+            if uuids.is_empty() {
+                continue;
+            }
+
+            let mut pipe = redis::pipe();
+            for uuid in uuids.iter() {
+                pipe.exists(format!("{}:{}", self.name, uuid));
+            }
+            let exists: Vec<bool> = pipe.query(&conn)?;
+            let mut pipe = redis::pipe();
+            for (uuid, _) in uuids.iter().zip(exists).filter(|&(_, o)| !o) {
+                if source == scheduled {
+                    pipe.zrem(&source, uuid);
+                } else {
+                    pipe.lrem(&source, 1, uuid);
+                }
+            }
+            let counts: Vec<usize> = pipe.query(&conn)?;
+            removed += counts.iter().sum::<usize>();
+        }
+
+        Ok(removed)
+    }
+
+    /// Delete results of completed jobs to free memory, keeping the jobs with their status and
+    /// time to live
+    ///
+    /// Queued and running jobs, the ready lists and the scheduled set are left as they are. Job
+    /// keys are found with `SCAN`, so that Redis isn't blocked.
+    ///
+    /// Returns number of cleared results
+    pub fn clear_results(&self) -> Result<usize, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        let pattern = format!("{}:{}", escape_glob(&self.name), UUID_GLOB);
+        let keys: Vec<String> = conn.scan_match(pattern)?.collect();
+        let mut cleared = 0;
+        for key in keys.iter() {
+            let done = redis::transaction(&conn, &[key], |pipe| {
+                let json: Option<String> = conn.get(key)?;
+                let mut job = match json.and_then(|o| serde_json::from_str::<Job>(&o).ok()) {
+                    Some(o) => o,
+                    None => return Ok(Some(false)),
+                };
+                if !job.status.is_terminal() || (job.result.is_none() && !job.result_bytes) {
+                    return Ok(Some(false));
+                }
+                if job.result_bytes {
+                    pipe.del(self.result_key(&job.uuid)).ignore();
+                }
+                job.result = None;
+                job.result_truncated = false;
+                job.result_bytes = false;
+                let json = self.encode(&job)
+                    .map_err(|e| redis::RedisError::from((redis::ErrorKind::TypeError,
+                                                          "job serialization failed",
+                                                          e.to_string())))?;
+
+                let ttl: i64 = redis::cmd("PTTL").arg(key).query(&conn)?;
+                if ttl > 0 {
+                    pipe.cmd("SET").arg(key).arg(json).arg("PX").arg(ttl).ignore();
+                } else {
+                    pipe.set(key, json).ignore();
+                }
+                let done: Option<()> = pipe.query(&conn)?;
+                Ok(done.map(|_| true))
+            })?;
+            if done {
+                cleared += 1;
+            }
+        }
+
+        Ok(cleared)
+    }
+
+    /// Put failed jobs back to the queue, e.g. once the bug they failed on is fixed
+    ///
+    /// Every `FAILED` job with fewer attempts than `QueueOptions::retry_limit` is queued again
+    /// without its result, it expires in `QueueOptions::expire` unless it is started. Jobs are
+    /// found with `SCAN`, so that Redis isn't blocked, only failed jobs which haven't expired yet
+    /// are found.
+    ///
+    /// Returns number of jobs put back
+    pub fn retry_failed(&self) -> Result<usize, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        let expire = self.options.expire;
+        let pattern = format!("{}:{}", escape_glob(&self.name), UUID_GLOB);
+        let keys: Vec<String> = conn.scan_match(pattern)?.collect();
+        let mut retried = 0;
+        for key in keys.iter() {
+            let done = redis::transaction(&conn, &[key], |pipe| {
+                let json: Option<String> = conn.get(key)?;
+                let mut job = match json.and_then(|o| serde_json::from_str::<Job>(&o).ok()) {
+                    Some(o) => o,
+                    None => return Ok(Some(false)),
+                };
+                if job.status != Status::FAILED || job.attempts >= self.options.retry_limit {
+                    return Ok(Some(false));
+                }
+                if job.result_bytes {
+                    pipe.del(self.result_key(&job.uuid)).ignore();
+                }
+                job.status = Status::QUEUED;
+                job.result = None;
+                job.result_truncated = false;
+                job.result_bytes = false;
+                let json = self.encode(&job)
+                    .map_err(|e| redis::RedisError::from((redis::ErrorKind::TypeError,
+                                                          "job serialization failed",
+                                                          e.to_string())))?;
+
+                self.write_job(pipe, &job, json, expire, false);
+                // Pinned jobs have their arguments list persisted along with them
+                if job.args_list && !self.options.pin {
+                    pipe.pexpire(self.args_key(&job.uuid), millis(expire)).ignore();
+                }
+                let done: Option<()> = pipe.query(&conn)?;
+                Ok(done.map(|_| true))
+            })?;
+            if done {
+                retried += 1;
+            }
+        }
+
+        Ok(retried)
+    }
+
+    /// Estimate Redis memory used by the queue
+    ///
+    /// Sums `MEMORY USAGE` over all keys of the queue: jobs, ready lists, the scheduled set and
+    /// the rest, found with `SCAN` so that Redis isn't blocked. The figure is approximate, Redis
+    /// samples large lists and sets, and keys of queues named `{name}:...` are counted as well.
+    /// `MEMORY USAGE` requires Redis 4 or later.
+    ///
+    /// Returns estimate in bytes
+    pub fn memory_usage(&self) -> Result<usize, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        let version = self.version(&conn)?;
+        if version < (4, 0, 0) {
+            return Err(RjqError::Unsupported {
+                    command: "MEMORY USAGE",
+                    version,
+                }
+                .into());
+        }
+
+        let keys: Vec<String> = conn.scan_match(format!("{}:*", escape_glob(&self.name)))?
+            .collect();
+        let mut usage = 0;
+        for chunk in keys.chunks(MEMORY_BATCH) {
+            let mut pipe = redis::pipe();
+            for key in chunk {
+                pipe.cmd("MEMORY").arg("USAGE").arg(key);
+            }
+            // Keys expired since the scan have no usage
+            let sizes: Vec<Option<usize>> = pipe.query(&conn)?;
+            usage += sizes.into_iter().flatten().sum::<usize>();
+        }
+
+        Ok(usage)
+    }
+
+    /// Count jobs of the queue by status, exactly
+    ///
+    /// Every job key is found with `SCAN`, so that Redis isn't blocked, and read in pipelined
+    /// batches, so it takes a round trip per 100 jobs. Counts are exact as of the scan: jobs which
+    /// have expired aren't counted, and jobs prefetched by workers are counted as queued. Dead
+    /// jobs are the length of the dead letter list. See `stats_estimate` for a single round trip.
+    pub fn stats(&self) -> Result<QueueStats, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        let pattern = format!("{}:{}", escape_glob(&self.name), UUID_GLOB);
+        let keys: Vec<String> = conn.scan_match(pattern)?.collect();
+        let mut stats = QueueStats::default();
+        for chunk in keys.chunks(MEMORY_BATCH) {
+            // Keys expired since the scan are skipped, as are corrupt jobs
+            let jsons: Vec<Option<String>> = redis::cmd("MGET").arg(chunk).query(&conn)?;
+            for job in jsons.iter().flatten().filter_map(|o| serde_json::from_str::<Job>(o).ok()) {
+                match job.status {
+                    Status::QUEUED => stats.queued += 1,
+                    Status::RUNNING => stats.running += 1,
+                    Status::FINISHED => stats.finished += 1,
+                    Status::FAILED => stats.failed += 1,
+                    Status::LOST => stats.lost += 1,
+                    Status::CANCELLED => stats.cancelled += 1,
+                }
+            }
+        }
+        stats.dead = conn.llen(format!("{}:dead", self.name))?;
+
+        Ok(stats)
+    }
+
+    /// Count jobs of the queue by status approximately, in a single round trip
+    ///
+    /// Queued jobs are the lengths of the ready lists, so delayed jobs aren't counted. Running
+    /// jobs are the length of the processing list, which holds prefetched jobs as well. Finished,
+    /// failed, lost and cancelled jobs are totals counted whenever a job gets the status, kept in
+    /// the `{name}:counts` hash: they include jobs which have expired since, and jobs written by
+    /// earlier versions aren't counted. See `stats` for exact counts.
+    pub fn stats_estimate(&self) -> Result<QueueStats, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        let ready = self.ready_keys(&conn)?;
+        let mut pipe = redis::pipe();
+        for key in ready.iter() {
+            pipe.llen(key);
+        }
+        pipe.llen(self.processing_key())
+            .llen(format!("{}:dead", self.name))
+            .hgetall(self.counts_key());
+        let (lengths, counts): (Vec<usize>, HashMap<String, usize>) = {
+            let mut replies: Vec<redis::Value> = pipe.query(&conn)?;
+            let counts = redis::from_redis_value(&replies.pop().unwrap_or(redis::Value::Nil))?;
+            (redis::from_redis_value(&redis::Value::Bulk(replies))?, counts)
+        };
+        let count = |status: Status| counts.get(&format!("{:?}", status)).cloned().unwrap_or(0);
+
+        Ok(QueueStats {
+            queued: lengths[..ready.len()].iter().sum(),
+            running: lengths[ready.len()],
+            finished: count(Status::FINISHED),
+            failed: count(Status::FAILED),
+            lost: count(Status::LOST),
+            cancelled: count(Status::CANCELLED),
+            dead: lengths[ready.len() + 1],
+        })
+    }
+
+    /// Key of the hash counting jobs which got each terminal status, see `stats_estimate`
+    fn counts_key(&self) -> String {
+        format!("{}:counts", self.name)
+    }
+
+    /// Pause queue, workers stop picking up new jobs until resumed, running jobs are allowed to
+    /// finish
+    pub fn pause(&self) -> Result<(), Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        let _: () = conn.set(format!("{}:paused", self.name), 1)?;
+
+        Ok(())
+    }
+
+    /// Resume paused queue
+    pub fn resume(&self) -> Result<(), Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        let _: () = conn.del(format!("{}:paused", self.name))?;
+
+        Ok(())
+    }
+
+    /// Check if queue is paused
+    pub fn is_paused(&self) -> Result<bool, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        Ok(conn.exists(format!("{}:paused", self.name))?)
+    }
+
+    /// Enqueue new job
+    ///
+    /// `args` - job arguments
+    ///
+    /// `expire` - job expiration time in seconds, if hasn't started during this time it will be
+    /// removed
+    ///
+    /// Returns unique job identifier
+    #[deprecated(note = "use `enqueue_for`, which takes a `Duration`")]
+    pub fn enqueue(&self, args: Vec<String>, expire: usize) -> Result<String, Box<dyn Error>> {
+        self.enqueue_for(args, Duration::from_secs(expire as u64))
+    }
+
+    /// Enqueue new job
+    ///
+    /// `args` - job arguments
+    ///
+    /// `expire` - job expiration time, if hasn't started during this time it will be removed,
+    /// counted in milliseconds
+    ///
+    /// Nothing is sent in the background: the job key and its ready list entry are written in one
+    /// transaction, and the identifier is only returned once Redis has acknowledged it, so the
+    /// job is visible to workers by then. See `QueueOptions::replicas` to wait for replicas too.
+    ///
+    /// Returns unique job identifier
+    pub fn enqueue_for(&self,
+                       args: Vec<String>,
+                       expire: Duration)
+                       -> Result<String, Box<dyn Error>> {
+        let mut job = self.new_job(args)?;
+        let conn = self.conn()?;
+
+        self.push(&conn, &mut job, expire)?;
+
+        Ok(job.uuid)
+    }
+
+    /// Enqueue new job at the head of the ready list, so that it is the next one taken by a FIFO
+    /// worker, e.g. for an urgent one-off job
+    ///
+    /// `args` - job arguments
+    ///
+    /// `expire` - job expiration time, if hasn't started during this time it will be removed,
+    /// counted in milliseconds
+    ///
+    /// It is a coarse priority: jobs already enqueued keep their order, and jobs enqueued at the
+    /// front later are taken before this one. With several `QueueOptions::shards` the job only
+    /// leads its own shard, and `WorkOptions::lifo` workers take it last.
+    ///
+    /// Returns unique job identifier
+    pub fn enqueue_front(&self,
+                         args: Vec<String>,
+                         expire: Duration)
+                         -> Result<String, Box<dyn Error>> {
+        let mut job = self.new_job(args)?;
+        let conn = self.conn()?;
+
+        self.push_at(&conn, &mut job, expire, true)?;
+
+        Ok(job.uuid)
+    }
+
+    /// Enqueue new job once the queue has room for it, so that a producer faster than the workers
+    /// is throttled instead of growing the backlog without bound
+    ///
+    /// `args` - job arguments
+    ///
+    /// `expire` - job expiration time, if hasn't started during this time it will be removed,
+    /// counted in milliseconds
+    ///
+    /// `max_len` - number of jobs on the ready lists, of all kinds, below which the job is
+    /// enqueued
+    ///
+    /// `timeout` - longest time to wait for room, counted on `QueueOptions::clock`
+    ///
+    /// The ready lists are polled every 100 milliseconds. Checking the length and enqueueing
+    /// aren't atomic, so producers enqueueing at once may outgrow `max_len` by one job each.
+    ///
+    /// Returns unique job identifier, or `RjqError::QueueFull` if the queue has had no room
+    /// during `timeout`, nothing is written then
+    pub fn enqueue_blocking(&self,
+                            args: Vec<String>,
+                            expire: Duration,
+                            max_len: usize,
+                            timeout: Duration)
+                            -> Result<String, Box<dyn Error>> {
+        let mut job = self.new_job(args)?;
+        let conn = self.conn()?;
+
+        let clock = &*self.options.clock;
+        let deadline = clock.elapsed() + timeout;
+        loop {
+            let mut pipe = redis::pipe();
+            for key in self.ready_keys(&conn)? {
+                pipe.llen(key);
+            }
+            let lengths: Vec<usize> = pipe.query(&conn)?;
+            let len = lengths.iter().sum();
+            if len < max_len {
+                break;
+            }
+            let now = clock.elapsed();
+            if now >= deadline {
+                return Err(RjqError::QueueFull { len, max_len }.into());
+            }
+            clock.sleep(CLAIM_POLL.min(deadline.saturating_sub(now)));
+        }
+        self.push(&conn, &mut job, expire)?;
+
+        Ok(job.uuid)
+    }
+
+    /// Enqueue a large batch of jobs in the background, streaming their identifiers back as soon
+    /// as Redis has acknowledged them, so that the first jobs can be tracked before the last one
+    /// is written
+    ///
+    /// `batch` - arguments of every job
+    ///
+    /// `expire` - job expiration time, if hasn't started during this time it will be removed,
+    /// counted in milliseconds
+    ///
+    /// All arguments are checked before anything is written. Jobs are then written by a thread
+    /// of their own with a connection of its own, 100 jobs per pipelined transaction, and the
+    /// identifiers of a transaction are sent in enqueue order once it is acknowledged and
+    /// replicated as told by `QueueOptions::replicas`. A failed write is sent as its error
+    /// message and ends the stream, identifiers of its transaction aren't sent, although its jobs
+    /// are written if only replication has failed. Dropping the receiver stops writing after the
+    /// transaction in flight.
+    ///
+    /// Returns receiver of job identifiers, disconnected once the batch is written
+    pub fn enqueue_stream(&self,
+                          batch: Vec<Vec<String>>,
+                          expire: Duration)
+                          -> Result<Receiver<Result<String, String>>, Box<dyn Error>> {
+        let mut jobs = Vec::with_capacity(batch.len());
+        for args in batch {
+            jobs.push(self.new_job(args)?);
+        }
+
+        let queue = self.clone();
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            let conn = match connect_with(&queue.url,
+                                          queue.options.op_timeout,
+                                          queue.options.client_name.as_deref()) {
+                Ok(o) => o,
+                Err(e) => {
+                    let _ = tx.send(Err(e.to_string()));
+                    return;
+                }
+            };
+            for chunk in jobs.chunks_mut(STREAM_BATCH) {
+                let written = queue.write_batch(&conn, chunk, expire);
+                let sent = match written {
+                    Ok(()) => chunk.iter().all(|o| tx.send(Ok(o.uuid.clone())).is_ok()),
+                    Err(e) => {
+                        let _ = tx.send(Err(e.to_string()));
+                        false
+                    }
+                };
+                if !sent {
+                    return;
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+
+    /// Store queued `jobs` as `push` does, all in one transaction
+    fn write_batch(&self,
+                   conn: &impl Commands,
+                   jobs: &mut [Job],
+                   expire: Duration)
+                   -> Result<(), Box<dyn Error>> {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for job in jobs.iter_mut() {
+            self.detach_args(&mut pipe, job, expire);
+            let json = self.encode(job)?;
+            self.write_job(&mut pipe, job, json, expire, false);
+        }
+        let _: () = pipe.query(conn)?;
+
+        self.replicate(conn)
+    }
+
+    /// Enqueue new job with a human readable label, e.g. `send-welcome-email`, shown in logs and
+    /// callbacks next to the job identifier, jobs are still addressed by the identifier only
+    ///
+    /// `label` - job label
+    ///
+    /// `args` - job arguments
+    ///
+    /// `expire` - job expiration time, if hasn't started during this time it will be removed
+    ///
+    /// Returns unique job identifier
+    pub fn enqueue_labeled(&self,
+                           label: &str,
+                           args: Vec<String>,
+                           expire: Duration)
+                           -> Result<String, Box<dyn Error>> {
+        let mut job = self.new_job(args)?;
+        let conn = self.conn()?;
+
+        job.label = Some(label.to_string());
+        self.push(&conn, &mut job, expire)?;
+
+        Ok(job.uuid)
+    }
+
+    /// Enqueue new job with tags passed to the worker function of `work_ctx`, e.g. the tenant or
+    /// the source of the job
+    ///
+    /// `args` - job arguments
+    ///
+    /// `expire` - job expiration time, if hasn't started during this time it will be removed,
+    /// counted in milliseconds
+    ///
+    /// `tags` - job tags
+    ///
+    /// Returns unique job identifier
+    pub fn enqueue_tagged(&self,
+                          args: Vec<String>,
+                          expire: Duration,
+                          tags: Vec<String>)
+                          -> Result<String, Box<dyn Error>> {
+        let mut job = self.new_job(args)?;
+        let conn = self.conn()?;
+
+        job.tags = tags;
+        self.push(&conn, &mut job, expire)?;
+
+        Ok(job.uuid)
+    }
+
+    /// Enqueue new job expiring in `QueueOptions::expire`
+    ///
+    /// `args` - job arguments
+    ///
+    /// Returns unique job identifier
+    pub fn enqueue_default(&self, args: Vec<String>) -> Result<String, Box<dyn Error>> {
+        self.enqueue_for(args, self.options.expire)
+    }
+
+    /// Enqueue new job with typed payload
+    ///
+    /// `payload` - job payload, it is serialized to JSON and passed to the worker function as the
+    /// only argument
+    ///
+    /// `expire` - job expiration time, if hasn't started during this time it will be removed
+    ///
+    /// Returns unique job identifier, or `RjqError::Serialize` if the payload can't be serialized,
+    /// in which case nothing is written
+    pub fn enqueue_json<T: Serialize>(&self,
+                                      payload: &T,
+                                      expire: Duration)
+                                      -> Result<String, Box<dyn Error>> {
+        let json = serde_json::to_string(payload).map_err(RjqError::Serialize)?;
+
+        self.enqueue_for(vec![json], expire)
+    }
+
+    /// Enqueue new job with a JSON value as arguments
+    ///
+    /// `value` - job arguments, stored in the job as they are and passed to the worker function as
+    /// the only argument, see `work_value`
+    ///
+    /// `expire` - job expiration time, if hasn't started during this time it will be removed
+    ///
+    /// Returns unique job identifier
+    pub fn enqueue_value(&self,
+                         value: serde_json::Value,
+                         expire: Duration)
+                         -> Result<String, Box<dyn Error>> {
+        self.push_value(value.to_string(), expire)
+    }
+
+    /// Enqueue new job with named arguments, e.g. `{"user": "42", "template": "welcome"}`, so
+    /// that producer and worker can't disagree on the position of an argument
+    ///
+    /// `args` - job arguments by name, stored in the job as a JSON object and passed to the worker
+    /// function as a map, see `work_named`
+    ///
+    /// `expire` - job expiration, if hasn't started during this time it will be removed
+    ///
+    /// Returns unique job identifier
+    pub fn enqueue_named(&self,
+                         args: BTreeMap<String, String>,
+                         expire: Duration)
+                         -> Result<String, Box<dyn Error>> {
+        let value = serde_json::to_string(&args).map_err(RjqError::Serialize)?;
+
+        self.push_value(value, expire)
+    }
+
+    /// Enqueue new job with JSON text `value` as arguments, see `enqueue_value`
+    fn push_value(&self, value: String, expire: Duration) -> Result<String, Box<dyn Error>> {
+        self.validate(slice::from_ref(&value))?;
+        let conn = self.conn()?;
+
+        let mut job = Job::new(vec![]);
+        job.value = Some(value);
+        job.enqueued_at = Some(self.now_ms());
+        self.push(&conn, &mut job, expire)?;
+
+        Ok(job.uuid)
+    }
+
+    /// Enqueue new job, POSTing its outcome to `callback_url` once a worker has written its
+    /// terminal status, see `webhook` for the payload
+    ///
+    /// `args` - job arguments
+    ///
+    /// `expire` - job expiration time, if hasn't started during this time it will be removed
+    ///
+    /// `callback_url` - `http://` URL to notify
+    ///
+    /// Returns unique job identifier
+    #[cfg(feature = "http")]
+    pub fn enqueue_callback(&self,
+                            args: Vec<String>,
+                            expire: Duration,
+                            callback_url: &str)
+                            -> Result<String, Box<dyn Error>> {
+        let mut job = self.new_job(args)?;
+        let conn = self.conn()?;
+
+        job.callback = Some(callback_url.to_string());
+        self.push(&conn, &mut job, expire)?;
+
+        Ok(job.uuid)
+    }
+
+    /// Enqueue new job, running `on_done` once a worker in this process has completed it
+    ///
+    /// `args` - job arguments
+    ///
+    /// `expire` - job expiration, if hasn't started during this time it will be removed
+    ///
+    /// `on_done` - closure taking the terminal status and result, run once on the thread writing
+    /// them, e.g. the worker, so it should be quick
+    ///
+    /// The closure is kept in memory rather than in Redis, so it is only run by workers and
+    /// `cancel` calls of this process, there is no fallback across processes: use
+    /// `enqueue_await` for jobs completed elsewhere. The closure of a job which a worker of this
+    /// process hasn't claimed within `expire` is dropped without being run, and so is the closure
+    /// of a claimed job once its running status would have expired, e.g. if it was completed by
+    /// a worker of another process meanwhile.
+    ///
+    /// Returns unique job identifier
+    pub fn enqueue_local<F>(&self,
+                            args: Vec<String>,
+                            expire: Duration,
+                            on_done: F)
+                            -> Result<String, Box<dyn Error>>
+        where F: FnOnce(Status, Option<String>) + Send + 'static
+    {
+        let mut job = self.new_job(args)?;
+        let conn = self.conn()?;
+
+        // Registered first, so that a worker completing the job at once finds it
+        let key = format!("{}:{}", self.name, job.uuid);
+        register_local(&key, Box::new(on_done), expire);
+        if let Err(e) = self.push(&conn, &mut job, expire) {
+            completions().closures.remove(&key);
+            return Err(e);
+        }
+
+        Ok(job.uuid)
+    }
+
+    /// Enqueue new job and get notified once it is completed, without polling
+    ///
+    /// `args` - job arguments
+    ///
+    /// `expire` - job expiration time, if hasn't started during this time it will be removed
+    ///
+    /// The job completion channel is subscribed to before the job is enqueued, and the stored job
+    /// is checked on every message and every second, so an outcome written before any message
+    /// came is never missed. The subscription holds a connection until the job is completed.
+    ///
+    /// Returns unique job identifier and receiver of the terminal status, result and number of
+    /// attempts, the receiver is disconnected without a value if the job expires or is deleted
+    /// before it is completed
+    pub fn enqueue_await(&self,
+                         args: Vec<String>,
+                         expire: Duration)
+                         -> Result<(String, Receiver<Outcome>), Box<dyn Error>> {
+        let mut job = self.new_job(args)?;
+        let uuid = job.uuid.clone();
+        let mut subscription = subscribe(&self.url, &[self.done_channel(&uuid)])?;
+        {
+            let conn = self.conn()?;
+            self.push(&conn, &mut job, expire)?;
+        }
+
+        let (sender, receiver) = sync_channel(1);
+        let queue = self.clone();
+        let key = format!("{}:{}", self.name, uuid);
+        thread::spawn(move || {
+            subscription.adopt();
+            loop {
+                let job = queue.conn().and_then(|conn| {
+                    let json: Option<String> = conn.get(&key)?;
+                    Ok(json.map(|o| serde_json::from_str::<Job>(&o)).transpose()?)
+                });
+                match job {
+                    Ok(Some(o)) if o.status.is_terminal() => {
+                        let _ = sender.send(Outcome {
+                            status: o.status,
+                            result: o.result,
+                            attempts: o.attempts,
+                        });
+                        return;
+                    }
+                    Ok(Some(_)) => {}
+                    Ok(None) => return,
+                    Err(e) => {
+                        error!("job {} can't be awaited: {}", key, e);
+                        return;
+                    }
+                }
+                if let Err(e) = subscription.wait(Duration::from_secs(1)) {
+                    error!("job {} can't be awaited: {}", key, e);
+                    return;
+                }
+            }
+        });
+
+        Ok((uuid, receiver))
+    }
+
+    /// Enqueue new job and get notified whenever a worker starts it and once it is completed,
+    /// e.g. to measure how long jobs wait in the queue
+    ///
+    /// `args` - job arguments
+    ///
+    /// `expire` - job expiration time, if hasn't started during this time it will be removed
+    ///
+    /// Workers publish the start time in milliseconds to `{name}:started:{uuid}` as they mark the
+    /// job as running. The start and completion channels are subscribed to before the job is
+    /// enqueued, and the stored job is checked on every message and every second as
+    /// `enqueue_await` does, so no event is missed: a job completed before it was seen running is
+    /// reported as started first.
+    ///
+    /// Returns unique job identifier and receiver of the job events, the receiver is disconnected
+    /// after `JobEvent::Completed`, or without it if the job expires or is deleted before it is
+    /// completed
+    pub fn enqueue_watch(&self,
+                         args: Vec<String>,
+                         expire: Duration)
+                         -> Result<(String, Receiver<JobEvent>), Box<dyn Error>> {
+        let mut job = self.new_job(args)?;
+        let uuid = job.uuid.clone();
+        let channels = [self.started_channel(&uuid), self.done_channel(&uuid)];
+        let mut subscription = subscribe(&self.url, &channels)?;
+        {
+            let conn = self.conn()?;
+            self.push(&conn, &mut job, expire)?;
+        }
+
+        let (sender, receiver) = channel();
+        let queue = self.clone();
+        let key = format!("{}:{}", self.name, uuid);
+        thread::spawn(move || {
+            subscription.adopt();
+            let mut reported = None;
+            loop {
+                let job = queue.conn().and_then(|conn| {
+                    let json: Option<String> = conn.get(&key)?;
+                    Ok(json.map(|o| serde_json::from_str::<Job>(&o)).transpose()?)
+                });
+                let job = match job {
+                    Ok(Some(o)) => o,
+                    Ok(None) => return,
+                    Err(e) => {
+                        error!("job {} can't be watched: {}", key, e);
+                        return;
+                    }
+                };
+                // Every attempt has a start time of its own
+                if let Some(started_at) = job.started_at.filter(|&o| reported != Some(o)) {
+                    reported = Some(started_at);
+                    let started = JobEvent::Started {
+                        enqueued_at: job.enqueued_at.map(Duration::from_millis),
+                        started_at: Duration::from_millis(started_at),
+                    };
+                    if sender.send(started).is_err() {
+                        return;
+                    }
+                }
+                if job.status.is_terminal() {
+                    let _ = sender.send(JobEvent::Completed(Outcome {
+                        status: job.status,
+                        result: job.result,
+                        attempts: job.attempts,
+                    }));
+                    return;
+                }
+                if let Err(e) = subscription.wait(Duration::from_secs(1)) {
+                    error!("job {} can't be watched: {}", key, e);
+                    return;
+                }
+            }
+        });
+
+        Ok((uuid, receiver))
+    }
+
+    /// Enqueue new job of given kind, it is only processed by workers subscribed to the kind with
+    /// `WorkOptions::kinds`
+    ///
+    /// `kind` - job kind, e.g. `email`
+    ///
+    /// `args` - job arguments
+    ///
+    /// `expire` - job expiration time, if hasn't started during this time it will be removed
+    ///
+    /// Returns unique job identifier
+    pub fn enqueue_kind(&self,
+                        kind: &str,
+                        args: Vec<String>,
+                        expire: Duration)
+                        -> Result<String, Box<dyn Error>> {
+        let mut job = self.new_job(args)?;
+        let conn = self.conn()?;
+
+        job.kind = Some(kind.to_string());
+        let _: () = conn.sadd(format!("{}:kinds", self.name), kind)?;
+        self.push(&conn, &mut job, expire)?;
+
+        Ok(job.uuid)
+    }
+
+    /// Enqueue new job unless a job with the same arguments is already queued or running
+    ///
+    /// `args` - job arguments
+    ///
+    /// `expire` - job expiration time, if hasn't started during this time it will be removed
+    ///
+    /// The lock is checked and taken, and the job written, by one script, a lock which has
+    /// outlived its job is taken over at once. `expire` must be positive.
+    ///
+    /// Returns unique identifier of the new job, or of the pending one with the same arguments
+    pub fn enqueue_unique(&self,
+                          args: Vec<String>,
+                          expire: Duration)
+                          -> Result<String, Box<dyn Error>> {
+        if expire.is_zero() {
+            return Err("expiration of a unique job must be positive".into());
+        }
+        let mut job = self.new_job(args)?;
+        let lock = format!("{}:unique:{}", self.name, args_hash(&job.args)?);
+        job.unique = Some(lock.clone());
+        let mut pipe = redis::pipe();
+        self.detach_args(&mut pipe, &mut job, expire);
+        let json = self.encode(&job)?;
+
+        let conn = self.conn()?;
+        // Arguments list is written ahead of the job, and deleted if a pending job is returned
+        let _: () = pipe.query(&conn)?;
+        let uuid: String = Script::new(ENQUEUE_UNIQUE_SCRIPT).key(lock)
+            .key(self.ready_key(None, &job.uuid))
+            .key(format!("{}:{}", self.name, job.uuid))
+            .key(self.deadlines_key())
+            .key(self.args_key(&job.uuid))
+            .arg(&self.name)
+            .arg(&job.uuid)
+            .arg(json)
+            .arg(millis(expire))
+            .arg(if self.options.pin { "1" } else { "0" })
+            .arg(self.now_ms() + millis(expire) as u64)
+            .invoke(&conn)?;
+        if uuid == job.uuid {
+            self.replicate(&conn)?;
+        } else if job.args_list {
+            let _: () = conn.del(self.args_key(&job.uuid))?;
+        }
+
+        Ok(uuid)
+    }
+
+    /// Enqueue new job with given initial status
+    ///
+    /// `args` - job arguments
+    ///
+    /// `expire` - job expiration time
+    ///
+    /// `status` - initial job status, any status is valid: `QUEUED` behaves as `enqueue`, with any
+    /// other status the job is only stored and never picked up by workers, e.g. `RUNNING` for a job
+    /// claimed by an out-of-band process which is still visible to `status`
+    ///
+    /// Returns unique job identifier
+    pub fn enqueue_with_status(&self,
+                               args: Vec<String>,
+                               expire: Duration,
+                               status: Status)
+                               -> Result<String, Box<dyn Error>> {
+        let mut job = self.new_job(args)?;
+        let conn = self.conn()?;
+
+        job.status = status;
+        self.push(&conn, &mut job, expire)?;
+
+        Ok(job.uuid)
+    }
+
+    /// Enqueue new job to be started after a delay
+    ///
+    /// `args` - job arguments
+    ///
+    /// `expire` - job expiration time counted from the moment it is due, if hasn't started during
+    /// this time it will be removed
+    ///
+    /// `delay` - delay, the job is kept in the scheduled set with `QUEUED` status and moved to the
+    /// ready list by the first worker iteration after it is due
+    ///
+    /// Returns unique job identifier
+    pub fn enqueue_delayed(&self,
+                           args: Vec<String>,
+                           expire: Duration,
+                           delay: Duration)
+                           -> Result<String, Box<dyn Error>> {
+        let mut job = self.new_job(args)?;
+        let conn = self.conn()?;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        self.detach_args(&mut pipe, &mut job, delay + expire);
+        self.write_key(&mut pipe, &job, self.encode(&job)?, delay + expire);
+        pipe.zadd(format!("{}:scheduled", self.name),
+                  &job.uuid,
+                  self.now_ms() + delay.as_millis() as u64)
+            .ignore();
+        let _: () = pipe.query(&conn)?;
+        self.replicate(&conn)?;
+
+        Ok(job.uuid)
+    }
+
+    /// Enqueue new job to be started after a delay unless a job with the same arguments is
+    /// already queued, scheduled or running
+    ///
+    /// `args` - job arguments
+    ///
+    /// `expire` - job expiration time counted from the moment it is due, if hasn't started during
+    /// this time it will be removed
+    ///
+    /// `delay` - delay, see `enqueue_delayed`
+    ///
+    /// The uniqueness check and scheduling are done at once by a Lua script. Jobs share the
+    /// uniqueness lock with `enqueue_unique`.
+    ///
+    /// Returns unique identifier of the new job, or of the pending one with the same arguments
+    pub fn enqueue_unique_delayed(&self,
+                                  args: Vec<String>,
+                                  expire: Duration,
+                                  delay: Duration)
+                                  -> Result<String, Box<dyn Error>> {
+        let mut job = self.new_job(args)?;
+        let lock = format!("{}:unique:{}", self.name, args_hash(&job.args)?);
+        job.unique = Some(lock.clone());
+        let mut pipe = redis::pipe();
+        self.detach_args(&mut pipe, &mut job, delay + expire);
+        let json = self.encode(&job)?;
+
+        let conn = self.conn()?;
+        // Arguments list is written ahead of the job, and deleted if a pending job is returned
+        let _: () = pipe.query(&conn)?;
+        let uuid: String = Script::new(SCHEDULE_UNIQUE_SCRIPT).key(lock)
+            .key(format!("{}:scheduled", self.name))
+            .key(format!("{}:{}", self.name, job.uuid))
+            .key(self.deadlines_key())
+            .key(self.args_key(&job.uuid))
+            .arg(&self.name)
+            .arg(&job.uuid)
+            .arg(json)
+            .arg(millis(delay + expire))
+            .arg(self.now_ms() + delay.as_millis() as u64)
+            .arg(if self.options.pin { "1" } else { "0" })
+            .arg(self.now_ms() + millis(delay + expire) as u64)
+            .invoke(&conn)?;
+        if uuid == job.uuid {
+            self.replicate(&conn)?;
+        } else if job.args_list {
+            let _: () = conn.del(self.args_key(&job.uuid))?;
+        }
+
+        Ok(uuid)
+    }
+
+    /// Move scheduled jobs which are due to the ready list and enqueue due runs of recurring jobs,
+    /// workers do this on every iteration
+    ///
+    /// Returns identifiers of promoted jobs, each due job is returned by exactly one caller
+    pub fn promote(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        self.promote_due(&conn)
+    }
+
+    fn promote_due(&self, conn: &impl Commands) -> Result<Vec<String>, Box<dyn Error>> {
+        let script = Script::new(PROMOTE_SCRIPT);
+        let mut promoted = self.promote_recurring(conn)?;
+        loop {
+            let mut invocation = script.key(format!("{}:scheduled", self.name));
+            for key in self.ready_shards(None) {
+                invocation.key(key);
+            }
+            let uuids: Vec<String> = invocation.arg(self.now_ms())
+                .arg(PROMOTE_BATCH)
+                .invoke(conn)?;
+            let done = uuids.len() < PROMOTE_BATCH;
+            promoted.extend(uuids);
+            if done {
+                break;
+            }
+        }
+
+        Ok(promoted)
+    }
+
+    /// Recurring schedules hash key
+    fn recurring_key(&self) -> String {
+        format!("{}:recurring", self.name)
+    }
+
+    /// Schedule job to be enqueued every `interval`
+    ///
+    /// `args` - job arguments
+    ///
+    /// `expire` - expiration time of every run, if it hasn't started during this time it will be
+    /// removed
+    ///
+    /// `interval` - interval between runs, counted in milliseconds, the first run is due at once
+    ///
+    /// Runs are enqueued by the first iteration of a worker without kinds after they are due, see
+    /// `promote`. A run isn't enqueued while the previous one is still queued or running, e.g. if
+    /// it takes longer than the interval, so runs never overlap and the skipped run is due at the
+    /// next interval.
+    ///
+    /// Returns schedule identifier
+    pub fn schedule_recurring(&self,
+                              args: Vec<String>,
+                              expire: Duration,
+                              interval: Duration)
+                              -> Result<String, Box<dyn Error>> {
+        self.validate(&args)?;
+        let conn = self.conn()?;
+
+        let id = Uuid::new_v4().to_string();
+        let recurring = Recurring {
+            args,
+            expire_ms: millis(expire) as u64,
+            interval_ms: millis(interval) as u64,
+            next: self.now_ms(),
+            last: None,
+        };
+        let json = serde_json::to_string(&recurring).map_err(RjqError::Serialize)?;
+        let _: () = conn.hset(self.recurring_key(), &id, json)?;
+
+        Ok(id)
+    }
+
+    /// Stop recurring job, a run already enqueued is left as it is
+    ///
+    /// `id` - schedule identifier returned by `schedule_recurring`
+    ///
+    /// Returns false if there is no such schedule
+    pub fn unschedule_recurring(&self, id: &str) -> Result<bool, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        Ok(conn.hdel(self.recurring_key(), id)?)
+    }
+
+    /// Enqueue due runs of recurring jobs, each by exactly one caller
+    ///
+    /// Returns enqueued uuids
+    fn promote_recurring(&self, conn: &impl Commands) -> Result<Vec<String>, Box<dyn Error>> {
+        let key = self.recurring_key();
+        let schedules: HashMap<String, String> = conn.hgetall(&key)?;
+        let now = self.now_ms();
+        let mut promoted = Vec::new();
+        for (id, json) in schedules.iter() {
+            let due = serde_json::from_str::<Recurring>(json).is_ok_and(|o| o.next <= now);
+            if !due {
+                continue;
+            }
+
+            let enqueued = redis::transaction(conn, &[&key], |pipe| {
+                let json: Option<String> = conn.hget(&key, id)?;
+                let mut recurring = match json.and_then(|o| {
+                    serde_json::from_str::<Recurring>(&o).ok()
+                }) {
+                    Some(ref o) if o.next > now => return Ok(Some(None)),
+                    Some(o) => o,
+                    None => return Ok(Some(None)),
+                };
+                // Next run is due at the first interval after now, runs missed meanwhile are
+                // skipped rather than enqueued at once
+                let interval = recurring.interval_ms;
+                recurring.next += (now - recurring.next) / interval * interval + interval;
+
+                let running = match recurring.last {
+                    Some(ref last) => {
+                        let json: Option<String> = conn.get(format!("{}:{}", self.name, last))?;
+                        json.and_then(|o| serde_json::from_str::<Job>(&o).ok())
+                            .is_some_and(|o| o.status.is_pending())
+                    }
+                    None => false,
+                };
+                let failed = |e: RjqError| {
+                    redis::RedisError::from((redis::ErrorKind::TypeError,
+                                             "job serialization failed",
+                                             e.to_string()))
+                };
+                let mut job = Job::new(recurring.args.clone());
+                job.enqueued_at = Some(self.now_ms());
+                if !running {
+                    recurring.last = Some(job.uuid.clone());
+                    let expire = Duration::from_millis(recurring.expire_ms);
+                    self.detach_args(pipe, &mut job, expire);
+                    self.write_job(pipe, &job, self.encode(&job).map_err(failed)?, expire, false);
+                }
+                let json = serde_json::to_string(&recurring).map_err(RjqError::Serialize)
+                    .map_err(failed)?;
+                pipe.hset(&key, id, json).ignore();
+                let done: Option<()> = pipe.query(conn)?;
+                Ok(done.map(|_| if running { None } else { Some(job.uuid) }))
+            })?;
+            promoted.extend(enqueued);
+        }
+
+        Ok(promoted)
+    }
+
+    /// Check size of job arguments against `QueueOptions::max_payload` and the arguments with
+    /// `QueueOptions::validator`, before anything is written
+    fn validate(&self, args: &[String]) -> Result<(), RjqError> {
+        let size = args.iter().map(String::len).sum();
+        if size > self.options.max_payload {
+            return Err(RjqError::PayloadTooLarge {
+                size,
+                limit: self.options.max_payload,
+            });
+        }
+
+        match self.options.validator {
+            Some(ref o) => o.validate(args).map_err(RjqError::Invalid),
+            None => Ok(()),
+        }
+    }
+
+    /// New job with `args` checked by `validate`
+    fn new_job(&self, args: Vec<String>) -> Result<Job, RjqError> {
+        self.validate(&args)?;
+
+        let mut job = Job::new(args);
+        job.enqueued_at = Some(self.now_ms());
+
+        Ok(job)
+    }
+
+    /// Store job, and put it to the end of the ready list if it is queued
+    ///
+    /// Both writes are done in one transaction, so a listed job always has its key
+    fn push(&self,
+            conn: &impl Commands,
+            job: &mut Job,
+            expire: Duration)
+            -> Result<(), Box<dyn Error>> {
+        self.push_at(conn, job, expire, false)
+    }
+
+    /// Store job as `push` does, putting it to the head of the ready list instead if `front`
+    fn push_at(&self,
+               conn: &impl Commands,
+               job: &mut Job,
+               expire: Duration,
+               front: bool)
+               -> Result<(), Box<dyn Error>> {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        self.detach_args(&mut pipe, job, expire);
+        let json = self.encode(job)?;
+        self.write_job(&mut pipe, job, json, expire, front);
+        let _: () = pipe.query(conn)?;
+
+        self.replicate(conn)
+    }
+
+    /// Write `job` encoded as `json` with `pipe`, expiring in `expire` or pinned, and push it to
+    /// its ready list, to the head if `front`, if it is queued
+    fn write_job(&self,
+                 pipe: &mut redis::Pipeline,
+                 job: &Job,
+                 json: String,
+                 expire: Duration,
+                 front: bool) {
+        self.write_key(pipe, job, json, expire);
+        if job.status == Status::QUEUED {
+            let ready_key = self.ready_key(job.kind.as_deref(), &job.uuid);
+            self.push_ready(pipe, &ready_key, &job.uuid, front);
+        }
+    }
+
+    /// Write `job` encoded as `json` with `pipe`, expiring in `expire`, or pinned with a deadline
+    /// in `expire` if it is queued
+    fn write_key(&self, pipe: &mut redis::Pipeline, job: &Job, json: String, expire: Duration) {
+        let key = format!("{}:{}", self.name, job.uuid);
+        if self.options.pin && job.status == Status::QUEUED {
+            pipe.set(&key, json)
+                .ignore()
+                .zadd(self.deadlines_key(), &job.uuid, self.now_ms() + millis(expire) as u64)
+                .ignore();
+            if job.args_list {
+                pipe.persist(self.args_key(&job.uuid)).ignore();
+            }
+        } else {
+            pipe.cmd("PSETEX").arg(key).arg(millis(expire)).arg(json).ignore();
+        }
+    }
+
+    /// Add push of job `uuid` to `ready_key` to `pipe`, to the front if `front`, unless it is
+    /// listed already, see `QueueOptions::dedupe`
+    fn push_ready(&self, pipe: &mut redis::Pipeline, ready_key: &str, uuid: &str, front: bool) {
+        let push = if front { "LPUSH" } else { "RPUSH" };
+        if self.options.dedupe {
+            pipe.cmd("EVAL").arg(PUSH_ONCE_SCRIPT).arg(1).arg(ready_key).arg(uuid).arg(push);
+        } else {
+            pipe.cmd(push).arg(ready_key).arg(uuid);
+        }
+        pipe.ignore();
+    }
+
+    /// Key of the deadlines of pinned jobs, see `QueueOptions::pin`
+    fn deadlines_key(&self) -> String {
+        format!("{}:deadlines", self.name)
+    }
+
+    /// Delete pinned jobs past their deadline, see `QueueOptions::pin`
+    ///
+    /// Returns number of deleted jobs
+    fn expire_pinned(&self, conn: &impl Commands) -> Result<usize, Box<dyn Error>> {
+        Ok(Script::new(EXPIRE_PINNED_SCRIPT).key(self.deadlines_key())
+            .arg(self.now_ms())
+            .arg(&self.name)
+            .invoke(conn)?)
+    }
+
+    /// Key of the arguments list of job `uuid`, see `QueueOptions::args_list`
+    fn args_key(&self, uuid: &str) -> String {
+        format!("{}:{}:args", self.name, uuid)
+    }
+
+    /// Key of the raw result of job `uuid`, see `work_bytes`
+    fn result_key(&self, uuid: &str) -> String {
+        format!("{}:{}:result", self.name, uuid)
+    }
+
+    /// Move job arguments to its arguments list written by `pipe` with `ttl`, if the queue
+    /// stores them apart, jobs without arguments are kept as they are
+    fn detach_args(&self, pipe: &mut redis::Pipeline, job: &mut Job, ttl: Duration) {
+        if !self.options.args_list || job.args.is_empty() {
+            return;
+        }
+
+        let key = self.args_key(&job.uuid);
+        pipe.rpush(&key, mem::take(&mut job.args))
+            .ignore()
+            .pexpire(key, millis(ttl))
+            .ignore();
+        job.args_list = true;
+    }
+
+    /// Get arguments of `job`, reading its arguments list and extending it to `ttl` if they are
+    /// stored apart
+    fn attached_args(&self,
+                     conn: &impl Commands,
+                     job: &Job,
+                     ttl: Duration)
+                     -> Result<Vec<String>, Box<dyn Error>> {
+        if let Some(ref value) = job.value {
+            return Ok(vec![value.clone()]);
+        }
+        if !job.args_list {
+            return Ok(job.args.clone());
+        }
+
+        let key = self.args_key(&job.uuid);
+        let (args,): (Vec<String>,) = redis::pipe()
+            .lrange(&key, 0, -1)
+            .pexpire(&key, millis(ttl))
+            .ignore()
+            .query(conn)?;
+
+        Ok(args)
+    }
+
+    /// Get arguments of `job` as `attached_args` does, leaving the time to live of its arguments
+    /// list as it is
+    fn peek_args(&self, conn: &impl Commands, job: &Job) -> Result<Vec<String>, Box<dyn Error>> {
+        match job.value {
+            Some(ref value) => Ok(vec![value.clone()]),
+            None if job.args_list => Ok(conn.lrange(self.args_key(&job.uuid), 0, -1)?),
+            None => Ok(job.args.clone()),
+        }
+    }
+
+    /// Wait for `QueueOptions::replicas` to acknowledge the writes made so far on `conn`
+    fn replicate(&self, conn: &impl Commands) -> Result<(), Box<dyn Error>> {
+        if self.options.replicas == 0 {
+            return Ok(());
+        }
+
+        let acked: usize = redis::cmd("WAIT")
+            .arg(self.options.replicas)
+            .arg(self.options.replicas_timeout.as_millis() as u64)
+            .query(conn)?;
+        if acked < self.options.replicas {
+            return Err(RjqError::Replication {
+                    acked,
+                    replicas: self.options.replicas,
+                }
+                .into());
+        }
+
+        Ok(())
+    }
+
+    /// Put job deferred by the worker function to the scheduled set, due in `delay`, so that it is
+    /// run again once it is promoted, see `RjqError::Defer`
+    ///
+    /// The attempt isn't counted, the job expires `expire` after it is due unless it is started
+    fn defer(&self,
+             conn: &impl Commands,
+             key: &str,
+             job: &mut Job,
+             delay: Duration,
+             expire: Duration)
+             -> Result<(), Box<dyn Error>> {
+        job.status = Status::QUEUED;
+        job.attempts = job.attempts.saturating_sub(1);
+        job.owner = None;
+        let ttl = millis(delay + expire);
+
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .cmd("PSETEX")
+            .arg(key)
+            .arg(ttl)
+            .arg(self.encode(job)?)
+            .ignore()
+            .zadd(format!("{}:scheduled", self.name),
+                  &job.uuid,
+                  self.now_ms() + millis(delay) as u64)
+            .ignore()
+            .lrem(self.processing_key(), 1, &job.uuid)
+            .ignore();
+        if let Some(ref lock) = job.unique {
+            pipe.pexpire(lock, ttl).ignore();
+        }
+        if job.args_list {
+            pipe.pexpire(self.args_key(&job.uuid), ttl).ignore();
+        }
+        let _: () = pipe.query(conn)?;
+
+        Ok(())
+    }
+
+    /// Put job which can't be processed to the dead letter list, its key is kept as is until it
+    /// expires
+    fn bury(&self, conn: &impl Commands, uuid: &str) -> Result<(), Box<dyn Error>> {
+        let _: () = conn.rpush(format!("{}:dead", self.name), uuid)?;
+
+        Ok(())
+    }
+
+    /// Get identifiers of dead jobs, which workers couldn't parse or ran out of attempts, see
+    /// `LostPolicy::Requeue`, in order they were found
+    pub fn dead(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        Ok(conn.lrange(format!("{}:dead", self.name), 0, -1)?)
+    }
+
+    /// Get number of dead jobs, e.g. for monitoring, see `dead`
+    pub fn dead_len(&self) -> Result<usize, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        Ok(conn.llen(format!("{}:dead", self.name))?)
+    }
+
+    /// Delete dead jobs along with their stored payloads, once they have been reviewed
+    ///
+    /// The jobs listed when called are deleted, jobs buried meanwhile by workers are kept for the
+    /// next review.
+    ///
+    /// Returns number of deleted jobs
+    pub fn flush_dead(&self) -> Result<usize, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        let dead = format!("{}:dead", self.name);
+        let uuids: Vec<String> = conn.lrange(&dead, 0, -1)?;
+        if uuids.is_empty() {
+            return Ok(0);
+        }
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for uuid in uuids.iter() {
+            pipe.del(format!("{}:{}", self.name, uuid))
+                .ignore()
+                .del(self.args_key(uuid))
+                .ignore()
+                .del(self.result_key(uuid))
+                .ignore();
+        }
+        let _: () = pipe.ltrim(dead, uuids.len() as isize, -1).ignore().query(&conn)?;
+
+        Ok(uuids.len())
+    }
+
+    /// Channel the terminal status of job `uuid` is published to
+    fn done_channel(&self, uuid: &str) -> String {
+        format!("{}:done:{}", self.name, uuid)
+    }
+
+    /// Channel the start time of job `uuid` is published to by `lua_start`
+    fn started_channel(&self, uuid: &str) -> String {
+        format!("{}:started:{}", self.name, uuid)
+    }
+
+    /// Key of the list of most recent run durations in milliseconds, newest first
+    fn durations_key(&self) -> String {
+        format!("{}:durations", self.name)
+    }
+
+    /// Get run duration percentiles of the last 1000 jobs workers have completed with any
+    /// terminal status, timed from claim to completion
+    pub fn latency_percentiles(&self) -> Result<LatencyStats, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        let mut durations: Vec<u64> = conn.lrange(self.durations_key(), 0, -1)?;
+        durations.sort_unstable();
+        // Nearest rank, the smallest duration at least `p` percent of durations don't exceed
+        let percentile = |p: usize| {
+            let rank = (durations.len() * p).div_ceil(100).max(1);
+            durations.get(rank - 1).map(|&o| Duration::from_millis(o)).unwrap_or_default()
+        };
+
+        Ok(LatencyStats {
+            count: durations.len(),
+            p50: percentile(50),
+            p95: percentile(95),
+            p99: percentile(99),
+        })
+    }
+
+    /// Processing list key, holding jobs claimed by workers until they are completed
+    fn processing_key(&self) -> String {
+        format!("{}:processing", self.name)
+    }
+
+    /// Claim next job from `ready_keys` for `owner`, running for at most `ttl`, waiting for one at
+    /// most `wait`, forever if `wait` is 0, the newest job first if `lifo`
+    fn claim(&self,
+             conn: &impl Commands,
+             ready_keys: &[String],
+             owner: &str,
+             ttl: Duration,
+             wait: Duration,
+             lifo: bool)
+             -> Result<Option<Claimed>, Box<dyn Error>> {
+        let sources = [(self, ready_keys.to_vec())];
+        let claimed = self.claim_first(conn, &sources, owner, ttl, wait, lifo, false)?;
+
+        Ok(claimed.map(|(_, o)| o))
+    }
+
+    /// Block until one of `keys` has a job, at most `wait`, forever if `wait` is 0, and pop its
+    /// identifier from the front, the back if `lifo`, pushing it to `processing[i]` if given for
+    /// `keys[i]`
+    ///
+    /// A single list is moved from with `BLMOVE`, or `BRPOPLPUSH` for `lifo` on Redis older than
+    /// 6.2, so that the identifier is on the processing list as soon as it is popped. Several lists
+    /// are popped from in the given order with `BLMPOP` on Redis 7.0 or later, `BLPOP` or `BRPOP`
+    /// before, and the identifier is pushed right after: a worker dying in between leaves the job
+    /// off every list until it expires.
+    ///
+    /// Returns index of the list the job was popped from along with its uuid, `None` if `keys`
+    /// stayed empty during `wait`
+    fn block_pop(&self,
+                 conn: &impl Commands,
+                 keys: &[String],
+                 processing: Option<&[String]>,
+                 wait: Duration,
+                 lifo: bool)
+                 -> Result<Option<(usize, String)>, Box<dyn Error>> {
+        let version = self.version(conn)?;
+        // Fractional timeouts are taken since Redis 6.0, 0 blocks forever
+        let ms = if wait.is_zero() { 0 } else { millis(wait) };
+        let timeout = if version >= (6, 0, 0) {
+            format!("{}.{:03}", ms / 1000, ms % 1000)
+        } else {
+            ms.div_ceil(1000).to_string()
+        };
+        let side = if lifo { "RIGHT" } else { "LEFT" };
+
+        if let (Some(processing), [key]) = (processing, keys) {
+            let moved: Option<String> = if version >= (6, 2, 0) {
+                redis::cmd("BLMOVE").arg(key)
+                    .arg(&processing[0])
+                    .arg(side)
+                    .arg("RIGHT")
+                    .arg(&timeout)
+                    .query(conn)?
+            } else if lifo {
+                redis::cmd("BRPOPLPUSH").arg(key).arg(&processing[0]).arg(&timeout).query(conn)?
+            } else {
+                None
+            };
+            if version >= (6, 2, 0) || lifo {
+                return Ok(moved.map(|o| (0, o)));
+            }
+        }
+
+        let popped: Option<(String, String)> = if version >= (7, 0, 0) {
+            let mut cmd = redis::cmd("BLMPOP");
+            cmd.arg(&timeout).arg(keys.len());
+            for key in keys {
+                cmd.arg(key);
+            }
+            let popped: Option<(String, Vec<String>)> = cmd.arg(side).query(conn)?;
+            popped.and_then(|(key, mut uuids)| uuids.pop().map(|o| (key, o)))
+        } else {
+            let mut cmd = redis::cmd(if lifo { "BRPOP" } else { "BLPOP" });
+            for key in keys {
+                cmd.arg(key);
+            }
+            cmd.arg(&timeout).query(conn)?
+        };
+        let (key, uuid) = match popped {
+            Some(o) => o,
+            None => return Ok(None),
+        };
+        let i = keys.iter().position(|o| *o == key).unwrap_or(0);
+        if let Some(processing) = processing {
+            let _: () = conn.rpush(&processing[i], &uuid)?;
+        }
+
+        Ok(Some((i, uuid)))
+    }
+
+    /// Ready lists of `sources` in pop order, with the index of the source and the processing
+    /// list of each
+    fn source_keys(sources: &[(&Queue, Vec<String>)]) -> (Vec<String>, Vec<usize>, Vec<String>) {
+        let mut keys = Vec::new();
+        let mut owners = Vec::new();
+        let mut processing = Vec::new();
+        for (i, &(queue, ref ready_keys)) in sources.iter().enumerate() {
+            for key in ready_keys {
+                keys.push(key.clone());
+                owners.push(i);
+                processing.push(queue.processing_key());
+            }
+        }
+
+        (keys, owners, processing)
+    }
+
+    /// Pop a job as `claim_first` does for a dry run, without marking it as running or putting
+    /// it to the processing list, see `WorkOptions::dry_run`
+    ///
+    /// Identifiers of expired jobs are dropped on the way as `CLAIM_SCRIPT` does, so that a list
+    /// of them can't be gone round forever, the job is returned as claimed if it is queued
+    fn peek(&self,
+            conn: &impl Commands,
+            sources: &[(&Queue, Vec<String>)],
+            wait: Duration,
+            lifo: bool)
+            -> Result<Option<(usize, Claimed)>, Box<dyn Error>> {
+        let (keys, owners, _) = Queue::source_keys(sources);
+        let inspect = |i: usize, uuid: String| -> Result<Option<(usize, Claimed)>, Box<dyn Error>> {
+            let queue = sources[owners[i]].0;
+            let json: Option<String> = conn.get(format!("{}:{}", queue.name, uuid))?;
+            let json = match json {
+                Some(o) => o,
+                None => return Ok(None),
+            };
+            let queued = serde_json::from_str::<Job>(&json)
+                .is_ok_and(|o| o.status == Status::QUEUED);
+            Ok(Some((owners[i], (keys[i].clone(), uuid, json, queued))))
+        };
+
+        let clock = &*self.options.clock;
+        let deadline = clock.elapsed() + wait;
+        loop {
+            for (i, key) in keys.iter().enumerate() {
+                let popped: Option<String> = if lifo {
+                    conn.rpop(key)?
+                } else {
+                    conn.lpop(key)?
+                };
+                if let Some(uuid) = popped {
+                    if let Some(o) = inspect(i, uuid)? {
+                        return Ok(Some(o));
+                    }
+                }
+            }
+            let left = deadline.saturating_sub(clock.elapsed());
+            if !wait.is_zero() && left.is_zero() {
+                return Ok(None);
+            }
+            match self.block_pop(conn, &keys, None, left, lifo)? {
+                Some((i, uuid)) => {
+                    if let Some(o) = inspect(i, uuid)? {
+                        return Ok(Some(o));
+                    }
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Claim a job as `claim` does from the first of `sources` which has one, each queue with its
+    /// ready lists, so that later queues are only claimed from while earlier ones are empty,
+    /// expired jobs are returned rather than dropped if `expired`
+    ///
+    /// While the ready lists are empty the worker blocks on them, see `block_pop`, and starts the
+    /// popped job with `START_SCRIPT`.
+    ///
+    /// Returns index of the source along with the claimed job
+    #[allow(clippy::too_many_arguments)]
+    fn claim_first(&self,
+                   conn: &impl Commands,
+                   sources: &[(&Queue, Vec<String>)],
+                   owner: &str,
+                   ttl: Duration,
+                   wait: Duration,
+                   lifo: bool,
+                   expired: bool)
+                   -> Result<Option<(usize, Claimed)>, Box<dyn Error>> {
+        let script = Script::new(CLAIM_SCRIPT);
+        let (keys, owners, processing) = Queue::source_keys(sources);
+        let clock = &*self.options.clock;
+        let deadline = clock.elapsed() + wait;
+        loop {
+            for (i, &(queue, ref ready_keys)) in sources.iter().enumerate() {
+                let mut invocation = script.prepare_invoke();
+                for key in ready_keys {
+                    invocation.key(key);
+                }
+                let claimed: Option<Claimed> = invocation.key(queue.processing_key())
+                    .arg(&queue.name)
+                    .arg(queue.now_ms())
+                    .arg(owner)
+                    .arg(millis(ttl))
+                    .arg(pop_command(lifo))
+                    .arg(if expired { "1" } else { "0" })
+                    .invoke(conn)?;
+                if let Some(o) = claimed {
+                    return Ok(Some((i, o)));
+                }
+            }
+            let left = deadline.saturating_sub(clock.elapsed());
+            if !wait.is_zero() && left.is_zero() {
+                return Ok(None);
+            }
+            let (i, uuid) = match self.block_pop(conn, &keys, Some(&processing), left, lifo)? {
+                Some(o) => o,
+                None => return Ok(None),
+            };
+            let source = owners[i];
+            match sources[source].0.start(conn, &uuid, owner, ttl)? {
+                Some((json, started)) => {
+                    return Ok(Some((source, (keys[i].clone(), uuid, json, started))));
+                }
+                None if expired => {
+                    return Ok(Some((source, (keys[i].clone(), uuid, String::new(), false))));
+                }
+                // An expired job is dropped, the lists may have more
+                None => {}
+            }
+        }
+    }
+
+    /// Move up to `count` jobs from `ready_keys` to the processing list, waiting for some at most
+    /// `wait`, forever if `wait` is 0
+    ///
+    /// Returns ready list and uuid of prefetched jobs
+    fn prefetch(&self,
+                conn: &impl Commands,
+                ready_keys: &[String],
+                count: usize,
+                wait: Duration,
+                lifo: bool)
+                -> Result<Vec<(String, String)>, Box<dyn Error>> {
+        let script = Script::new(PREFETCH_SCRIPT);
+        let fetch = |count: usize| -> Result<Vec<(String, String)>, Box<dyn Error>> {
+            let mut invocation = script.prepare_invoke();
+            for key in ready_keys {
+                invocation.key(key);
+            }
+            let moved: Vec<String> = invocation.key(self.processing_key())
+                .arg(count)
+                .arg(pop_command(lifo))
+                .invoke(conn)?;
+            Ok(moved.chunks(2).map(|o| (o[0].clone(), o[1].clone())).collect())
+        };
+
+        let moved = fetch(count)?;
+        if !moved.is_empty() {
+            return Ok(moved);
+        }
+        let processing = vec![self.processing_key(); ready_keys.len()];
+        let (i, uuid) = match self.block_pop(conn, ready_keys, Some(&processing), wait, lifo)? {
+            Some(o) => o,
+            None => return Ok(moved),
+        };
+        let mut moved = vec![(ready_keys[i].clone(), uuid)];
+        if count > 1 {
+            moved.extend(fetch(count - 1)?);
+        }
+
+        Ok(moved)
+    }
+
+    /// Start prefetched job `uuid` for `owner`, running for at most `ttl`
+    ///
+    /// Returns job json and whether the job was started, see `START_SCRIPT`
+    fn start(&self,
+             conn: &impl Commands,
+             uuid: &str,
+             owner: &str,
+             ttl: Duration)
+             -> Result<Option<(String, bool)>, Box<dyn Error>> {
+        Ok(Script::new(START_SCRIPT).key(format!("{}:{}", self.name, uuid))
+            .key(self.processing_key())
+            .arg(uuid)
+            .arg(self.now_ms())
+            .arg(owner)
+            .arg(millis(ttl))
+            .invoke(conn)?)
+    }
+
+    /// Deal with job `uuid` found expired on `ready_key` by a worker as told by `policy`
+    fn expired(&self,
+               ready_key: &str,
+               uuid: &str,
+               policy: ExpiredPolicy)
+               -> Result<(), Box<dyn Error>> {
+        match policy {
+            ExpiredPolicy::Skip => {}
+            ExpiredPolicy::Report => {
+                warn!("job {} has expired on {} before a worker took it", uuid, ready_key)
+            }
+            ExpiredPolicy::Strict => {
+                self.compact()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Put prefetched jobs which haven't been started back to the end of their ready lists they
+    /// were popped from, the back if `lifo`
+    fn release(&self,
+               conn: &impl Commands,
+               prefetched: Vec<(String, String)>,
+               lifo: bool)
+               -> Result<(), Box<dyn Error>> {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for (ready_key, uuid) in prefetched.into_iter().rev() {
+            pipe.lrem(self.processing_key(), 1, &uuid).ignore();
+            self.push_ready(&mut pipe, &ready_key, &uuid, !lifo);
+        }
+        let _: () = pipe.query(conn)?;
+
+        Ok(())
+    }
+
+    /// Put queued jobs left on the processing list back to the ready list, e.g. jobs prefetched
+    /// by a worker which died
+    ///
+    /// Jobs prefetched by live workers are put back as well, they are still processed only once.
+    /// Running jobs are left as they are.
+    ///
+    /// Returns number of jobs put back
+    pub fn recover(&self) -> Result<usize, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        let processing: Vec<String> = conn.lrange(self.processing_key(), 0, -1)?;
+        let mut recovered = 0;
+        for uuid in processing.iter() {
+            let key = format!("{}:{}", self.name, uuid);
+            let requeued = redis::transaction(&conn, &[&key], |pipe| {
+                let json: Option<String> = conn.get(&key)?;
+                let job = json.and_then(|o| serde_json::from_str::<Job>(&o).ok());
+                match job {
+                    Some(ref o) if o.status == Status::QUEUED => {
+                        let ready_key = self.ready_key(o.kind.as_deref(), uuid);
+                        pipe.lrem(self.processing_key(), 1, uuid).ignore();
+                        self.push_ready(pipe, &ready_key, uuid, false);
+                    }
+                    Some(_) => return Ok(Some(false)),
+                    None => {
+                        pipe.lrem(self.processing_key(), 1, uuid).ignore();
+                    }
+                }
+                let done: Option<()> = pipe.query(&conn)?;
+                Ok(done.map(|_| job.is_some()))
+            })?;
+            if requeued {
+                recovered += 1;
+            }
+        }
+
+        Ok(recovered)
+    }
+
+    /// Store job with its terminal status, remove it from the processing list and release its
+    /// uniqueness lock
+    fn finish(&self,
+              conn: &impl Commands,
+              key: &str,
+              job: &Job,
+              expire: Duration)
+              -> Result<(), Box<dyn Error>> {
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .cmd("PSETEX")
+            .arg(key)
+            .arg(millis(expire))
+            .arg(self.encode(job)?)
+            .ignore()
+            .lrem(self.processing_key(), 1, &job.uuid)
+            .ignore();
+        if let Some(ref lock) = job.unique {
+            pipe.del(lock).ignore();
+        }
+        if job.args_list {
+            pipe.pexpire(self.args_key(&job.uuid), millis(expire)).ignore();
+        }
+        if let Some(ref raw) = job.raw_result {
+            pipe.cmd("PSETEX")
+                .arg(self.result_key(&job.uuid))
+                .arg(millis(expire))
+                .arg(&raw[..])
+                .ignore();
+        }
+        pipe.publish(self.done_channel(&job.uuid), format!("{:?}", job.status))
+            .ignore()
+            .hincr(self.counts_key(), format!("{:?}", job.status), 1)
+            .ignore();
+        if let Some(started_at) = job.started_at {
+            let durations = self.durations_key();
+            pipe.lpush(&durations, self.now_ms().saturating_sub(started_at))
+                .ignore()
+                .ltrim(durations, 0, LATENCY_WINDOW as isize - 1)
+                .ignore();
+        }
+        let _: () = pipe.query(conn)?;
+
+        #[cfg(feature = "http")]
+        if let Some(ref url) = job.callback {
+            webhook::notify(url, job);
+        }
+        complete_local(key, job.status.clone(), job.result.clone());
+
+        Ok(())
+    }
+
+    /// Pop next queued job and mark it as running, for custom processing loops
+    ///
+    /// `wait` - timeout in seconds to block for a job
+    ///
+    /// Returns reserved job, `None` if no job was available during `wait` seconds
+    #[deprecated(note = "use `reserve_for`, which takes a `Duration`")]
+    pub fn reserve(&self, wait: usize) -> Result<Option<ReservedJob>, Box<dyn Error>> {
+        self.reserve_for(Duration::from_secs(wait as u64))
+    }
+
+    /// Pop next queued job and mark it as running, for custom processing loops
+    ///
+    /// `wait` - timeout to block for a job
+    ///
+    /// Running status and the terminal status written by `ReservedJob` expire as in `work_with`
+    /// with `work_options`. Cancelled jobs are skipped, the queue being paused counts as empty.
+    ///
+    /// The connection is taken as `QueueOptions::connections` tells and kept by the reserved job.
+    /// It blocks for a job at most half of `QueueOptions::op_timeout` at once, so that a long
+    /// `wait` doesn't time out.
+    ///
+    /// Returns reserved job, `None` if no job was available during `wait`
+    pub fn reserve_for(&self, wait: Duration) -> Result<Option<ReservedJob>, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        let paused: bool = conn.exists(format!("{}:paused", self.name))?;
+        if paused {
+            self.options.clock.sleep(wait);
+            return Ok(None);
+        }
+
+        self.promote_due(&conn)?;
+
+        let options = self.work_options();
+        let clock = &*self.options.clock;
+        let ready_keys = rotated(&[self.ready_shards(None)], self.now_ms() as usize, false);
+        let owner = Uuid::new_v4().to_string();
+        let ttl = options.timeout + options.expire;
+        let slice = self.options.op_timeout.map(|o| o / 2);
+        let deadline = clock.elapsed() + wait;
+        // Time blocked on empty claims, so that the wait ends on a clock which doesn't move too
+        let mut blocked = Duration::ZERO;
+        loop {
+            let left = deadline.saturating_sub(clock.elapsed()).min(wait - blocked);
+            let block = slice.map_or(left, |o| left.min(o)).max(Duration::from_millis(1));
+            let claimed = self.claim(&conn, &ready_keys, &owner, ttl, block, false)?;
+            let (_, uuid, json, claimed) = match claimed {
+                Some(o) => o,
+                None => {
+                    blocked += block.min(wait - blocked);
+                    if blocked < wait && clock.elapsed() < deadline {
+                        continue;
+                    }
+                    return Ok(None);
+                }
+            };
+
+            let job: Job = match serde_json::from_str(&json) {
+                Ok(o) => o,
+                Err(e) => {
+                    error!("job {} is corrupt, moved to dead letters: {}", uuid, e);
+                    self.bury(&conn, &uuid)?;
+                    continue;
+                }
+            };
+            if !claimed {
+                continue;
+            }
+
+            let key = format!("{}:{}", self.name, uuid);
+            claim_local(&key, ttl);
+            let args = self.attached_args(&conn, &job, ttl)?;
+            return Ok(Some(ReservedJob {
+                queue: self.clone(),
+                conn,
+                key,
+                job,
+                args,
+                expire: options.expire,
+                done: false,
+            }));
+        }
+    }
+
+    /// Iterate over jobs as they become available, reserving them one by one as `reserve_for`, for
+    /// custom processing loops, e.g. `for job in queue.iter(wait) { job?.complete(result)?; }`
+    ///
+    /// `wait` - timeout to block for a job on every step
+    ///
+    /// The iterator ends once no job was available during `wait`. A failed reservation is
+    /// yielded as an error and ends it as well, so that a loop over it doesn't spin while Redis is
+    /// unreachable, call `iter` again to go on. A job dropped without being completed or failed is
+    /// put back and yielded again next.
+    pub fn iter(&self, wait: Duration) -> Reservations<'_> {
+        Reservations {
+            queue: self,
+            wait,
+            failed: false,
+        }
     }
 
     /// Get job status
     ///
-    /// `uuid` - unique job identifier
+    /// `uuid` - unique job identifier
+    ///
+    /// Returns job status
+    pub fn status(&self, uuid: &str) -> Result<Status, Box<dyn Error>> {
+        let json = self.on_store(|store| self.job_json(store, uuid))?;
+        let job: Job = serde_json::from_str(&json)?;
+
+        Ok(job.status)
+    }
+
+    /// Get statuses of many jobs at once
+    ///
+    /// `uuids` - unique job identifiers
+    ///
+    /// Returns job statuses in the order of `uuids`, `None` for jobs which have expired or never
+    /// existed
+    pub fn statuses(&self, uuids: &[String]) -> Result<Vec<Option<Status>>, Box<dyn Error>> {
+        Ok(self.jobs(uuids)?.into_iter().map(|o| o.map(|job| job.status)).collect())
+    }
+
+    /// Get results of many jobs at once
+    ///
+    /// `uuids` - unique job identifiers
+    ///
+    /// Returns job results in the order of `uuids`, `None` for jobs without a result and jobs
+    /// which have expired or never existed
+    pub fn results(&self, uuids: &[String]) -> Result<Vec<Option<String>>, Box<dyn Error>> {
+        Ok(self.jobs(uuids)?.into_iter().map(|o| o.and_then(|job| job.result)).collect())
+    }
+
+    /// Read jobs by MGET, split over pooled connections and read concurrently if there are more
+    /// than `MGET_BATCH` of them
+    fn jobs(&self, uuids: &[String]) -> Result<Vec<Option<Job>>, Box<dyn Error>> {
+        if uuids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let keys: Vec<String> = uuids.iter().map(|o| format!("{}:{}", self.name, o)).collect();
+
+        let size = self.pool.as_ref().map_or(1, |o| o.size());
+        let batch = MGET_BATCH.max(keys.len().div_ceil(size));
+        let jsons: Vec<Option<String>> = match self.pool {
+            Some(ref pool) if keys.len() > batch => {
+                // Errors aren't Send, threads hand back their text
+                let batches: Vec<Result<Vec<Option<String>>, String>> = thread::scope(|scope| {
+                    let threads: Vec<_> = keys.chunks(batch)
+                        .map(|chunk| {
+                            scope.spawn(move || {
+                                let conn = Pool::get(pool).map_err(|e| e.to_string())?;
+                                redis::cmd("MGET").arg(chunk).query(&conn).map_err(|e| e.to_string())
+                            })
+                        })
+                        .collect();
+                    threads.into_iter()
+                        .map(|o| o.join().unwrap_or_else(|_| Err("MGET thread panicked".into())))
+                        .collect()
+                });
+                let mut jsons = Vec::with_capacity(keys.len());
+                for o in batches {
+                    jsons.extend(o?);
+                }
+                jsons
+            }
+            _ => {
+                let conn = self.conn()?;
+                redis::cmd("MGET").arg(&keys[..]).query(&conn)?
+            }
+        };
+
+        let mut jobs = Vec::with_capacity(jsons.len());
+        for json in jsons {
+            jobs.push(match json {
+                Some(o) => Some(serde_json::from_str(&o)?),
+                None => None,
+            });
+        }
+
+        Ok(jobs)
+    }
+
+    /// Check if job exists, without reading it
+    ///
+    /// `uuid` - unique job identifier
+    ///
+    /// Returns false if the job has expired or never existed
+    pub fn exists(&self, uuid: &str) -> Result<bool, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        Ok(conn.exists(format!("{}:{}", self.name, uuid))?)
+    }
+
+    /// Get job label
+    ///
+    /// `uuid` - unique job identifier
+    ///
+    /// Returns label the job was enqueued with by `enqueue_labeled`
+    pub fn label(&self, uuid: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let json = self.on_store(|store| self.job_json(store, uuid))?;
+        let job: Job = serde_json::from_str(&json)?;
+
+        Ok(job.label)
+    }
+
+    /// Get job details, same as `job_info`
+    ///
+    /// `uuid` - unique job identifier
+    ///
+    /// Returns job status, result, label and attempts, `None` if the job has expired or never
+    /// existed
+    pub fn job(&self, uuid: &str) -> Result<Option<JobInfo>, Box<dyn Error>> {
+        self.job_info(uuid)
+    }
+
+    /// Get everything about a job in one round trip, e.g. to debug it end to end
+    ///
+    /// `uuid` - unique job identifier
+    ///
+    /// The job, its time to live, its arguments list and its raw result are read in one pipeline,
+    /// so the details are consistent with each other. `JobInfo` serializes to JSON for logs.
+    ///
+    /// Returns job details, `None` if the job has expired or never existed
+    pub fn job_info(&self, uuid: &str) -> Result<Option<JobInfo>, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        let key = format!("{}:{}", self.name, uuid);
+        let (json, ttl, listed, raw): (Option<String>, i64, Vec<String>, usize) = redis::pipe()
+            .get(&key)
+            .cmd("PTTL")
+            .arg(&key)
+            .lrange(self.args_key(uuid), 0, -1)
+            .strlen(self.result_key(uuid))
+            .query(&conn)?;
+        let (job, mut stored_bytes): (Job, usize) = match json {
+            Some(o) => (serde_json::from_str(&o)?, o.len()),
+            None => return Ok(None),
+        };
+        let listed = if job.args_list { listed } else { Vec::new() };
+        let payload_bytes = match job.value {
+            Some(ref value) => value.len(),
+            None => job.args.iter().chain(listed.iter()).map(String::len).sum(),
+        };
+        stored_bytes += listed.iter().map(String::len).sum::<usize>();
+        if job.result_bytes {
+            stored_bytes += raw;
+        }
+        let args = match job.value {
+            Some(value) => vec![value],
+            None if job.args_list => listed,
+            None => job.args,
+        };
+
+        Ok(Some(JobInfo {
+            uuid: job.uuid,
+            status: job.status,
+            args,
+            result: job.result,
+            label: job.label,
+            kind: job.kind,
+            tags: job.tags,
+            owner: job.owner,
+            enqueued_at: job.enqueued_at.map(Duration::from_millis),
+            started_at: job.started_at.map(Duration::from_millis),
+            ttl: if ttl < 0 { None } else { Some(Duration::from_millis(ttl as u64)) },
+            attempts: job.attempts,
+            max_attempts: job.max_attempts,
+            stored_bytes,
+            payload_bytes,
+        }))
+    }
+
+    /// Get job arguments, skipping the rest of the job
+    ///
+    /// `uuid` - unique job identifier
+    ///
+    /// Returns job arguments, `None` if the job has expired or never existed
+    pub fn args(&self, uuid: &str) -> Result<Option<Vec<String>>, Box<dyn Error>> {
+        // Only the arguments are deserialized, the result and other fields are skipped
+        #[derive(Deserialize)]
+        struct Args {
+            #[serde(deserialize_with = "deserialize_args")]
+            args: Vec<String>,
+            #[serde(default)]
+            args_list: bool,
+            #[serde(default)]
+            value: Option<String>,
+        }
+
+        let conn = self.conn()?;
+
+        let json: Option<String> = conn.get(format!("{}:{}", self.name, uuid))?;
+        let job: Args = match json {
+            Some(o) => serde_json::from_str(&o)?,
+            None => return Ok(None),
+        };
+        if let Some(value) = job.value {
+            return Ok(Some(vec![value]));
+        }
+        if job.args_list {
+            return Ok(Some(conn.lrange(self.args_key(uuid), 0, -1)?));
+        }
+
+        Ok(Some(job.args))
+    }
+
+    /// Key holding the job worker `worker_id` is processing
+    fn current_key(&self, worker_id: &str) -> String {
+        format!("{}:worker:{}:current", self.name, worker_id)
+    }
+
+    /// Get job a worker is processing right now
+    ///
+    /// `worker_id` - worker identifier, set with `WorkOptions::worker_id`
+    ///
+    /// Returns unique job identifier, `None` if the worker is idle or unknown
+    pub fn current_job(&self, worker_id: &str) -> Result<Option<String>, Box<dyn Error>> {
+        self.on_store(|store| store.get(&self.current_key(worker_id)))
+    }
+
+    /// Cancel queued job, it is kept until expiration with `CANCELLED` status and skipped by
+    /// workers
+    ///
+    /// `uuid` - unique job identifier
+    ///
+    /// Returns false if the job doesn't exist or isn't queued anymore
+    pub fn cancel(&self, uuid: &str) -> Result<bool, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        let key = format!("{}:{}", self.name, uuid);
+        let cancelled = redis::transaction(&conn, &[&key], |pipe| {
+            let json: Option<String> = conn.get(&key)?;
+            let mut job = match json.and_then(|o| serde_json::from_str::<Job>(&o).ok()) {
+                Some(ref o) if o.status != Status::QUEUED => return Ok(Some(false)),
+                Some(o) => o,
+                None => return Ok(Some(false)),
+            };
+            let ttl: i64 = redis::cmd("PTTL").arg(&key).query(&conn)?;
+
+            job.status = Status::CANCELLED;
+            let json = self.encode(&job)
+                .map_err(|e| redis::RedisError::from((redis::ErrorKind::TypeError,
+                                                      "job serialization failed",
+                                                      e.to_string())))?;
+            // Pinned jobs have no time to live, their deadline still applies
+            if ttl < 0 {
+                pipe.set(&key, json).ignore();
+            } else {
+                pipe.cmd("PSETEX").arg(&key).arg(ttl.max(1)).arg(json).ignore();
+            }
+            if let Some(ref lock) = job.unique {
+                pipe.del(lock).ignore();
+            }
+            pipe.hincr(self.counts_key(), "CANCELLED", 1).ignore();
+            let done: Option<()> = pipe.query(&conn)?;
+            Ok(done.map(|_| true))
+        })?;
+        if cancelled {
+            complete_local(&key, Status::CANCELLED, None);
+        }
+
+        Ok(cancelled)
+    }
+
+    /// Move queued job to another queue, e.g. to rebalance backlogs
+    ///
+    /// `uuid` - unique job identifier
+    ///
+    /// `other` - target queue on the same Redis server
+    ///
+    /// The job is taken off the ready list of this queue, written under the target queue with its
+    /// time to live and put to the end of its ready list, all at once by a Lua script. The job
+    /// keeps its identifier, kind and uniqueness lock.
+    ///
+    /// Returns false if the job doesn't exist or isn't waiting on the ready list anymore, e.g. it
+    /// is running already or delayed, nothing is changed then
+    pub fn move_to(&self, uuid: &str, other: &Queue) -> Result<bool, Box<dyn Error>> {
+        if self.url != other.url {
+            return Err(format!("queue {} isn't on the same server as {}", other.name, self.name)
+                .into());
+        }
+
+        let conn = self.conn()?;
+
+        let key = format!("{}:{}", self.name, uuid);
+        let json: Option<String> = conn.get(&key)?;
+        let job = match json.and_then(|o| serde_json::from_str::<Job>(&o).ok()) {
+            Some(o) => o,
+            None => return Ok(false),
+        };
+        if let Some(ref kind) = job.kind {
+            let _: () = conn.sadd(format!("{}:kinds", other.name), kind)?;
+        }
+
+        let moved: bool = Script::new(MOVE_SCRIPT).key(key)
+            .key(self.ready_key(job.kind.as_deref(), uuid))
+            .key(format!("{}:{}", other.name, uuid))
+            .key(other.ready_key(job.kind.as_deref(), uuid))
+            .key(self.args_key(uuid))
+            .key(other.args_key(uuid))
+            .arg(uuid)
+            .invoke(&conn)?;
+        // A pinned job keeps its deadline, the job key was moved without a time to live
+        let deadline: Option<u64> = conn.zscore(self.deadlines_key(), uuid)?;
+        if let (true, Some(deadline)) = (moved, deadline) {
+            let _: () = redis::pipe()
+                .zrem(self.deadlines_key(), uuid)
+                .ignore()
+                .zadd(other.deadlines_key(), uuid, deadline)
+                .ignore()
+                .query(&conn)?;
+        }
+
+        Ok(moved)
+    }
+
+    /// Get remaining time to live of job
+    ///
+    /// `uuid` - unique job identifier
+    ///
+    /// Returns number of seconds before the job is removed, `None` if there is no such job, and
+    /// `Some(-1)` if the job has no expiration, which only happens for queued jobs of a queue with
+    /// `QueueOptions::pin`
+    pub fn ttl(&self, uuid: &str) -> Result<Option<i64>, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        let ttl: i64 = redis::cmd("TTL").arg(format!("{}:{}", self.name, uuid)).query(&conn)?;
+
+        Ok(if ttl == -2 { None } else { Some(ttl) })
+    }
+
+    /// Work on queue, process enqueued jobs
+    ///
+    /// `fun` - function that would work on jobs
+    ///
+    /// `wait` - timeout in seconds to wait for one iteration of BLPOP, 10 by default
+    ///
+    /// `timeout` - timeout in seconds, if job hasn't been completed during this time, it will be
+    /// marked as lost, 30 by default
+    ///
+    /// `freq` - frequency of checking job status while counting on timeout, number of checks per
+    /// second, 1 by default
+    ///
+    /// `expire` - job result expiration time in seconds, 30 by default
+    ///
+    /// `fall` - panic if job was lost, true by default
+    ///
+    /// `infinite` - process jobs infinitely, true by default
+    #[deprecated(note = "use `work_with`, which takes `WorkOptions`")]
+    #[allow(clippy::too_many_arguments)]
+    pub fn work<F: Fn(String, Vec<String>) -> Result<String, Box<dyn Error>> + Send + Sync + 'static>
+        (&self,
+         fun: F,
+         wait: Option<usize>,
+         timeout: Option<usize>,
+         freq: Option<usize>,
+         expire: Option<usize>,
+         fall: Option<bool>,
+         infinite: Option<bool>)
+         -> Result<(), Box<dyn Error>> {
+        let defaults = WorkOptions::default();
+        let secs = |value: Option<usize>, default| {
+            value.map_or(default, |o| Duration::from_secs(o as u64))
+        };
+        let options = WorkOptions {
+            wait: secs(wait, defaults.wait),
+            timeout: secs(timeout, defaults.timeout),
+            freq: freq.unwrap_or(defaults.freq),
+            expire: secs(expire, defaults.expire),
+            fall: fall.unwrap_or(defaults.fall),
+            infinite: infinite.unwrap_or(defaults.infinite),
+            ..defaults
+        };
+        self.work_with(fun, &options)?;
+
+        Ok(())
+    }
+
+    /// Work on queue, process enqueued jobs
+    ///
+    /// `fun` - function that would work on jobs
+    ///
+    /// `options` - worker options, see `WorkOptions` for defaults
+    ///
+    /// While the queue is paused the worker idles for `wait` per iteration instead of popping
+    /// jobs
+    ///
+    /// Returns totals once the worker stops: after one iteration if not `infinite`, or once
+    /// `max_jobs` jobs were processed
+    pub fn work_with<F>(&self, fun: F, options: &WorkOptions) -> Result<WorkSummary, Box<dyn Error>>
+        where F: Fn(String, Vec<String>) -> Result<String, Box<dyn Error>> + Send + Sync + 'static
+    {
+        self.run(by_uuid(fun), options, false, None, None, &[], None)
+    }
+
+    /// Work on queue as `work_with`, passing job details to `fun` instead of the job identifier
+    /// only
+    ///
+    /// `fun` - function that would work on jobs, taking `JobContext` and job arguments
+    ///
+    /// `options` - worker options
+    ///
+    /// The context tells the attempt number, e.g. to skip an optimistic path on a retry, when the
+    /// job was enqueued and its tags. `JobContext::is_cancelled` turns true once the job has
+    /// timed out, so that a long job can stop instead of running on with its result discarded.
+    ///
+    /// Returns totals once the worker stops
+    pub fn work_ctx<F>(&self, fun: F, options: &WorkOptions) -> Result<WorkSummary, Box<dyn Error>>
+        where F: Fn(JobContext, Vec<String>) -> Result<String, Box<dyn Error>>
+                     + Send
+                     + Sync
+                     + 'static
+    {
+        self.run(fun, options, false, None, None, &[], None)
+    }
+
+    /// Work on queue as `work_with`, taking commands from `commands` while running, e.g. to pause
+    /// one worker or to stop it once the queue is empty
+    ///
+    /// `fun` - function that would work on jobs
     ///
-    /// Returns job status
-    pub fn status(&self, uuid: &str) -> Result<Status, Box<Error>> {
-        let client = redis::Client::open(self.url.as_str())?;
-        let conn = client.get_connection()?;
+    /// `options` - worker options
+    ///
+    /// `commands` - worker commands, see `WorkerCommand`
+    ///
+    /// Commands are taken before every poll for a job, so they take effect between jobs, within
+    /// `wait` of being sent while the queue is empty. A job is never interrupted, since it runs on
+    /// a thread of its own: the job in flight completes or is lost by its timeout before any
+    /// command is taken. Once all senders are dropped the worker goes on as `work_with`.
+    ///
+    /// Returns totals once the worker stops
+    pub fn work_controlled<F>(&self,
+                              fun: F,
+                              options: &WorkOptions,
+                              commands: &Receiver<WorkerCommand>)
+                              -> Result<WorkSummary, Box<dyn Error>>
+        where F: Fn(String, Vec<String>) -> Result<String, Box<dyn Error>> + Send + Sync + 'static
+    {
+        self.run(by_uuid(fun), options, false, None, Some(commands), &[], None)
+    }
 
-        let json: String = conn.get(format!("{}:{}", self.name, uuid))?;
-        let job: Job = serde_json::from_str(&json)?;
+    /// Work on several queues by priority, see `work_with`
+    ///
+    /// `queues` - queues in order of priority, the first one is the most urgent
+    ///
+    /// `fun` - function that would work on jobs of all queues
+    ///
+    /// `options` - worker options, the same for all queues
+    ///
+    /// Every poll claims a job from the first queue which has one, so that jobs of a queue are
+    /// only taken while all queues before it are empty or paused. A busy urgent queue starves the
+    /// lower ones. Queues must be on the same server, `prefetch` only applies to a single queue.
+    ///
+    /// Priority levels are queues rather than scores of a sorted set, so there is no score to tie:
+    /// within a level jobs keep the order of its ready lists, oldest first unless `lifo`.
+    ///
+    /// Returns totals of all queues once the worker stops
+    pub fn work_prioritized<F>(queues: &[Queue],
+                               fun: F,
+                               options: &WorkOptions)
+                               -> Result<WorkSummary, Box<dyn Error>>
+        where F: Fn(String, Vec<String>) -> Result<String, Box<dyn Error>> + Send + Sync + 'static
+    {
+        let (first, lower) = match queues.split_first() {
+            Some(o) => o,
+            None => return Err("no queues to work on".into()),
+        };
+        if let Some(queue) = lower.iter().find(|o| o.url != first.url) {
+            return Err(format!("queue {} isn't on the same server as {}", queue.name, first.name)
+                .into());
+        }
 
-        Ok(job.status)
+        first.run(by_uuid(fun), options, false, None, None, lower, None)
     }
 
-    /// Work on queue, process enqueued jobs
+    /// Work on several queues picked by weight, see `work_with`
     ///
-    /// `fun` - function that would work on jobs
+    /// `queues` - queues with their weights, which are relative, e.g. `3.0` and `1.0`
     ///
-    /// `wait` - timeout in seconds to wait for one iteration of BLPOP, 10 by default
+    /// `fun` - function that would work on jobs of all queues
     ///
-    /// `timeout` - timeout in seconds, if job hasn't been completed during this time, it will be
-    /// marked as lost, 30 by default
+    /// `options` - worker options, the same for all queues
     ///
-    /// `freq` - frequency of checking job status while counting on timeout, number of checks per
-    /// second, 1 by default
+    /// Every poll orders the queues by weighted random sampling: the first one is drawn with
+    /// probability proportional to its weight, the next one likewise among the rest, and a job is
+    /// claimed from the first queue which has one. While all queues have jobs waiting, each gets
+    /// a share of the claims in proportion to its weight, so unlike `work_prioritized` a busy queue
+    /// doesn't starve the others. Queues of zero weight are only polled while all others are
+    /// empty or paused. Queues must be on the same server, `prefetch` only applies to a single
+    /// queue.
     ///
-    /// `expire` - job result expiration time in seconds, 30 by default
+    /// Returns totals of all queues once the worker stops
+    pub fn work_weighted<F>(queues: &[(Queue, f64)],
+                            fun: F,
+                            options: &WorkOptions)
+                            -> Result<WorkSummary, Box<dyn Error>>
+        where F: Fn(String, Vec<String>) -> Result<String, Box<dyn Error>> + Send + Sync + 'static
+    {
+        let ((first, _), rest) = match queues.split_first() {
+            Some(o) => o,
+            None => return Err("no queues to work on".into()),
+        };
+        if let Some((queue, _)) = rest.iter().find(|o| o.0.url != first.url) {
+            return Err(format!("queue {} isn't on the same server as {}", queue.name, first.name)
+                .into());
+        }
+        if let Some(&(ref queue, weight)) =
+            queues.iter().find(|o| !o.1.is_finite() || o.1 < 0.0) {
+            return Err(format!("weight {} of queue {} isn't a finite non-negative number",
+                               weight,
+                               queue.name)
+                .into());
+        }
+        if queues.iter().all(|o| o.1 == 0.0) {
+            return Err("no queue has a positive weight".into());
+        }
+
+        let lower: Vec<Queue> = rest.iter().map(|o| o.0.clone()).collect();
+        let weights: Vec<f64> = queues.iter().map(|o| o.1).collect();
+        first.run(by_uuid(fun), options, false, None, None, &lower, Some(&weights))
+    }
+
+    /// Work on queue until it is empty or `deadline` passes, e.g. to drain it before a deploy
+    /// without being blocked by a huge backlog
     ///
-    /// `fall` - panic if job was lost, true by default
+    /// `fun` - function that would work on jobs
     ///
-    /// `infinite` - process jobs infinitely, true by default
-    pub fn work<F: Fn(String, Vec<String>) -> Result<String, Box<Error>> + Send + Sync + 'static>
-        (&self,
-         fun: F,
-         wait: Option<usize>,
-         timeout: Option<usize>,
-         freq: Option<usize>,
-         expire: Option<usize>,
-         fall: Option<bool>,
-         infinite: Option<bool>)
-         -> Result<(), Box<Error>> {
-        let wait = wait.unwrap_or(10);
-        let timeout = timeout.unwrap_or(30);
-        let freq = freq.unwrap_or(1);
-        let expire = expire.unwrap_or(30);
-        let fall = fall.unwrap_or(true);
-        let infinite = infinite.unwrap_or(true);
+    /// `options` - worker options, `infinite` is ignored
+    ///
+    /// `deadline` - longest time to drain for, counted on `QueueOptions::clock`
+    ///
+    /// The queue is empty once a poll waiting `wait`, cut to the time left, finds no job, and a
+    /// paused queue ends draining as well. The deadline is checked between jobs, so a running job
+    /// is never abandoned: it completes or is lost by its timeout as usual, and jobs prefetched but
+    /// not started are put back to the ready lists. `max_jobs` still stops draining early.
+    ///
+    /// Returns totals of the processed jobs and the number of jobs left
+    pub fn drain_until<F>(&self,
+                          fun: F,
+                          options: &WorkOptions,
+                          deadline: Duration)
+                          -> Result<DrainSummary, Box<dyn Error>>
+        where F: Fn(String, Vec<String>) -> Result<String, Box<dyn Error>> + Send + Sync + 'static
+    {
+        let until = self.options.clock.elapsed() + deadline;
+        let work = self.run(by_uuid(fun), options, false, Some(until), None, &[], None)?;
+        let timed_out = self.options.clock.elapsed() >= until;
+
+        let conn = self.conn()?;
+        let mut pipe = redis::pipe();
+        for key in self.worker_shards(options.kinds.as_ref()).iter().flatten() {
+            pipe.llen(key);
+        }
+        let lengths: Vec<usize> = pipe.query(&conn)?;
+
+        Ok(DrainSummary {
+            work,
+            remaining: lengths.iter().sum(),
+            timed_out,
+        })
+    }
+
+    /// Ready list shards a worker of `kinds` pops from, one group per kind
+    fn worker_shards(&self, kinds: Option<&Vec<String>>) -> Vec<Vec<String>> {
+        match kinds {
+            Some(kinds) => kinds.iter().map(|o| self.ready_shards(Some(o))).collect(),
+            None => vec![self.ready_shards(None)],
+        }
+    }
 
-        let client = redis::Client::open(self.url.as_str())?;
-        let conn = client.get_connection()?;
+    /// Work loop of `work_with`, results of `fun` carry raw bytes, one char per byte, if `bytes`,
+    /// and it stops on an empty poll or once the clock reaches `until` if draining, `commands` are
+    /// taken before every poll. Jobs of `lower` queues are taken in their order while this queue
+    /// and the queues before them are empty, or in an order sampled by `weights` of this queue
+    /// followed by `lower` ones on every poll, see `work_weighted`.
+    #[allow(clippy::too_many_arguments)]
+    fn run<F>(&self,
+              fun: F,
+              options: &WorkOptions,
+              bytes: bool,
+              until: Option<Duration>,
+              commands: Option<&Receiver<WorkerCommand>>,
+              lower: &[Queue],
+              weights: Option<&[f64]>)
+              -> Result<WorkSummary, Box<dyn Error>>
+        where F: Fn(JobContext, Vec<String>) -> Result<String, Box<dyn Error>>
+                     + Send
+                     + Sync
+                     + 'static
+    {
+        let WorkOptions { wait,
+                          timeout,
+                          freq,
+                          expire,
+                          fall,
+                          on_fall,
+                          infinite,
+                          max_jobs,
+                          lost,
+                          backoff,
+                          connect_retry: retry,
+                          max_result,
+                          prefetch,
+                          kinds,
+                          fair,
+                          lifo,
+                          worker_id,
+                          executor,
+                          dry_run,
+                          client_name,
+                          expired,
+                          partition } = options.clone();
+
+        check_freq(freq)?;
+        let conn = connect_retry(&self.url, retry.as_ref())?;
+        let queues: Vec<&Queue> = iter::once(self).chain(lower).collect();
+        for queue in queues.iter() {
+            queue.check_eviction();
+        }
 
         let afun = Arc::new(fun);
-        let uuids_key = format!("{}:uuids", self.name);
+        let mut ready_shards: Vec<Vec<Vec<String>>> =
+            queues.iter().map(|o| o.worker_shards(kinds.as_ref())).collect();
+        if let Some((index, count)) = partition {
+            if index >= count {
+                return Err(format!("partition {} is out of {} partitions", index, count).into());
+            }
+            for (queue, groups) in queues.iter().zip(ready_shards.iter_mut()) {
+                if queue.options.shards.max(1) % count != 0 {
+                    return Err(format!("{} shards of queue {} can't be split into {} partitions",
+                                       queue.options.shards,
+                                       queue.name,
+                                       count)
+                        .into());
+                }
+                for shards in groups.iter_mut() {
+                    *shards = shards.drain(..).skip(index).step_by(count).collect();
+                }
+            }
+        }
+        // Draining goes on after every job and stops once there is none
+        let infinite = infinite || until.is_some();
+        let mut draining = until.is_some();
+        let mut polls = 0;
+        let mut prefetched = VecDeque::new();
+        let owner = worker_id.clone().unwrap_or_else(|| Uuid::new_v4().to_string());
+        // A server refusing CLIENT, e.g. renamed away, only loses the label
+        let client_name = client_name.unwrap_or_else(|| format!("rjq:{}:{}", self.name, owner));
+        if let Err(e) = set_name(&conn, &client_name) {
+            warn!("could not name worker connection {}: {}", client_name, e);
+        }
+        let mut summary = WorkSummary::default();
+        let mut idle = Idle::new(backoff);
+        let mut fell = None;
         loop {
-            let uuids: Vec<String> = conn.blpop(&uuids_key, wait)?;
-            if uuids.len() < 2 {
-                if !infinite {
+            // Time left to drain, the poll below waits no longer
+            let wait = match until {
+                Some(until) => {
+                    let now = self.options.clock.elapsed();
+                    if now >= until {
+                        summary.exit = WorkerExit::Deadline;
+                        break;
+                    }
+                    if wait.is_zero() { until - now } else { wait.min(until - now) }
+                }
+                None => wait,
+            };
+
+            // A paused worker only waits for the next command, its prefetched jobs are put back
+            let mut command = commands.and_then(|o| o.try_recv().ok());
+            while command == Some(WorkerCommand::Pause) {
+                if !prefetched.is_empty() {
+                    self.release(&conn, prefetched.drain(..).collect(), lifo)?;
+                }
+                command = commands.and_then(|o| o.recv().ok());
+            }
+            match command {
+                Some(WorkerCommand::Stop) => {
+                    summary.exit = WorkerExit::Stopped;
+                    break;
+                }
+                Some(WorkerCommand::Drain) => draining = true,
+                _ => {}
+            }
+
+            // Paused queues are passed over, the worker idles once all of them are paused
+            let mut sources = Vec::new();
+            let mut source_weights = Vec::new();
+            for (i, (queue, shards)) in queues.iter().zip(ready_shards.iter()).enumerate() {
+                let paused: bool = conn.exists(format!("{}:paused", queue.name))?;
+                if paused {
+                    continue;
+                }
+                if let Some(weights) = weights {
+                    source_weights.push(weights[i]);
+                }
+                if kinds.is_none() {
+                    queue.promote_due(&conn)?;
+                }
+                if queue.options.pin {
+                    queue.expire_pinned(&conn)?;
+                }
+                sources.push((*queue, rotated(shards, polls, fair)));
+            }
+            if weights.is_some() {
+                let order = weighted_order(&source_weights);
+                sources = order.into_iter().map(|i| sources[i].clone()).collect();
+            }
+            if sources.is_empty() {
+                if !infinite || draining {
+                    summary.exit = drained(draining);
                     break;
                 }
+                self.options.clock.sleep(wait);
                 continue;
             }
+            polls += 1;
 
-            let uuid = &uuids[1].to_string();
-            let key = format!("{}:{}", self.name, uuid);
-            let json: String = match conn.get(&key) {
-                Ok(o) => o,
-                Err(_) => {
-                    if !infinite {
+            let claimed = if dry_run {
+                self.peek(&conn, &sources, wait, lifo)?.map(|(i, o)| (sources[i].0, o))
+            } else if prefetch > 0 && lower.is_empty() {
+                if prefetched.is_empty() {
+                    let ready_keys = &sources[0].1;
+                    prefetched.extend(self.prefetch(&conn, ready_keys, prefetch + 1, wait, lifo)?);
+                }
+                let mut claimed = None;
+                while let Some((ready_key, uuid)) = prefetched.pop_front() {
+                    match self.start(&conn, &uuid, &owner, timeout + expire)? {
+                        Some((json, started)) => {
+                            claimed = Some((self, (ready_key, uuid, json, started)));
+                            break;
+                        }
+                        None if expired != ExpiredPolicy::Skip => {
+                            self.expired(&ready_key, &uuid, expired)?;
+                            summary.expired += 1;
+                        }
+                        None => {}
+                    }
+                }
+                claimed
+            } else {
+                let report = expired != ExpiredPolicy::Skip;
+                self.claim_first(&conn, &sources, &owner, timeout + expire, wait, lifo, report)?
+                    .map(|(i, o)| (sources[i].0, o))
+            };
+            let (queue, (ready_key, uuid, json, claimed)) = match claimed {
+                Some(o) => o,
+                None => {
+                    if !infinite || draining {
+                        summary.exit = drained(draining);
                         break;
                     }
+                    idle.empty(&*self.options.clock);
                     continue;
                 }
             };
+            idle.reset();
 
-            let mut job: Job = serde_json::from_str(&json)?;
+            let uuid = &uuid;
+            if !claimed && json.is_empty() {
+                queue.expired(&ready_key, uuid, expired)?;
+                summary.expired += 1;
+                continue;
+            }
+            let key = format!("{}:{}", queue.name, uuid);
+            let mut job: Job = match serde_json::from_str(&json) {
+                Ok(o) => o,
+                Err(e) => {
+                    if dry_run {
+                        warn!("job {} is corrupt, put back by dry run: {}", uuid, e);
+                        put_back(&conn, &ready_key, uuid, lifo)?;
+                    } else {
+                        error!("job {} is corrupt, moved to dead letters: {}", uuid, e);
+                        queue.bury(&conn, uuid)?;
+                    }
 
-            job.status = Status::RUNNING;
-            let _: () = conn.set_ex(&key, serde_json::to_string(&job)?, timeout + expire)?;
+                    summary.processed += 1;
+                    summary.failed += 1;
+                    if let Some(exit) = stop_after(summary.processed, max_jobs, infinite) {
+                        summary.exit = exit;
+                        break;
+                    }
+                    continue;
+                }
+            };
 
-            let (tx, rx) = channel();
-            let cafun = afun.clone();
-            let cuuid = uuid.clone();
-            let cargs = job.args.clone();
-            thread::spawn(move || {
-                let r = match cafun(cuuid, cargs) {
-                    Ok(o) => (Status::FINISHED, Some(o)),
-                    Err(_) => (Status::FAILED, None),
-                };
-                tx.send(r).unwrap_or(())
+            if !claimed {
+                if dry_run {
+                    put_back(&conn, &ready_key, uuid, lifo)?;
+                }
+                if job.status == Status::CANCELLED {
+                    summary.cancelled += 1;
+                }
+                if !infinite {
+                    break;
+                }
+                continue;
+            }
+
+            if !dry_run {
+                claim_local(&key, timeout + expire);
+            }
+            job.max_attempts = Some(match lost {
+                LostPolicy::Requeue { max_attempts } => max_attempts,
+                LostPolicy::Lost => 1,
             });
+            // Attempts are counted by the claim, workers before this one may have used them up
+            if let LostPolicy::Requeue { max_attempts } = lost {
+                if !dry_run && job.attempts > max_attempts {
+                    warn!("job {} has been attempted {} times, moved to dead letters",
+                          uuid,
+                          max_attempts);
+                    job.attempts -= 1;
+                    job.status = Status::LOST;
+                    queue.finish(&conn, &key, &job, expire)?;
+                    queue.bury(&conn, uuid)?;
 
-            for _ in 0..(timeout * freq) {
-                let (status, result) = rx.try_recv().unwrap_or((Status::RUNNING, None));
-                job.status = status;
-                job.result = result;
-                if job.status != Status::RUNNING {
-                    break;
+                    summary.processed += 1;
+                    summary.lost += 1;
+                    if let Some(exit) = stop_after(summary.processed, max_jobs, infinite) {
+                        summary.exit = exit;
+                        break;
+                    }
+                    continue;
                 }
-                sleep(Duration::from_millis(1000 / freq as u64));
             }
-            if job.status == Status::RUNNING {
-                job.status = Status::LOST;
+            let args = if dry_run {
+                queue.peek_args(&conn, &job)?
+            } else {
+                queue.attached_args(&conn, &job, timeout + expire)?
+            };
+            let current_key = worker_id.as_ref().filter(|_| !dry_run).map(|o| queue.current_key(o));
+            if let Some(ref current) = current_key {
+                let _: () = redis::cmd("PSETEX").arg(current)
+                    .arg(millis(timeout + expire))
+                    .arg(uuid)
+                    .query(&conn)?;
             }
-            let _: () = conn.set_ex(&key, serde_json::to_string(&job)?, expire)?;
 
-            if fall && job.status == Status::LOST {
-                panic!("LOST");
+            let context = JobContext {
+                uuid: uuid.to_string(),
+                attempt: job.attempts,
+                enqueued_at: job.enqueued_at.map(Duration::from_millis),
+                tags: job.tags.clone(),
+                cancelled: Arc::new(AtomicBool::new(false)),
+            };
+            let cancelled = context.cancelled.clone();
+            let handler = {
+                let afun = afun.clone();
+                Arc::new(move |_: String, args: Vec<String>| afun(context.clone(), args))
+            };
+            let ran = {
+                let lock = job.unique.as_deref();
+                execute(&handler,
+                        executor.as_deref(),
+                        &*self.options.clock,
+                        uuid,
+                        job.label.as_deref(),
+                        args.clone(),
+                        timeout,
+                        freq,
+                        &mut || if !dry_run {
+                            keepalive(&conn, &key, lock, timeout + expire)
+                        })
+            };
+            let (status, result) = match ran {
+                // Deferred jobs are promoted as delayed ones are, to the unkinded ready list
+                Ran::Deferred(delay) if job.kind.is_none() => {
+                    if let Some(ref current) = current_key {
+                        let _: () = conn.del(current)?;
+                    }
+                    if dry_run {
+                        put_back(&conn, &ready_key, uuid, lifo)?;
+                    } else {
+                        queue.defer(&conn, &key, &mut job, delay, expire)?;
+                    }
+
+                    summary.processed += 1;
+                    summary.deferred += 1;
+                    if let Some(exit) = stop_after(summary.processed, max_jobs, infinite) {
+                        summary.exit = exit;
+                        break;
+                    }
+                    continue;
+                }
+                Ran::Deferred(_) => {
+                    warn!("job {} of a kind can't be deferred, failed instead", uuid);
+                    (Status::FAILED, None)
+                }
+                Ran::Done(status, result) => (status, result),
+            };
+            if status == Status::LOST {
+                cancelled.store(true, Ordering::SeqCst);
+            }
+            job.status = status;
+            job.result = result;
+            if let Some(ref current) = current_key {
+                let _: () = conn.del(current)?;
+            }
+            if bytes {
+                if let Some(result) = job.result.take() {
+                    let mut raw: Vec<u8> = result.chars().map(|o| o as u8).collect();
+                    if let Some(max) = max_result {
+                        job.result_truncated = raw.len() > max;
+                        raw.truncate(max);
+                    }
+                    job.raw_result = Some(raw);
+                    job.result_bytes = true;
+                }
+            } else if let Some(max) = max_result {
+                if let Some(result) = job.result.take() {
+                    let (result, truncated) = truncate(result, max);
+                    job.result = Some(result);
+                    job.result_truncated = truncated;
+                }
+            }
+
+            if dry_run {
+                put_back(&conn, &ready_key, uuid, lifo)?;
+            } else if let LostPolicy::Requeue { max_attempts } = lost {
+                if job.status == Status::LOST && job.attempts < max_attempts {
+                    job.status = Status::QUEUED;
+                    let mut pipe = redis::pipe();
+                    pipe.atomic()
+                        .cmd("PSETEX")
+                        .arg(&key)
+                        .arg(millis(expire))
+                        .arg(queue.encode(&job)?)
+                        .ignore();
+                    queue.push_ready(&mut pipe, &ready_key, uuid, false);
+                    pipe.lrem(queue.processing_key(), 1, uuid).ignore();
+                    if job.args_list {
+                        pipe.pexpire(queue.args_key(uuid), millis(expire)).ignore();
+                    }
+                    let _: () = pipe.query(&conn)?;
+
+                    summary.processed += 1;
+                    if let Some(exit) = stop_after(summary.processed, max_jobs, infinite) {
+                        summary.exit = exit;
+                        break;
+                    }
+                    continue;
+                }
+            }
+
+            if !dry_run {
+                queue.finish(&conn, &key, &job, expire)?;
+                if job.status == Status::LOST && lost != LostPolicy::Lost {
+                    queue.bury(&conn, uuid)?;
+                }
             }
 
-            if !infinite {
+            summary.processed += 1;
+            match job.status {
+                Status::FINISHED => summary.finished += 1,
+                Status::FAILED => summary.failed += 1,
+                Status::LOST => summary.lost += 1,
+                _ => {}
+            }
+            if fall && job.status == Status::LOST {
+                let lost = RjqError::JobLost {
+                    uuid: uuid.to_string(),
+                    args,
+                };
+                match on_fall {
+                    FallPolicy::Panic => panic!("{}", lost),
+                    FallPolicy::Error => {
+                        fell = Some(lost);
+                        break;
+                    }
+                    FallPolicy::Callback(ref callback) => callback(uuid),
+                }
+            }
+            if let Some(exit) = stop_after(summary.processed, max_jobs, infinite) {
+                summary.exit = exit;
                 break;
             }
         }
 
-        Ok(())
+        if !prefetched.is_empty() {
+            self.release(&conn, prefetched.into_iter().collect(), lifo)?;
+        }
+        if let Some(lost) = fell {
+            return Err(lost.into());
+        }
+
+        Ok(summary)
     }
 
-    /// Get job result
+    /// Work on queue, passing job arguments to `fun` as a JSON value, see `work_with`
+    ///
+    /// `fun` - function that would work on jobs enqueued with `enqueue_value` or `enqueue_json`,
+    /// jobs whose arguments aren't a single JSON value fail, see `work_json`
+    ///
+    /// `options` - worker options
+    pub fn work_value<F>(&self,
+                         fun: F,
+                         options: &WorkOptions)
+                         -> Result<WorkSummary, Box<dyn Error>>
+        where F: Fn(String, serde_json::Value) -> Result<String, Box<dyn Error>>
+                     + Send
+                     + Sync
+                     + 'static
+    {
+        self.work_json(fun, options)
+    }
+
+    /// Work on queue, passing job arguments to `fun` by name, see `work_with`
+    ///
+    /// `fun` - function that would work on jobs enqueued with `enqueue_named`, jobs with
+    /// positional arguments fail without calling it, see `work_json`
+    ///
+    /// `options` - worker options
+    pub fn work_named<F>(&self,
+                         fun: F,
+                         options: &WorkOptions)
+                         -> Result<WorkSummary, Box<dyn Error>>
+        where F: Fn(String, BTreeMap<String, String>) -> Result<String, Box<dyn Error>>
+                     + Send
+                     + Sync
+                     + 'static
+    {
+        self.work_json(fun, options)
+    }
+
+    /// Work on queue, passing typed job payload to `fun`, see `work_with`
+    ///
+    /// `fun` - function that would work on jobs enqueued with `enqueue_json` or `enqueue_value`
+    ///
+    /// `options` - worker options
+    ///
+    /// A job whose arguments can't be decoded as `T`, e.g. enqueued by an older producer, fails
+    /// without calling `fun`, its result is the `RjqError::ArgsDecode` message, and the worker
+    /// goes on
+    pub fn work_json<T, F>(&self,
+                           fun: F,
+                           options: &WorkOptions)
+                           -> Result<WorkSummary, Box<dyn Error>>
+        where T: DeserializeOwned,
+              F: Fn(String, T) -> Result<String, Box<dyn Error>> + Send + Sync + 'static
+    {
+        self.work_with(move |uuid, args| {
+                           let payload = match args.as_slice() {
+                               [json] => {
+                                   serde_json::from_str(json)
+                                       .map_err(|e| RjqError::ArgsDecode(e.to_string()))?
+                               }
+                               _ => {
+                                   let e = format!("expected 1 argument, got {}", args.len());
+                                   return Err(RjqError::ArgsDecode(e).into());
+                               }
+                           };
+                           fun(uuid, payload)
+                       },
+                       options)
+    }
+
+    /// Work on queue, storing results of `fun` as raw bytes, e.g. generated images or PDFs, see
+    /// `work_with`
+    ///
+    /// `fun` - function that would work on jobs, returning binary or non-UTF8 results
+    ///
+    /// `options` - worker options, `max_result` cuts results to that many bytes without a marker
+    ///
+    /// Results are written as they are to `{name}:{uuid}:result` next to the job and expire
+    /// together with it, read them with `result_bytes`. `result` returns `None` for such jobs.
+    pub fn work_bytes<F>(&self,
+                         fun: F,
+                         options: &WorkOptions)
+                         -> Result<WorkSummary, Box<dyn Error>>
+        where F: Fn(String, Vec<String>) -> Result<Vec<u8>, Box<dyn Error>> + Send + Sync + 'static
+    {
+        // Each byte travels as the char of the same code point, so that the result goes through
+        // the worker as text and comes back unchanged
+        self.run(move |ctx: JobContext, args| {
+                     fun(ctx.uuid, args).map(|o| o.into_iter().map(char::from).collect())
+                 },
+                 options,
+                 true,
+                 None,
+                 None,
+                 &[],
+                 None)
+    }
+
+    /// Work on queue with `work_options`
+    ///
+    /// `fun` - function that would work on jobs
+    pub fn work_default<F>(&self, fun: F) -> Result<WorkSummary, Box<dyn Error>>
+        where F: Fn(String, Vec<String>) -> Result<String, Box<dyn Error>> + Send + Sync + 'static
+    {
+        self.work_with(fun, &self.work_options())
+    }
+
+    /// Get job result as bytes
     ///
     /// `uuid` - unique job identifier
     ///
-    /// Returns job result
-    pub fn result(&self, uuid: &str) -> Result<Option<String>, Box<Error>> {
-        let client = redis::Client::open(self.url.as_str())?;
-        let conn = client.get_connection()?;
+    /// Returns raw result of a job processed by `work_bytes`, or the text result of any other job
+    /// as UTF-8 bytes
+    ///
+    /// Raw results needn't be UTF-8, so unlike `result` this reads Redis even for a queue made
+    /// `with_store`, whose store returns strings only
+    pub fn result_bytes(&self, uuid: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let conn = self.conn()?;
 
         let json: String = conn.get(format!("{}:{}", self.name, uuid))?;
         let job: Job = serde_json::from_str(&json)?;
+        if !job.result_bytes {
+            return Ok(job.result.map(String::into_bytes));
+        }
+
+        Ok(conn.get(self.result_key(uuid))?)
+    }
+
+    /// Get job result
+    ///
+    /// `uuid` - unique job identifier
+    ///
+    /// Returns job result
+    pub fn result(&self, uuid: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let json = self.on_store(|store| self.job_json(store, uuid))?;
+        let job: Job = serde_json::from_str(&json)?;
 
         Ok(job.result)
     }
+
+    /// Get job result, waiting a little for a running job to complete first
+    ///
+    /// `uuid` - unique job identifier
+    ///
+    /// `grace` - longest time to wait for a running job, e.g. a few milliseconds so that a result
+    /// being written at the moment isn't missed, counted on `QueueOptions::clock`
+    ///
+    /// The job is read again every 10 milliseconds while it is running, other jobs are returned
+    /// at once, so a queued job isn't waited for.
+    ///
+    /// Returns job result, `None` if the job is still running after `grace`
+    pub fn result_or_wait(&self,
+                          uuid: &str,
+                          grace: Duration)
+                          -> Result<Option<String>, Box<dyn Error>> {
+        self.on_store(|store| {
+            let clock = &*self.options.clock;
+            let deadline = clock.elapsed() + grace;
+            loop {
+                let job: Job = serde_json::from_str(&self.job_json(store, uuid)?)?;
+                let now = clock.elapsed();
+                if job.status != Status::RUNNING || now >= deadline {
+                    return Ok(job.result);
+                }
+                clock.sleep(GRACE_POLL.min(deadline - now));
+            }
+        })
+    }
+
+    /// Get job result along with whether the job is done, unlike `result` which returns `None`
+    /// for a job not done yet as well as for a job done without a result
+    ///
+    /// `uuid` - unique job identifier
+    ///
+    /// Returns job result state, `ResultState::NotFound` if the job has expired or never existed
+    pub fn result_state(&self, uuid: &str) -> Result<ResultState, Box<dyn Error>> {
+        let json = self.on_store(|store| store.get(&format!("{}:{}", self.name, uuid)))?;
+        let job: Job = match json {
+            Some(o) => serde_json::from_str(&o)?,
+            None => return Ok(ResultState::NotFound),
+        };
+
+        Ok(match job.status {
+            Status::QUEUED | Status::RUNNING => ResultState::Pending,
+            Status::FINISHED => ResultState::Done(job.result),
+            Status::FAILED => ResultState::Failed(job.result),
+            Status::LOST => ResultState::Lost,
+            Status::CANCELLED => ResultState::Cancelled,
+        })
+    }
+
+    /// Get job result and delete the job, so that the result can't be read twice
+    ///
+    /// `uuid` - unique job identifier
+    ///
+    /// The job is removed whatever its status, take the result once the job has completed. Uses
+    /// GETDEL on Redis 6.2+ and a Lua script on older servers.
+    ///
+    /// Returns job result, `None` if there is no such job or it has no result
+    pub fn take_result(&self, uuid: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let conn = self.conn()?;
+
+        let key = format!("{}:{}", self.name, uuid);
+        // Unknown commands fail and a failed command closes the connection, so check the version
+        let json: Option<String> = if self.version(&conn)? >= (6, 2, 0) {
+            redis::cmd("GETDEL").arg(&key).query(&conn)?
+        } else {
+            Script::new(GETDEL_SCRIPT).key(&key).invoke(&conn)?
+        };
+
+        match json {
+            Some(json) => {
+                let job: Job = serde_json::from_str(&json)?;
+                Ok(job.result)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Check if job result was truncated by the worker's `WorkOptions::max_result`
+    ///
+    /// `uuid` - unique job identifier
+    ///
+    /// Returns true if the stored result is partial
+    pub fn result_truncated(&self, uuid: &str) -> Result<bool, Box<dyn Error>> {
+        let json = self.on_store(|store| self.job_json(store, uuid))?;
+        let job: Job = serde_json::from_str(&json)?;
+
+        Ok(job.result_truncated)
+    }
+}
+
+/// Job popped by `Queue::reserve_for` and marked as running
+///
+/// The job keeps the connection it was reserved with, a pooled one if the queue has a pool, until
+/// it is completed or failed. Dropping it without either puts the job back to the head of its
+/// ready list, so that it is reserved again next. A process which dies holding it can't, the job
+/// is left running until its status expires.
+pub struct ReservedJob {
+    queue: Queue,
+    conn: Lease,
+    key: String,
+    job: Job,
+    args: Vec<String>,
+    expire: Duration,
+    /// Job was completed or failed
+    done: bool,
+}
+
+impl ReservedJob {
+    /// Unique job identifier
+    pub fn uuid(&self) -> &str {
+        &self.job.uuid
+    }
+
+    /// Job arguments
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Refresh time to live of the running job, call it periodically if processing may take
+    /// longer than the queue timeout
+    pub fn keepalive(&self) -> Result<(), Box<dyn Error>> {
+        let options = self.queue.work_options();
+        let mut pipe = redis::pipe();
+        let ttl = millis(options.timeout + options.expire);
+        pipe.pexpire(&self.key, ttl).ignore();
+        if let Some(ref lock) = self.job.unique {
+            pipe.pexpire(lock, ttl).ignore();
+        }
+        let _: () = pipe.query(&self.conn)?;
+
+        Ok(())
+    }
+
+    /// Mark job as finished
+    ///
+    /// `result` - job result
+    pub fn complete(mut self, result: String) -> Result<(), Box<dyn Error>> {
+        self.job.status = Status::FINISHED;
+        self.job.result = Some(result);
+        self.done = true;
+
+        self.queue.finish(&self.conn, &self.key, &self.job, self.expire)
+    }
+
+    /// Mark job as failed
+    ///
+    /// `err` - job error, its text is stored as the job result, see `Queue::result_state`
+    pub fn fail(mut self, err: Box<dyn Error>) -> Result<(), Box<dyn Error>> {
+        self.job.status = Status::FAILED;
+        self.job.result = Some(err.to_string());
+        self.done = true;
+
+        self.queue.finish(&self.conn, &self.key, &self.job, self.expire)
+    }
+
+    /// Put the job back to the head of its ready list as queued
+    fn requeue(&mut self) -> Result<(), Box<dyn Error>> {
+        let queue = &self.queue;
+        self.job.status = Status::QUEUED;
+        self.job.owner = None;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic().lrem(queue.processing_key(), 1, &self.job.uuid).ignore();
+        queue.write_job(&mut pipe, &self.job, queue.encode(&self.job)?, self.expire, true);
+        if self.job.args_list && !queue.options.pin {
+            pipe.pexpire(queue.args_key(&self.job.uuid), millis(self.expire)).ignore();
+        }
+        let _: () = pipe.query(&self.conn)?;
+
+        Ok(())
+    }
+}
+
+impl Drop for ReservedJob {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        if let Err(e) = self.requeue() {
+            error!("reserved job {} couldn't be put back: {}", self.job.uuid, e);
+        }
+    }
+}
+
+/// Iterator over jobs reserved one by one, see `Queue::iter`
+pub struct Reservations<'a> {
+    queue: &'a Queue,
+    wait: Duration,
+    /// Whether an error has been yielded, after which the iterator is fused
+    failed: bool,
+}
+
+impl<'a> Iterator for Reservations<'a> {
+    type Item = Result<ReservedJob, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let reserved = self.queue.reserve_for(self.wait).transpose();
+        self.failed = matches!(reserved, Some(Err(_)));
+
+        reserved
+    }
 }
@@ -0,0 +1,85 @@
+//! Store for the plain reads of a queue.
+//!
+//! `Store` serves the reads of calls which only look a job or a worker up, each a single `GET`:
+//! `status`, `result`, `result_state`, `result_or_wait`, `result_truncated`, `label` and
+//! `current_job`. `RedisStore` runs them on a Redis connection and is what a queue uses unless it
+//! is made with `Queue::with_store`, which sends them to another store instead, e.g. a fake
+//! holding the jobs a piece of code is tested against:
+//!
+//! ```rust,ignore
+//! extern crate rjq;
+//!
+//! use std::collections::HashMap;
+//! use std::error::Error;
+//! use std::sync::{Arc, Mutex};
+//! use rjq::{Queue, QueueOptions, Store};
+//!
+//! #[derive(Default)]
+//! struct Fake {
+//!     keys: Mutex<HashMap<String, String>>,
+//! }
+//!
+//! impl Store for Fake {
+//!     fn get(&self, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+//!         Ok(self.keys.lock().unwrap().get(key).cloned())
+//!     }
+//! }
+//!
+//! let fake = Arc::new(Fake::default());
+//! let queue = Queue::with_store("redis://localhost/", "rjq", QueueOptions::default(), fake);
+//! ```
+//!
+//! These calls run on the store without connecting to Redis. A custom store must return what
+//! Redis would for the keys it is given, `None` for a missing or expired key. Keys are full Redis
+//! keys such as `{name}:{uuid}` for a job and `{name}:worker:{id}:current` for the job of a worker.
+//!
+//! Everything changing the queue, along with calls reading several keys such as `job` or
+//! `result_bytes`, runs on Redis at the queue url: enqueueing, claiming and completing jobs rely on
+//! Lua scripts, transactions and pipelines to keep several keys consistent. So the store is a way
+//! to read, e.g. from a replica or a fake, not to keep jobs elsewhere. To fake a whole queue use a
+//! `Backend` such as `MemoryQueue` instead.
+//!
+//! References, `Box` and `Arc` of a store are stores too.
+
+use std::error::Error;
+use std::sync::Arc;
+use redis::Commands;
+
+/// Plain reads of a queue
+pub trait Store {
+    /// Get value of `key`, `None` if there is no such key
+    fn get(&self, key: &str) -> Result<Option<String>, Box<dyn Error>>;
+}
+
+/// Store running commands on a Redis connection
+pub struct RedisStore<'a, C: 'a> {
+    conn: &'a C,
+}
+
+impl<'a, C: Commands> RedisStore<'a, C> {
+    /// Init new store on `conn`, e.g. a `redis::Connection`
+    pub fn new(conn: &'a C) -> RedisStore<'a, C> {
+        RedisStore { conn }
+    }
+}
+
+impl<'a, C: Commands> Store for RedisStore<'a, C> {
+    fn get(&self, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(self.conn.get(key)?)
+    }
+}
+
+/// Implement `Store` for a pointer type by forwarding to the store it points to
+macro_rules! forward_store {
+    ($($pointer:ty),*) => {
+        $(
+        impl<'a, S: Store + ?Sized> Store for $pointer {
+            fn get(&self, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+                (**self).get(key)
+            }
+        }
+        )*
+    };
+}
+
+forward_store!(&'a S, Box<S>, Arc<S>);
@@ -0,0 +1,547 @@
+//! Redis Streams job queue backend.
+//!
+//! [`Queue`](../struct.Queue.html) keeps ready job identifiers in a list and claims them by moving
+//! them to a processing list, so a job is delivered at most once: if a worker dies after the
+//! claim, the job is left running on the processing list and never processed again.
+//!
+//! `StreamQueue` appends job identifiers to a stream with XADD and reads them through a consumer
+//! group with XREADGROUP. A delivered entry stays in the group's pending entries list until the
+//! worker acknowledges it with XACK after writing the terminal status, so delivery is at least
+//! once: a worker restarted with the same consumer name first re-reads its own pending entries
+//! and processes them again, and entries of a consumer which isn't restarted are claimed by the
+//! other workers of the group once they have been pending for longer than the job timeout. Job
+//! functions should therefore be idempotent.
+//!
+//! Workers of a queue made with `StreamQueue::at_most_once` acknowledge an entry instead just
+//! before running its job, for jobs where running twice is worse than not running at all. A
+//! worker dying mid-run then loses the job, which is left `RUNNING` until its status expires and
+//! never processed again. A worker dying between the read and the acknowledgement hasn't started
+//! the job yet, which is processed once on restart.
+//!
+//! Job keys and statuses are shared with the list backend, `status` and `result` work the same.
+//!
+//! ```rust,ignore
+//! extern crate rjq;
+//!
+//! use std::time::Duration;
+//! use rjq::{StreamQueue, WorkOptions};
+//!
+//! let queue = StreamQueue::new("redis://localhost/", "rjq", "workers");
+//! let uuid = queue.enqueue(vec![], Duration::from_secs(30))?;
+//! let options = WorkOptions {
+//!     timeout: Duration::from_secs(60),
+//!     fall: false,
+//!     ..Default::default()
+//! };
+//! let summary = queue.work_with(process, "worker-1", &options)?;
+//! ```
+
+use std::error::Error;
+use std::marker::{Send, Sync};
+use std::sync::Arc;
+use std::time::Duration;
+use redis::{self, Commands, Connection, Value};
+use serde_json;
+
+use super::{check_freq, execute, keepalive, millis, stop_after, truncate, FallPolicy, Job,
+            RjqError, Status, WorkOptions, WorkSummary};
+use super::clock::SystemClock;
+use super::connection::{connect, connect_retry};
+
+/// Stream entry field holding the job identifier
+const UUID_FIELD: &str = "uuid";
+
+/// Pending entries looked at for one claim of entries left by other consumers
+const STALE_SCAN: usize = 10;
+
+/// Entry id and job identifier of a delivered stream entry, no identifier if the entry has been
+/// deleted while it was pending
+type Entry = (String, Option<String>);
+
+/// Queue backed by a Redis stream and a consumer group
+pub struct StreamQueue {
+    /// Redis url
+    url: String,
+    /// Queue name
+    name: String,
+    /// Consumer group name
+    group: String,
+    /// Acknowledge entries before running their jobs
+    at_most_once: bool,
+}
+
+impl StreamQueue {
+    /// Init new stream queue object
+    ///
+    /// `url` - redis url to connect
+    ///
+    /// `name` - queue name
+    ///
+    /// `group` - consumer group name, workers sharing a group share the jobs
+    pub fn new(url: &str, name: &str, group: &str) -> StreamQueue {
+        StreamQueue {
+            url: url.to_string(),
+            name: name.to_string(),
+            group: group.to_string(),
+            at_most_once: false,
+        }
+    }
+
+    /// Init new stream queue object whose workers acknowledge entries before running their jobs,
+    /// so that a job is processed at most once
+    ///
+    /// `url` - redis url to connect
+    ///
+    /// `name` - queue name
+    ///
+    /// `group` - consumer group name, workers sharing a group share the jobs
+    pub fn at_most_once(url: &str, name: &str, group: &str) -> StreamQueue {
+        StreamQueue { at_most_once: true, ..StreamQueue::new(url, name, group) }
+    }
+
+    fn stream_key(&self) -> String {
+        format!("{}:stream", self.name)
+    }
+
+    /// Delete the stream with all enqueued and pending entries and its consumer groups
+    pub fn drop(&self) -> Result<(), Box<dyn Error>> {
+        let conn = connect(&self.url)?;
+
+        let _: () = conn.del(self.stream_key())?;
+
+        Ok(())
+    }
+
+    /// Enqueue new job
+    ///
+    /// `args` - job arguments
+    ///
+    /// `expire` - job expiration time, if hasn't started during this time it will be removed
+    ///
+    /// Returns unique job identifier
+    pub fn enqueue(&self, args: Vec<String>, expire: Duration) -> Result<String, Box<dyn Error>> {
+        let conn = connect(&self.url)?;
+
+        let job = Job::new(args);
+
+        // Job key and stream entry are written in one transaction
+        let _: () = redis::pipe()
+            .atomic()
+            .cmd("PSETEX")
+            .arg(format!("{}:{}", self.name, job.uuid))
+            .arg(millis(expire))
+            .arg(encode(&job)?)
+            .ignore()
+            .cmd("XADD")
+            .arg(self.stream_key())
+            .arg("*")
+            .arg(UUID_FIELD)
+            .arg(&job.uuid)
+            .ignore()
+            .query(&conn)?;
+
+        Ok(job.uuid)
+    }
+
+    /// Get job status
+    ///
+    /// `uuid` - unique job identifier
+    ///
+    /// Returns job status
+    pub fn status(&self, uuid: &str) -> Result<Status, Box<dyn Error>> {
+        let conn = connect(&self.url)?;
+
+        let json: String = conn.get(format!("{}:{}", self.name, uuid))?;
+        let job: Job = serde_json::from_str(&json)?;
+
+        Ok(job.status)
+    }
+
+    /// Get job result
+    ///
+    /// `uuid` - unique job identifier
+    ///
+    /// Returns job result
+    pub fn result(&self, uuid: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let conn = connect(&self.url)?;
+
+        let json: String = conn.get(format!("{}:{}", self.name, uuid))?;
+        let job: Job = serde_json::from_str(&json)?;
+
+        Ok(job.result)
+    }
+
+    /// Get number of entries delivered to consumers of the group but not acknowledged yet
+    pub fn pending(&self) -> Result<usize, Box<dyn Error>> {
+        let conn = connect(&self.url)?;
+
+        let summary: Value = match redis::cmd("XPENDING")
+            .arg(self.stream_key())
+            .arg(&self.group)
+            .query(&conn) {
+            Ok(o) => o,
+            Err(ref e) if e.extension_error_code() == Some("NOGROUP") => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+
+        match summary {
+            Value::Bulk(ref items) if !items.is_empty() => {
+                Ok(redis::from_redis_value(&items[0])?)
+            }
+            _ => Ok(0),
+        }
+    }
+
+    fn create_group(&self, conn: &Connection) -> Result<(), Box<dyn Error>> {
+        let created: redis::RedisResult<()> = redis::cmd("XGROUP")
+            .arg("CREATE")
+            .arg(self.stream_key())
+            .arg(&self.group)
+            .arg("0")
+            .arg("MKSTREAM")
+            .query(conn);
+
+        match created {
+            Err(ref e) if e.extension_error_code() == Some("BUSYGROUP") => Ok(()),
+            Err(e) => Err(e.into()),
+            Ok(()) => Ok(()),
+        }
+    }
+
+    /// Read one entry for `consumer`, `id` is either `0` for the consumer's own pending entries
+    /// or `>` for entries never delivered to the group
+    fn read(&self,
+            conn: &Connection,
+            consumer: &str,
+            id: &str,
+            wait: Duration)
+            -> Result<Option<Entry>, Box<dyn Error>> {
+        let mut cmd = redis::cmd("XREADGROUP");
+        cmd.arg("GROUP").arg(&self.group).arg(consumer).arg("COUNT").arg(1);
+        if id == ">" {
+            cmd.arg("BLOCK").arg(wait.as_millis() as u64);
+        }
+        cmd.arg("STREAMS").arg(self.stream_key()).arg(id);
+
+        let reply: Value = cmd.query(conn)?;
+
+        Ok(parse_entry(&reply))
+    }
+
+    /// Claim one entry delivered to another consumer of the group and left pending for at least
+    /// `idle`, e.g. by a worker which died, with XPENDING and XCLAIM
+    fn claim_stale(&self,
+                   conn: &Connection,
+                   consumer: &str,
+                   idle: Duration)
+                   -> Result<Option<Entry>, Box<dyn Error>> {
+        let pending: Vec<Value> = redis::cmd("XPENDING")
+            .arg(self.stream_key())
+            .arg(&self.group)
+            .arg("-")
+            .arg("+")
+            .arg(STALE_SCAN)
+            .query(conn)?;
+
+        for entry in pending {
+            let (id, owner, pending_for, _): (String, String, usize, usize) =
+                redis::from_redis_value(&entry)?;
+            if owner == consumer || pending_for < millis(idle) {
+                continue;
+            }
+            // Another worker may have claimed the entry meanwhile, XCLAIM then returns nothing
+            let claimed: Value = redis::cmd("XCLAIM")
+                .arg(self.stream_key())
+                .arg(&self.group)
+                .arg(consumer)
+                .arg(millis(idle))
+                .arg(&id)
+                .query(conn)?;
+            match claimed {
+                // Servers before Redis 7 claim a deleted entry as nil
+                Value::Bulk(ref items) if items.first() == Some(&Value::Nil) => {
+                    return Ok(Some((id, None)));
+                }
+                Value::Bulk(ref items) => {
+                    if let Some(entry) = items.first().and_then(parse_item) {
+                        return Ok(Some(entry));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn ack(&self, conn: &Connection, id: &str) -> Result<(), Box<dyn Error>> {
+        // Entries are removed once acknowledged, the job key keeps the outcome
+        let _: () = redis::pipe()
+            .cmd("XACK").arg(self.stream_key()).arg(&self.group).arg(id).ignore()
+            .cmd("XDEL").arg(self.stream_key()).arg(id).ignore()
+            .query(conn)?;
+
+        Ok(())
+    }
+
+    /// Work on queue as a member of the consumer group, process enqueued jobs
+    ///
+    /// `fun` - function that would work on jobs
+    ///
+    /// `consumer` - consumer name, unique within the group and stable across restarts so that
+    /// pending entries of a crashed worker are processed again
+    ///
+    /// `wait` - time to block for one iteration of XREADGROUP, 10 seconds by default, zero blocks
+    /// forever
+    ///
+    /// `timeout` - timeout, if job hasn't been completed during this time, it will be marked as
+    /// lost, 30 seconds by default
+    ///
+    /// `freq` - frequency of checking job status while counting on timeout, number of checks per
+    /// second, 1 by default
+    ///
+    /// `expire` - job result expiration time, 30 seconds by default
+    ///
+    /// `fall` - panic if job was lost, true by default
+    ///
+    /// `infinite` - process jobs infinitely, true by default
+    #[deprecated(note = "use `work_with`, which takes `WorkOptions`")]
+    #[allow(clippy::too_many_arguments)]
+    pub fn work<F: Fn(String, Vec<String>) -> Result<String, Box<dyn Error>> + Send + Sync + 'static>
+        (&self,
+         fun: F,
+         consumer: &str,
+         wait: Option<Duration>,
+         timeout: Option<Duration>,
+         freq: Option<usize>,
+         expire: Option<Duration>,
+         fall: Option<bool>,
+         infinite: Option<bool>)
+         -> Result<(), Box<dyn Error>> {
+        let defaults = WorkOptions::default();
+        let options = WorkOptions {
+            wait: wait.unwrap_or(defaults.wait),
+            timeout: timeout.unwrap_or(defaults.timeout),
+            freq: freq.unwrap_or(defaults.freq),
+            expire: expire.unwrap_or(defaults.expire),
+            fall: fall.unwrap_or(defaults.fall),
+            infinite: infinite.unwrap_or(defaults.infinite),
+            ..defaults
+        };
+        self.work_with(fun, consumer, &options)?;
+
+        Ok(())
+    }
+
+    /// Work on queue as a member of the consumer group, process enqueued jobs
+    ///
+    /// `fun` - function that would work on jobs
+    ///
+    /// `consumer` - consumer name, unique within the group and stable across restarts so that
+    /// pending entries of a crashed worker are processed again
+    ///
+    /// Entries left pending by other consumers for longer than the timeout and half the result
+    /// expiration are claimed as well, so that jobs of a worker which died and is never restarted
+    /// are processed by the rest of the group. The job key of such an entry outlives it, it isn't
+    /// refreshed by the dead worker but lives for the timeout and the expiration.
+    ///
+    /// Entries whose jobs have expired or been deleted are acknowledged and passed over, as are
+    /// entries of jobs which aren't `QUEUED`, or `RUNNING` for an entry delivered before
+    ///
+    /// `options` - worker options, `wait` is the time to block for one iteration of XREADGROUP.
+    /// Only `wait`, `timeout`, `freq`, `expire`, `fall`, `on_fall`, `infinite`, `max_jobs`,
+    /// `connect_retry` and `max_result` apply to stream queues, the other options are ignored
+    ///
+    /// Returns totals once the worker stops, as `Queue::work_with` does
+    pub fn work_with<F>(&self,
+                        fun: F,
+                        consumer: &str,
+                        options: &WorkOptions)
+                        -> Result<WorkSummary, Box<dyn Error>>
+        where F: Fn(String, Vec<String>) -> Result<String, Box<dyn Error>> + Send + Sync + 'static
+    {
+        let WorkOptions { wait, timeout, freq, expire, fall, ref on_fall, infinite, max_jobs, .. } =
+            *options;
+
+        check_freq(freq)?;
+        let conn = connect_retry(&self.url, options.connect_retry.as_ref())?;
+
+        self.create_group(&conn)?;
+
+        let afun = Arc::new(fun);
+        let stale = timeout + expire / 2;
+        let mut summary = WorkSummary::default();
+        let mut recovering = true;
+        loop {
+            let mut entry = None;
+            if recovering {
+                entry = self.read(&conn, consumer, "0", wait)?;
+                recovering = entry.is_some();
+            }
+            if entry.is_none() {
+                entry = self.claim_stale(&conn, consumer, stale)?;
+            }
+            // Entries delivered before may have been started by a worker which died
+            let (entry, redelivered) = match entry {
+                Some(o) => (Some(o), true),
+                None => (self.read(&conn, consumer, ">", wait)?, false),
+            };
+            let (id, uuid) = match entry {
+                Some((id, Some(uuid))) => (id, uuid),
+                Some((id, None)) => {
+                    self.ack(&conn, &id)?;
+                    continue;
+                }
+                None => {
+                    if !infinite {
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            let key = format!("{}:{}", self.name, uuid);
+            let json: Option<String> = conn.get(&key)?;
+            let json = match json {
+                Some(o) => o,
+                None => {
+                    self.ack(&conn, &id)?;
+                    continue;
+                }
+            };
+
+            let mut job: Job = match serde_json::from_str(&json) {
+                Ok(o) => o,
+                Err(e) => {
+                    error!("job {} is corrupt, moved to dead letters: {}", uuid, e);
+                    let _: () = conn.rpush(format!("{}:dead", self.name), &uuid)?;
+                    self.ack(&conn, &id)?;
+
+                    summary.processed += 1;
+                    summary.failed += 1;
+                    if let Some(exit) = stop_after(summary.processed, max_jobs, infinite) {
+                        summary.exit = exit;
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            let runnable = match job.status {
+                Status::QUEUED => true,
+                Status::RUNNING => redelivered,
+                _ => false,
+            };
+            if !runnable {
+                if job.status == Status::CANCELLED {
+                    summary.cancelled += 1;
+                }
+                self.ack(&conn, &id)?;
+                continue;
+            }
+
+            job.status = Status::RUNNING;
+            let _: () = redis::cmd("PSETEX").arg(&key)
+                .arg(millis(timeout + expire))
+                .arg(encode(&job)?)
+                .query(&conn)?;
+            if self.at_most_once {
+                self.ack(&conn, &id)?;
+            }
+
+            let ttl = timeout + expire;
+            let (status, result) = execute(&afun,
+                                           None,
+                                           &SystemClock,
+                                           &uuid,
+                                           job.label.as_deref(),
+                                           job.args.clone(),
+                                           timeout,
+                                           freq,
+                                           &mut || keepalive(&conn, &key, None, ttl))
+                .outcome();
+            job.status = status;
+            job.result = result;
+            if let Some(max) = options.max_result {
+                if let Some(result) = job.result.take() {
+                    let (result, truncated) = truncate(result, max);
+                    job.result = Some(result);
+                    job.result_truncated = truncated;
+                }
+            }
+            let _: () = redis::cmd("PSETEX").arg(&key)
+                .arg(millis(expire))
+                .arg(encode(&job)?)
+                .query(&conn)?;
+            if !self.at_most_once {
+                self.ack(&conn, &id)?;
+            }
+
+            summary.processed += 1;
+            match job.status {
+                Status::FINISHED => summary.finished += 1,
+                Status::FAILED => summary.failed += 1,
+                Status::LOST => summary.lost += 1,
+                _ => {}
+            }
+            if fall && job.status == Status::LOST {
+                let lost = RjqError::JobLost {
+                    uuid: uuid.clone(),
+                    args: job.args,
+                };
+                match *on_fall {
+                    FallPolicy::Panic => panic!("{}", lost),
+                    FallPolicy::Error => return Err(lost.into()),
+                    FallPolicy::Callback(ref callback) => callback(&uuid),
+                }
+            }
+            if let Some(exit) = stop_after(summary.processed, max_jobs, infinite) {
+                summary.exit = exit;
+                break;
+            }
+        }
+
+        Ok(summary)
+    }
+}
+
+/// Extract entry id and job identifier from XREADGROUP reply for a single stream
+fn parse_entry(reply: &Value) -> Option<Entry> {
+    let streams = match *reply {
+        Value::Bulk(ref o) => o,
+        _ => return None,
+    };
+    let entries = match streams.first() {
+        Some(Value::Bulk(stream)) if stream.len() == 2 => {
+            match stream[1] {
+                Value::Bulk(ref o) => o,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+
+    entries.first().and_then(parse_item)
+}
+
+/// Extract entry id and job identifier from a stream entry, the fields of an entry deleted while
+/// it was pending are nil
+fn parse_item(entry: &Value) -> Option<Entry> {
+    let entry = match *entry {
+        Value::Bulk(ref o) if o.len() == 2 => o,
+        _ => return None,
+    };
+
+    let id: String = redis::from_redis_value(&entry[0]).ok()?;
+    let fields: Vec<String> = redis::from_redis_value(&entry[1]).unwrap_or_default();
+    let uuid = fields.chunks(2)
+        .find(|pair| pair.len() == 2 && pair[0] == UUID_FIELD)
+        .map(|pair| pair[1].clone());
+
+    Some((id, uuid))
+}
+
+/// Serialize job for storage
+fn encode(job: &Job) -> Result<String, RjqError> {
+    serde_json::to_string(job).map_err(RjqError::Serialize)
+}
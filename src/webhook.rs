@@ -0,0 +1,121 @@
+//! Job completion webhooks, enabled with the `http` feature.
+//!
+//! Once a worker writes the terminal status of a job enqueued with a callback URL, it POSTs
+//! `{"uuid": "..", "status": "FINISHED", "result": "..", "attempts": 1, "max_attempts": 1}` to the
+//! URL as `application/json`. `result` is `null` if the job has none, `label` is added for jobs
+//! enqueued with one. Delivery runs on its own thread so the worker isn't blocked, it is retried a
+//! few times on failure and then only logged as an error.
+//!
+//! Requests are plain HTTP/1.1 over `TcpStream`, `https` URLs are not supported. A response with
+//! a 2xx status counts as delivered.
+
+use std::error::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::thread;
+use std::thread::sleep;
+use std::time::Duration;
+use serde_json;
+
+use super::{job_name, Job, Status};
+
+/// Number of delivery attempts, including the first one
+const ATTEMPTS: u32 = 3;
+
+/// Sleep before the second attempt, doubled for every next one
+const RETRY: Duration = Duration::from_secs(1);
+
+/// Connect timeout of every address the host resolves to, read and write timeout of one attempt
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    uuid: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<&'a str>,
+    status: &'a Status,
+    result: Option<&'a str>,
+    attempts: usize,
+    max_attempts: Option<usize>,
+}
+
+/// Notify `url` of the terminal status of `job` in the background
+pub(crate) fn notify(url: &str, job: &Job) {
+    let uuid = job_name(&job.uuid, job.label.as_deref());
+    let body = match serde_json::to_string(&Payload {
+        uuid: &job.uuid,
+        label: job.label.as_deref(),
+        status: &job.status,
+        result: job.result.as_deref(),
+        attempts: job.attempts,
+        max_attempts: job.max_attempts,
+    }) {
+        Ok(o) => o,
+        Err(e) => {
+            error!("job {} callback not sent: {}", uuid, e);
+            return;
+        }
+    };
+    let url = url.to_string();
+
+    thread::spawn(move || {
+        let mut delay = RETRY;
+        for attempt in 1..=ATTEMPTS {
+            match post(&url, &body) {
+                Ok(()) => return,
+                Err(e) if attempt == ATTEMPTS => {
+                    error!("job {} callback to {} failed, giving up: {}", uuid, url, e)
+                }
+                Err(e) => {
+                    warn!("job {} callback to {} failed, retrying in {:?}: {}",
+                          uuid,
+                          url,
+                          delay,
+                          e);
+                    sleep(delay);
+                    delay *= 2;
+                }
+            }
+        }
+    });
+}
+
+/// POST JSON `body` to `url`
+fn post(url: &str, body: &str) -> Result<(), Box<dyn Error>> {
+    let rest = url.strip_prefix("http://").ok_or("only http:// callback urls are supported")?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+
+    // Every resolved address is tried in turn, each for at most `TIMEOUT`
+    let mut connected = Err(format!("{} resolved to no address", address).into());
+    for addr in address.to_socket_addrs()? {
+        connected = TcpStream::connect_timeout(&addr, TIMEOUT).map_err(Box::<dyn Error>::from);
+        if connected.is_ok() {
+            break;
+        }
+    }
+    let mut stream = connected?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    write!(stream,
+           "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+            Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+           path,
+           authority,
+           body.len(),
+           body)?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    match line.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(format!("unexpected response {:?}", line.trim_end()).into()),
+    }
+}
@@ -0,0 +1,164 @@
+#[cfg(test)]
+extern crate rjq;
+extern crate redis;
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use rjq::{ConnectionStrategy, ExpiredPolicy, Queue, QueueOptions, RjqError, Status, WorkOptions};
+
+// Connections cap is process wide, so it is tested apart from the other tests, which take turns
+// on it
+static CAP: Mutex<()> = Mutex::new(());
+
+#[test]
+fn test_max_connections() {
+    let _cap = CAP.lock().unwrap_or_else(|e| e.into_inner());
+    assert!(rjq::max_connections() == rjq::connection::DEFAULT_MAX_CONNECTIONS);
+    rjq::set_max_connections(2);
+
+    let queue = Arc::new(Queue::new("redis://localhost/", "test-connections"));
+    let done = Arc::new(AtomicBool::new(false));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let sampler = {
+        let done = done.clone();
+        let peak = peak.clone();
+        thread::spawn(move || while !done.load(Ordering::SeqCst) {
+            peak.fetch_max(rjq::open_connections(), Ordering::SeqCst);
+        })
+    };
+
+    let producers: Vec<_> = (0..10)
+        .map(|_| {
+            let queue = queue.clone();
+            thread::spawn(move || for _ in 0..10 {
+                let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+                queue.status(&uuid).unwrap();
+            })
+        })
+        .collect();
+    for producer in producers {
+        producer.join().unwrap();
+    }
+    done.store(true, Ordering::SeqCst);
+    sampler.join().unwrap();
+
+    let peak = peak.load(Ordering::SeqCst);
+    assert!(peak > 0 && peak <= 2);
+    assert!(rjq::open_connections() == 0);
+
+    rjq::set_max_connections(rjq::connection::DEFAULT_MAX_CONNECTIONS);
+}
+
+// Hanging the server with DEBUG SLEEP holds up every client, so it is tested apart as well
+
+#[test]
+fn test_op_timeout() {
+    let _cap = CAP.lock().unwrap_or_else(|e| e.into_inner());
+    let hang = || {
+        thread::spawn(|| {
+            let client = redis::Client::open("redis://localhost/").unwrap();
+            let conn = client.get_connection().unwrap();
+            let _: () = redis::cmd("DEBUG").arg("SLEEP").arg(1).query(&conn).unwrap();
+        })
+    };
+    let options = || {
+        QueueOptions {
+            op_timeout: Some(Duration::from_millis(200)),
+            ..Default::default()
+        }
+    };
+
+    let queue = Queue::with_options("redis://localhost/", "test-op-timeout", options());
+    let sleeper = hang();
+    thread::sleep(Duration::from_millis(100));
+    let started = Instant::now();
+    let err = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap_err();
+    assert!(started.elapsed() < Duration::from_millis(800));
+    assert!(err.downcast_ref::<redis::RedisError>().is_some_and(|o| o.is_timeout()), "{}", err);
+    sleeper.join().unwrap();
+
+    // A pooled connection whose command timed out isn't reused once its late reply has come
+    let pooled = Queue::with_options("redis://localhost/",
+                                     "test-op-timeout",
+                                     QueueOptions {
+                                         connections: ConnectionStrategy::Pooled(1),
+                                         ..options()
+                                     });
+    let uuid = pooled.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let sleeper = hang();
+    thread::sleep(Duration::from_millis(100));
+    assert!(pooled.exists("missing").is_err());
+    sleeper.join().unwrap();
+    assert!(pooled.status(&uuid).unwrap() == Status::QUEUED);
+}
+
+#[test]
+fn test_nested_connections() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn std::error::Error>> {
+        Ok("ok".to_string())
+    }
+
+    let _cap = CAP.lock().unwrap_or_else(|e| e.into_inner());
+    assert!(rjq::connection_wait() == rjq::connection::DEFAULT_CONNECTION_WAIT);
+    rjq::set_max_connections(1);
+    rjq::set_connection_wait(Duration::from_millis(300));
+
+    let queue = Arc::new(Queue::new("redis://localhost/", "test-nested"));
+    Queue::drop(&queue).unwrap();
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+
+    // A thread holding a connection gets another one beyond the cap, other threads wait for a
+    // while and fail
+    let (nested, other) = queue.with_connection(|_| {
+            let other = {
+                let queue = queue.clone();
+                let uuid = uuid.clone();
+                thread::spawn(move || queue.status(&uuid).map_err(|e| e.to_string()))
+            };
+            Ok((queue.status(&uuid).ok(), other.join().unwrap()))
+        })
+        .unwrap();
+    assert!(nested == Some(Status::QUEUED));
+    assert!(other.unwrap_err() ==
+            RjqError::ConnectionsExhausted {
+                    max: 1,
+                    wait: Duration::from_millis(300),
+                }
+                .to_string());
+    assert!(rjq::open_connections() == 0);
+
+    // Idle pooled connections are closed to make room
+    let pooled = Queue::with_options("redis://localhost/",
+                                     "test-nested",
+                                     QueueOptions {
+                                         connections: ConnectionStrategy::Pooled(1),
+                                         ..Default::default()
+                                     });
+    assert!(pooled.status(&uuid).unwrap() == Status::QUEUED);
+    assert!(rjq::open_connections() == 1);
+    assert!(queue.status(&uuid).unwrap() == Status::QUEUED);
+    assert!(pooled.status(&uuid).unwrap() == Status::QUEUED);
+
+    // A worker checks eviction and compacts its queue on the connection cap it holds
+    drop(pooled);
+    Queue::drop(&queue).unwrap();
+    queue.enqueue_for(vec![], Duration::from_millis(10)).unwrap();
+    thread::sleep(Duration::from_millis(50));
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let summary = queue.work_with(fn_ok,
+                                  &WorkOptions {
+                                      wait: Duration::from_secs(1),
+                                      fall: false,
+                                      infinite: false,
+                                      expired: ExpiredPolicy::Strict,
+                                      ..Default::default()
+                                  })
+        .unwrap();
+    assert!(summary.expired == 1 && summary.finished == 1);
+    assert!(queue.status(&uuid).unwrap() == Status::FINISHED);
+
+    rjq::set_connection_wait(rjq::connection::DEFAULT_CONNECTION_WAIT);
+    rjq::set_max_connections(rjq::connection::DEFAULT_MAX_CONNECTIONS);
+}
@@ -1,11 +1,26 @@
+#![allow(deprecated, bare_trait_objects)]
 #[cfg(test)]
-
 extern crate rjq;
+extern crate redis;
+extern crate serde;
+extern crate serde_json;
 
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::thread::sleep;
 use std::error::Error;
-use rjq::{Status, Queue};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::thread;
+use rjq::{Status, Queue, QueueOptions, StreamQueue, LostPolicy, FallPolicy, WorkOptions,
+          WorkSummary, Backoff, Retry, LatencyStats, QueueStats, ConnectionStrategy, ResultState,
+          RjqError, Backend, MemoryQueue, MockClock, DrainSummary, WorkerCommand, JobContext,
+          JobEvent, WorkerExit, ExpiredPolicy, Store, RedisStore, Clock, SystemClock,
+          Outcome};
+use redis::Commands;
+use serde::{Serialize, Serializer};
+use serde::ser;
 
 #[test]
 fn test_job_queued() {
@@ -18,6 +33,438 @@ fn test_job_queued() {
     assert!(status == Status::QUEUED);
 }
 
+#[test]
+fn test_memory_job_queued() {
+    let queue = MemoryQueue::new();
+    let uuid = queue.enqueue(vec![], Duration::from_secs(5)).unwrap();
+
+    assert!(queue.status(&uuid).unwrap() == Status::QUEUED);
+}
+
+#[test]
+fn test_status_terminal() {
+    for status in [Status::QUEUED, Status::RUNNING] {
+        assert!(status.is_pending() && !status.is_terminal());
+    }
+    for status in [Status::LOST, Status::FINISHED, Status::FAILED, Status::CANCELLED] {
+        assert!(status.is_terminal() && !status.is_pending());
+    }
+}
+
+#[test]
+fn test_result_state() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-result-state");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    assert!(queue.result_state(&uuid).unwrap() == ResultState::Pending);
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        freq: 10,
+                        fall: false,
+                        infinite: false,
+                        ..Default::default()
+                    })
+        .unwrap();
+    assert!(queue.result_state(&uuid).unwrap() == ResultState::Done(Some("ok".to_string())));
+    assert!(queue.result_state("missing").unwrap() == ResultState::NotFound);
+
+    // Failed job holds its stored error, here of arguments which aren't JSON
+    let uuid = queue.enqueue_for(vec!["{".to_string()], Duration::from_secs(10)).unwrap();
+    queue.work_value(|_, _| Ok("ok".to_string()),
+                     &WorkOptions {
+                         wait: Duration::from_secs(1),
+                         freq: 10,
+                         fall: false,
+                         infinite: false,
+                         ..Default::default()
+                     })
+        .unwrap();
+    match queue.result_state(&uuid).unwrap() {
+        ResultState::Failed(Some(error)) => assert!(!error.is_empty()),
+        state => panic!("unexpected state {:?}", state),
+    }
+}
+
+#[test]
+fn test_job_label() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-job-label");
+    queue.drop().unwrap();
+
+    let labeled = queue.enqueue_labeled("send-welcome-email",
+                                        vec!["a".to_string()],
+                                        Duration::from_secs(10))
+        .unwrap();
+    let plain = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    assert!(queue.label(&labeled).unwrap() == Some("send-welcome-email".to_string()));
+    assert!(queue.label(&plain).unwrap().is_none());
+
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        freq: 10,
+                        fall: false,
+                        infinite: false,
+                        ..Default::default()
+                    })
+        .unwrap();
+    assert!(queue.status(&labeled).unwrap() == Status::FINISHED);
+    assert!(queue.label(&labeled).unwrap() == Some("send-welcome-email".to_string()));
+}
+
+#[test]
+fn test_executor() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-executor");
+    queue.drop().unwrap();
+
+    let spawned = Arc::new(Mutex::new(0));
+    let counter = spawned.clone();
+    let executor = move |job: Box<dyn FnOnce() + Send>| {
+        *counter.lock().unwrap() += 1;
+        std::thread::spawn(job);
+    };
+
+    let uuids: Vec<String> = (0..2)
+        .map(|_| queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap())
+        .collect();
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        freq: 10,
+                        fall: false,
+                        max_jobs: Some(2),
+                        executor: Some(Arc::new(executor)),
+                        ..Default::default()
+                    })
+        .unwrap();
+    for uuid in uuids.iter() {
+        assert!(queue.status(uuid).unwrap() == Status::FINISHED);
+    }
+    assert!(*spawned.lock().unwrap() == 2);
+}
+
+#[test]
+fn test_drain_until() {
+    fn fn_drain(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        sleep(Duration::from_millis(300));
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-drain-until");
+    queue.drop().unwrap();
+
+    for _ in 0..5 {
+        queue.enqueue_for(vec![], Duration::from_secs(30)).unwrap();
+    }
+    let options = WorkOptions {
+        wait: Duration::from_secs(5),
+        freq: 20,
+        fall: false,
+        ..Default::default()
+    };
+
+    // The running job is completed past the deadline, the others are left queued
+    let started = Instant::now();
+    let summary = queue.drain_until(fn_drain, &options, Duration::from_millis(500)).unwrap();
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(summary.timed_out);
+    assert!(summary.work.exit == WorkerExit::Deadline);
+    assert!(summary.work.finished == summary.work.processed);
+    assert!(summary.work.processed >= 1 && summary.work.processed < 5);
+    assert!(summary.remaining == 5 - summary.work.processed);
+
+    // An empty queue ends draining without waiting for the deadline
+    let remaining = summary.remaining;
+    let started = Instant::now();
+    let summary = queue.drain_until(fn_drain, &options, Duration::from_secs(30)).unwrap();
+    assert!(started.elapsed() < Duration::from_secs(10));
+    assert!(summary ==
+            DrainSummary {
+                work: WorkSummary {
+                    processed: remaining,
+                    finished: remaining,
+                    exit: WorkerExit::Drained,
+                    ..Default::default()
+                },
+                remaining: 0,
+                timed_out: false,
+            });
+}
+
+#[test]
+fn test_job_value() {
+    fn fn_value(_: String, value: serde_json::Value) -> Result<String, Box<dyn Error>> {
+        Ok(value.to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-job-value");
+    queue.drop().unwrap();
+
+    let value: serde_json::Value = serde_json::from_str(r#"{"ids":[],"n":12345678901234567}"#)
+        .unwrap();
+    let uuid = queue.enqueue_value(value.clone(), Duration::from_secs(10)).unwrap();
+    let plain = queue.enqueue_for(vec!["a".to_string(), "b".to_string()],
+                                  Duration::from_secs(10))
+        .unwrap();
+    let work = || {
+        queue.work_value(fn_value,
+                         &WorkOptions {
+                             wait: Duration::from_secs(1),
+                             freq: 10,
+                             fall: false,
+                             infinite: false,
+                             ..Default::default()
+                         })
+            .unwrap()
+    };
+
+    work();
+    assert!(queue.status(&uuid).unwrap() == Status::FINISHED);
+    assert!(queue.result(&uuid).unwrap() == Some(value.to_string()));
+
+    // Jobs with arguments other than a single JSON value fail
+    work();
+    assert!(queue.status(&plain).unwrap() == Status::FAILED);
+}
+
+#[test]
+fn test_job_args_decode() {
+    fn fn_sum(_: String, numbers: Vec<u32>) -> Result<String, Box<dyn Error>> {
+        Ok(numbers.iter().sum::<u32>().to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-args-decode");
+    queue.drop().unwrap();
+
+    let summed = queue.enqueue_json(&vec![1, 2], Duration::from_secs(10)).unwrap();
+    let text = queue.enqueue_json(&"text", Duration::from_secs(10)).unwrap();
+    let plain = queue.enqueue_for(vec!["1".to_string(), "2".to_string()],
+                                  Duration::from_secs(10))
+        .unwrap();
+    let summary = queue.work_json(fn_sum,
+                                  &WorkOptions {
+                                      wait: Duration::from_secs(1),
+                                      freq: 10,
+                                      fall: false,
+                                      max_jobs: Some(3),
+                                      ..Default::default()
+                                  })
+        .unwrap();
+
+    assert!(summary.finished == 1 && summary.failed == 2);
+    assert!(queue.result(&summed).unwrap() == Some("3".to_string()));
+    for uuid in [&text, &plain] {
+        assert!(queue.status(uuid).unwrap() == Status::FAILED);
+        let result = queue.result(uuid).unwrap().unwrap();
+        assert!(result.starts_with("args decode error: "), "{}", result);
+    }
+}
+
+#[test]
+fn test_job_named() {
+    fn fn_greet(_: String, args: BTreeMap<String, String>) -> Result<String, Box<dyn Error>> {
+        Ok(format!("{} {}", args["greeting"], args["name"]))
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-job-named");
+    queue.drop().unwrap();
+
+    let mut args = BTreeMap::new();
+    args.insert("name".to_string(), "world".to_string());
+    args.insert("greeting".to_string(), "hello".to_string());
+    let named = queue.enqueue_named(args.clone(), Duration::from_secs(10)).unwrap();
+    let plain = queue.enqueue_for(vec!["hello".to_string(), "world".to_string()],
+                                  Duration::from_secs(10))
+        .unwrap();
+    let summary = queue.work_named(fn_greet,
+                                   &WorkOptions {
+                                       wait: Duration::from_secs(1),
+                                       freq: 10,
+                                       fall: false,
+                                       max_jobs: Some(2),
+                                       ..Default::default()
+                                   })
+        .unwrap();
+
+    assert!(summary.finished == 1 && summary.failed == 1);
+    assert!(queue.result(&named).unwrap() == Some("hello world".to_string()));
+    assert!(queue.args(&named).unwrap() == Some(vec![serde_json::to_string(&args).unwrap()]));
+    let result = queue.result(&plain).unwrap().unwrap();
+    assert!(result.starts_with("args decode error: "), "{}", result);
+}
+
+#[test]
+fn test_result_or_wait() {
+    fn fn_slow(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        sleep(Duration::from_millis(300));
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-result-or-wait");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let queued = Instant::now();
+    assert!(queue.result_or_wait(&uuid, Duration::from_secs(5)).unwrap().is_none());
+    assert!(queued.elapsed() < Duration::from_secs(1));
+
+    let worker = {
+        let queue = queue.clone();
+        thread::spawn(move || {
+            queue.work_with(fn_slow,
+                            &WorkOptions {
+                                wait: Duration::from_secs(1),
+                                freq: 100,
+                                max_jobs: Some(1),
+                                ..Default::default()
+                            })
+                .unwrap()
+        })
+    };
+    while queue.status(&uuid).unwrap() != Status::RUNNING {
+        sleep(Duration::from_millis(10));
+    }
+
+    assert!(queue.result_or_wait(&uuid, Duration::from_millis(10)).unwrap().is_none());
+    assert!(queue.result_or_wait(&uuid, Duration::from_secs(5)).unwrap() ==
+            Some("ok".to_string()));
+    worker.join().unwrap();
+}
+
+#[test]
+fn test_drop() {
+    let queue = Queue::new("redis://localhost/", "test-drop");
+    queue.drop().unwrap();
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    queue.drop().unwrap();
+
+    assert!(queue.ttl(&uuid).unwrap().is_none());
+}
+
+#[test]
+fn test_drop_safe() {
+    let queue = Queue::new("redis://localhost/", "test-drop-safe");
+    queue.drop().unwrap();
+
+    let queued = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let running = queue.enqueue_with_status(vec![],
+                                            Duration::from_secs(10),
+                                            Status::RUNNING)
+        .unwrap();
+
+    assert!(queue.drop_safe().unwrap() == 1);
+    assert!(queue.ttl(&queued).unwrap().is_none());
+    assert!(queue.status(&running).unwrap() == Status::RUNNING);
+}
+
+#[test]
+fn test_job_delayed() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-delayed");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_delayed(vec![],
+                                     Duration::from_secs(10),
+                                     Duration::from_secs(2))
+        .unwrap();
+    assert!(queue.status(&uuid).unwrap() == Status::QUEUED);
+    assert!(queue.promote().unwrap().is_empty());
+
+    sleep(Duration::from_millis(2500));
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        timeout: Duration::from_secs(5),
+                        expire: Duration::from_secs(5),
+                        fall: false,
+                        infinite: false,
+                        ..Default::default()
+                    })
+        .unwrap();
+    assert!(queue.status(&uuid).unwrap() == Status::FINISHED);
+}
+
+#[test]
+fn test_promote_once() {
+    let queue = Queue::new("redis://localhost/", "test-promote-once");
+    queue.drop().unwrap();
+
+    let uuids: HashSet<String> = (0..200)
+        .map(|_| {
+            queue.enqueue_delayed(vec![], Duration::from_secs(10), Duration::from_secs(1)).unwrap()
+        })
+        .collect();
+    sleep(Duration::from_millis(1500));
+
+    let workers: Vec<_> = (0..2)
+        .map(|_| {
+            thread::spawn(|| {
+                let queue = Queue::new("redis://localhost/", "test-promote-once");
+                let mut promoted = Vec::new();
+                for _ in 0..10 {
+                    promoted.extend(queue.promote().unwrap());
+                }
+                promoted
+            })
+        })
+        .collect();
+    let promoted: Vec<String> = workers.into_iter().flat_map(|o| o.join().unwrap()).collect();
+
+    assert!(promoted.len() == uuids.len());
+    assert!(promoted.into_iter().collect::<HashSet<_>>() == uuids);
+}
+
+#[test]
+fn test_job_with_status() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-with-status");
+    queue.drop().unwrap();
+
+    let running = queue.enqueue_with_status(vec![],
+                                            Duration::from_secs(10),
+                                            Status::RUNNING)
+        .unwrap();
+    let queued = queue.enqueue_with_status(vec![],
+                                           Duration::from_secs(10),
+                                           Status::QUEUED)
+        .unwrap();
+
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        timeout: Duration::from_secs(5),
+                        expire: Duration::from_secs(5),
+                        fall: false,
+                        infinite: false,
+                        ..Default::default()
+                    })
+        .unwrap();
+
+    assert!(queue.status(&running).unwrap() == Status::RUNNING);
+    assert!(queue.status(&queued).unwrap() == Status::FINISHED);
+}
+
 #[test]
 #[should_panic]
 fn test_job_expired() {
@@ -31,97 +478,3446 @@ fn test_job_expired() {
 }
 
 #[test]
-fn test_job_finished() {
-    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<Error>> {
-        sleep(Duration::from_millis(1000));
+fn test_memory_job_expired() {
+    let queue = MemoryQueue::new();
+    let uuid = queue.enqueue(vec![], Duration::from_millis(200)).unwrap();
+    sleep(Duration::from_millis(400));
+
+    assert!(queue.status(&uuid).is_err());
+}
+
+#[test]
+fn test_result_expire_ms() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
         Ok("ok".to_string())
     }
 
-    let queue = Queue::new("redis://localhost/", "test-finished");
+    let queue = Queue::new("redis://localhost/", "test-result-expire-ms");
     queue.drop().unwrap();
 
-    let uuid = queue.enqueue(vec![], 10).unwrap();
-    queue.work(fn_ok,
-              Some(1),
-              Some(5),
-              Some(1),
-              Some(5),
-              Some(false),
-              Some(false))
+    let uuid = queue.enqueue_for(vec![], Duration::from_millis(500)).unwrap();
+    let pttl: i64 = queue.with_connection(|conn| {
+            redis::cmd("PTTL").arg(format!("test-result-expire-ms:{}", uuid)).query(conn)
+        })
         .unwrap();
+    assert!(pttl > 0 && pttl <= 500);
 
-    let status = queue.status(&uuid).unwrap();
-    assert!(status == Status::FINISHED);
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_millis(100),
+                        expire: Duration::from_millis(300),
+                        freq: 10,
+                        fall: false,
+                        infinite: false,
+                        ..Default::default()
+                    })
+        .unwrap();
+    assert!(queue.status(&uuid).unwrap() == Status::FINISHED);
+
+    sleep(Duration::from_millis(500));
+    assert!(!queue.exists(&uuid).unwrap());
 }
 
 #[test]
-fn test_job_result() {
-    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<Error>> {
-        sleep(Duration::from_millis(1000));
+fn test_clear_results() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
         Ok("ok".to_string())
     }
 
-    let queue = Queue::new("redis://localhost/", "test-result");
+    let queue = Queue::new("redis://localhost/", "test-clear-results");
     queue.drop().unwrap();
 
-    let uuid = queue.enqueue(vec![], 10).unwrap();
-    queue.work(fn_ok,
-              Some(1),
-              Some(5),
-              Some(1),
-              Some(5),
-              Some(false),
-              Some(false))
+    let finished = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        freq: 10,
+                        expire: Duration::from_secs(60),
+                        fall: false,
+                        infinite: false,
+                        ..Default::default()
+                    })
         .unwrap();
+    let queued = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    assert!(queue.result(&finished).unwrap() == Some("ok".to_string()));
 
-    let res = queue.result(&uuid).unwrap().unwrap();
-    assert!(res == "ok");
+    assert!(queue.clear_results().unwrap() == 1);
+    assert!(queue.clear_results().unwrap() == 0);
+    assert!(queue.status(&finished).unwrap() == Status::FINISHED);
+    assert!(queue.result(&finished).unwrap().is_none());
+    assert!(queue.ttl(&finished).unwrap().is_some_and(|o| o > 10));
+    assert!(queue.status(&queued).unwrap() == Status::QUEUED);
 }
 
 #[test]
-fn test_job_failed() {
-    fn fn_err(_: String, _: Vec<String>) -> Result<String, Box<Error>> {
-        sleep(Duration::from_millis(1000));
-        Err(From::from("err"))
+fn test_retry_failed() {
+    fn fn_flaky(_: String, args: Vec<String>) -> Result<String, Box<dyn Error>> {
+        match args.first() {
+            Some(o) if o == "fail" => Err("failed".into()),
+            _ => Ok("ok".to_string()),
+        }
     }
 
-    let queue = Queue::new("redis://localhost/", "test-failed");
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-retry-failed",
+                                    QueueOptions { retry_limit: 2, ..Default::default() });
     queue.drop().unwrap();
 
-    let uuid = queue.enqueue(vec![], 10).unwrap();
-    queue.work(fn_err,
-              Some(1),
-              Some(5),
-              Some(1),
-              Some(5),
-              Some(false),
-              Some(false))
+    let failed = queue.enqueue_for(vec!["fail".to_string()], Duration::from_secs(10)).unwrap();
+    let finished = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let options = WorkOptions {
+        wait: Duration::from_secs(1),
+        freq: 10,
+        expire: Duration::from_secs(60),
+        fall: false,
+        max_jobs: Some(2),
+        ..Default::default()
+    };
+    queue.work_with(fn_flaky, &options).unwrap();
+    assert!(queue.status(&failed).unwrap() == Status::FAILED);
+
+    assert!(queue.retry_failed().unwrap() == 1);
+    assert!(queue.status(&failed).unwrap() == Status::QUEUED);
+    assert!(queue.status(&finished).unwrap() == Status::FINISHED);
+    assert!(queue.args(&failed).unwrap() == Some(vec!["fail".to_string()]));
+
+    // Second attempt reaches the limit, the job is left failed
+    queue.work_with(fn_flaky, &WorkOptions { max_jobs: Some(1), ..options }).unwrap();
+    assert!(queue.status(&failed).unwrap() == Status::FAILED);
+    assert!(queue.job(&failed).unwrap().unwrap().attempts == 2);
+    assert!(queue.retry_failed().unwrap() == 0);
+    assert!(queue.status(&failed).unwrap() == Status::FAILED);
+}
+
+#[test]
+fn test_job_recurring() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-recurring");
+    queue.drop().unwrap();
+
+    let id = queue.schedule_recurring(vec!["a".to_string()],
+                                      Duration::from_secs(10),
+                                      Duration::from_secs(1))
         .unwrap();
+    let first = queue.promote().unwrap();
+    assert!(first.len() == 1);
+    assert!(queue.promote().unwrap().is_empty());
 
-    let status = queue.status(&uuid).unwrap();
-    assert!(status == Status::FAILED);
+    // Run isn't enqueued while the previous one is still queued
+    sleep(Duration::from_millis(1100));
+    assert!(queue.promote().unwrap().is_empty());
+
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        freq: 10,
+                        fall: false,
+                        infinite: false,
+                        ..Default::default()
+                    })
+        .unwrap();
+    assert!(queue.status(&first[0]).unwrap() == Status::FINISHED);
+    sleep(Duration::from_millis(1100));
+    let second = queue.promote().unwrap();
+    assert!(second.len() == 1 && second != first);
+    assert!(queue.args(&second[0]).unwrap() == Some(vec!["a".to_string()]));
+
+    assert!(queue.unschedule_recurring(&id).unwrap());
+    assert!(!queue.unschedule_recurring(&id).unwrap());
+    sleep(Duration::from_millis(1100));
+    assert!(queue.promote().unwrap().is_empty());
 }
 
 #[test]
-fn test_job_lost() {
-    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<Error>> {
-        sleep(Duration::from_millis(10000));
+fn test_memory_usage() {
+    let queue = Queue::new("redis://localhost/", "test-memory-usage");
+    queue.drop().unwrap();
+    assert!(queue.memory_usage().unwrap() == 0);
+
+    queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let one = queue.memory_usage().unwrap();
+    assert!(one > 0);
+    queue.enqueue_for(vec!["a".repeat(1000)], Duration::from_secs(10)).unwrap();
+    assert!(queue.memory_usage().unwrap() > one + 1000);
+}
+
+#[test]
+fn test_compact() {
+    let queue = Queue::new("redis://localhost/", "test-compact");
+    queue.drop().unwrap();
+
+    queue.enqueue_for(vec![], Duration::from_secs(1)).unwrap();
+    let live = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    queue.enqueue_delayed(vec![], Duration::from_secs(0), Duration::from_secs(1)).unwrap();
+    queue.enqueue_kind("email", vec![], Duration::from_secs(1)).unwrap();
+    sleep(Duration::from_millis(1100));
+
+    assert!(queue.compact().unwrap() == 3);
+    assert!(queue.compact().unwrap() == 0);
+    let client = redis::Client::open("redis://localhost/").unwrap();
+    let conn = client.get_connection().unwrap();
+    let ready: Vec<String> = conn.lrange("test-compact:uuids", 0, -1).unwrap();
+    assert!(ready == vec![live]);
+}
+
+#[test]
+fn test_work_expired() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
         Ok("ok".to_string())
     }
 
-    let queue = Queue::new("redis://localhost/", "test-lost");
+    let queue = Queue::new("redis://localhost/", "test-work-expired");
+    let client = redis::Client::open("redis://localhost/").unwrap();
+    let conn = client.get_connection().unwrap();
+    for &(policy, expired, left) in &[(ExpiredPolicy::Skip, 0, 1),
+                                      (ExpiredPolicy::Report, 1, 1),
+                                      (ExpiredPolicy::Strict, 1, 0)] {
+        queue.drop().unwrap();
+        queue.enqueue_for(vec![], Duration::from_millis(100)).unwrap();
+        let live = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+        queue.enqueue_for(vec![], Duration::from_millis(100)).unwrap();
+        sleep(Duration::from_millis(200));
+
+        let summary = queue.work_with(fn_ok,
+                                      &WorkOptions {
+                                          wait: Duration::from_secs(1),
+                                          freq: 10,
+                                          max_jobs: Some(1),
+                                          expired: policy,
+                                          ..Default::default()
+                                      })
+            .unwrap();
+
+        assert!(summary.finished == 1 && summary.expired == expired, "{:?}", policy);
+        assert!(queue.status(&live).unwrap() == Status::FINISHED);
+        let ready: usize = conn.llen("test-work-expired:uuids").unwrap();
+        assert!(ready == left, "{:?}", policy);
+    }
+}
+
+#[test]
+fn test_enqueue_local() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-enqueue-local");
     queue.drop().unwrap();
 
-    let uuid = queue.enqueue(vec![], 10).unwrap();
-    queue.work(fn_ok,
-              Some(1),
-              Some(5),
-              Some(1),
-              Some(5),
-              Some(false),
-              Some(false))
+    let (sender, receiver) = channel();
+    let done = sender.clone();
+    let finished = queue.enqueue_local(vec![],
+                                       Duration::from_secs(10),
+                                       move |status, result| {
+                                           done.send((status, result)).unwrap();
+                                       })
         .unwrap();
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        freq: 10,
+                        max_jobs: Some(1),
+                        ..Default::default()
+                    })
+        .unwrap();
+    assert!(queue.status(&finished).unwrap() == Status::FINISHED);
+    assert!(receiver.recv_timeout(Duration::from_secs(1)).unwrap() ==
+            (Status::FINISHED, Some("ok".to_string())));
 
-    let status = queue.status(&uuid).unwrap();
-    assert!(status == Status::LOST);
+    let cancelled = queue.enqueue_local(vec![],
+                                        Duration::from_secs(10),
+                                        move |status, result| {
+                                            sender.send((status, result)).unwrap();
+                                        })
+        .unwrap();
+    assert!(queue.cancel(&cancelled).unwrap());
+    assert!(receiver.recv_timeout(Duration::from_secs(1)).unwrap() == (Status::CANCELLED, None));
+    // Closures are run once, the sender is dropped with the last one
+    assert!(receiver.recv().is_err());
+
+    // The closure of a job which expires unclaimed is dropped by a later sweep without being run
+    let (sender, receiver) = channel();
+    queue.enqueue_local(vec![],
+                       Duration::from_millis(10),
+                       move |status, result| {
+                           sender.send((status, result)).unwrap();
+                       })
+        .unwrap();
+    sleep(Duration::from_millis(1100));
+    queue.enqueue_local(vec![], Duration::from_secs(10), |_, _| {}).unwrap();
+    assert!(receiver.recv_timeout(Duration::from_secs(1)) == Err(RecvTimeoutError::Disconnected));
+}
+
+#[test]
+fn test_job_exists() {
+    let queue = Queue::new("redis://localhost/", "test-exists");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    assert!(queue.exists(&uuid).unwrap());
+    assert!(!queue.exists("missing").unwrap());
+}
+
+#[test]
+fn test_job_args() {
+    let queue = Queue::new("redis://localhost/", "test-args");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_for(vec!["a".to_string(), "b".to_string()],
+                                 Duration::from_secs(10))
+        .unwrap();
+    assert_eq!(queue.args(&uuid).unwrap(),
+               Some(vec!["a".to_string(), "b".to_string()]));
+    assert_eq!(queue.args("missing").unwrap(), None);
+}
+
+#[test]
+fn test_discover() {
+    let plain = Queue::new("redis://localhost/", "test-discover-plain");
+    let sharded = Queue::with_options("redis://localhost/",
+                                      "test-discover-sharded",
+                                      QueueOptions {
+                                          shards: 2,
+                                          ..Default::default()
+                                      });
+    let kinded = Queue::new("redis://localhost/", "test-discover-kinded");
+    let empty = Queue::new("redis://localhost/", "test-discover-empty");
+    for queue in [&plain, &sharded, &kinded, &empty] {
+        queue.drop().unwrap();
+    }
+
+    plain.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    for _ in 0..4 {
+        sharded.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    }
+    kinded.enqueue_kind("email", vec![], Duration::from_secs(10)).unwrap();
+
+    assert!(Queue::discover("redis://localhost/", "test-discover-").unwrap() ==
+            vec!["test-discover-kinded", "test-discover-plain", "test-discover-sharded"]);
+    assert!(Queue::discover("redis://localhost/", "test-discover-p").unwrap() ==
+            vec!["test-discover-plain"]);
+}
+
+#[test]
+#[cfg(feature = "http")]
+fn test_job_callback() {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/done", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut request = String::new();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if let Some(o) = line.strip_prefix("Content-Length: ") {
+                length = o.trim().parse().unwrap();
+            }
+            request.push_str(&line);
+            if line == "\r\n" {
+                break;
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+        (request, String::from_utf8(body).unwrap())
+    });
+
+    let queue = Queue::new("redis://localhost/", "test-callback");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_callback(vec![], Duration::from_secs(10), &url).unwrap();
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        freq: 10,
+                        fall: false,
+                        infinite: false,
+                        ..Default::default()
+                    })
+        .unwrap();
+
+    let (request, body) = server.join().unwrap();
+    assert!(request.starts_with("POST /done HTTP/1.1\r\n"));
+    assert!(body ==
+            format!(concat!(r#"{{"uuid":"{}","status":"FINISHED","result":"ok","#,
+                            r#""attempts":1,"max_attempts":1}}"#),
+                    uuid));
+}
+
+#[test]
+fn test_job_args_list() {
+    fn fn_echo(_: String, args: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok(args.join(","))
+    }
+
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-args-list",
+                                    QueueOptions {
+                                        args_list: true,
+                                        ..Default::default()
+                                    });
+    queue.drop().unwrap();
+
+    let args = vec!["say \"hi\"\n".to_string(), "b".to_string()];
+    let uuid = queue.enqueue_for(args.clone(), Duration::from_secs(10)).unwrap();
+    let delayed = queue.enqueue_delayed(args.clone(),
+                                        Duration::from_secs(10),
+                                        Duration::from_secs(0))
+        .unwrap();
+
+    let client = redis::Client::open("redis://localhost/").unwrap();
+    let conn = client.get_connection().unwrap();
+    let json: String = conn.get(format!("test-args-list:{}", uuid)).unwrap();
+    assert!(!json.contains("hi"));
+    let stored: Vec<String> = conn.lrange(format!("test-args-list:{}:args", uuid), 0, -1).unwrap();
+    assert!(stored == args);
+    assert!(queue.args(&uuid).unwrap() == Some(args.clone()));
+
+    queue.work_with(fn_echo,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        freq: 10,
+                        expire: Duration::from_secs(5),
+                        fall: false,
+                        max_jobs: Some(2),
+                        ..Default::default()
+                    })
+        .unwrap();
+    for uuid in [&uuid, &delayed] {
+        assert!(queue.result(uuid).unwrap() == Some(args.join(",")));
+        let ttl: i64 = redis::cmd("TTL")
+            .arg(format!("test-args-list:{}:args", uuid))
+            .query(&conn)
+            .unwrap();
+        assert!(ttl > 0 && ttl <= 5);
+    }
+
+    queue.drop().unwrap();
+}
+
+#[test]
+fn test_job_await() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-await");
+    queue.drop().unwrap();
+
+    let (uuid, completed) = queue.enqueue_await(vec![], Duration::from_secs(10)).unwrap();
+    let (_, expired) = queue.enqueue_await(vec![], Duration::from_secs(1)).unwrap();
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        freq: 10,
+                        fall: false,
+                        infinite: false,
+                        ..Default::default()
+                    })
+        .unwrap();
+
+    let outcome = completed.recv().unwrap();
+    assert!(outcome.status == Status::FINISHED);
+    assert!(outcome.result == Some("ok".to_string()));
+    assert!(outcome.attempts == 1);
+    assert!(queue.status(&uuid).unwrap() == Status::FINISHED);
+    assert!(expired.recv().is_err());
+}
+
+#[test]
+fn test_job_watch() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-watch");
+    queue.drop().unwrap();
+
+    let (uuid, events) = queue.enqueue_watch(vec![], Duration::from_secs(10)).unwrap();
+    let client = redis::Client::open("redis://localhost/").unwrap();
+    let mut pubsub = client.get_pubsub().unwrap();
+    pubsub.subscribe(format!("test-watch:started:{}", uuid)).unwrap();
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        freq: 10,
+                        infinite: false,
+                        ..Default::default()
+                    })
+        .unwrap();
+
+    let message = pubsub.get_message().unwrap();
+    assert!(message.get_channel_name() == format!("test-watch:started:{}", uuid));
+    let published: u64 = message.get_payload().unwrap();
+    match events.recv().unwrap() {
+        JobEvent::Started { enqueued_at, started_at } => {
+            assert!(started_at == Duration::from_millis(published));
+            assert!(enqueued_at.unwrap() <= started_at);
+        }
+        JobEvent::Completed(_) => unreachable!("completed before started"),
+    }
+    let completed = events.recv().unwrap();
+    assert!(completed ==
+            JobEvent::Completed(Outcome {
+                status: Status::FINISHED,
+                result: Some("ok".to_string()),
+                attempts: 1,
+            }));
+    assert!(events.recv().is_err());
+}
+
+#[test]
+fn test_job_json() {
+    struct Unserializable;
+
+    impl Serialize for Unserializable {
+        fn serialize<S: Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+            Err(ser::Error::custom("unserializable"))
+        }
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-json");
+    queue.drop().unwrap();
+
+    let err = queue.enqueue_json(&Unserializable, Duration::from_secs(10)).unwrap_err();
+    assert!(matches!(err.downcast_ref::<RjqError>(), Some(RjqError::Serialize(_))));
+
+    let client = redis::Client::open("redis://localhost/").unwrap();
+    let conn = client.get_connection().unwrap();
+    let listed: usize = conn.llen("test-json:uuids").unwrap();
+    assert!(listed == 0);
+
+    let uuid = queue.enqueue_json(&("a", 1), Duration::from_secs(10)).unwrap();
+    assert!(queue.status(&uuid).unwrap() == Status::QUEUED);
+}
+
+#[test]
+fn test_job_ttl() {
+    let queue = Queue::new("redis://localhost/", "test-ttl");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(5)).unwrap();
+
+    let ttl = queue.ttl(&uuid).unwrap().unwrap();
+    assert!(ttl > 0 && ttl <= 5);
+    assert!(queue.ttl("missing").unwrap().is_none());
+}
+
+#[test]
+fn test_custom_backend() {
+    struct Recorder {
+        enqueued: Mutex<Vec<Vec<String>>>,
+    }
+
+    impl Backend for Recorder {
+        fn enqueue(&self, args: Vec<String>, _: Duration) -> Result<String, Box<dyn Error>> {
+            let mut enqueued = self.enqueued.lock().unwrap();
+            enqueued.push(args);
+            Ok(enqueued.len().to_string())
+        }
+
+        fn status(&self, _: &str) -> Result<Status, Box<dyn Error>> {
+            Ok(Status::QUEUED)
+        }
+
+        fn result(&self, _: &str) -> Result<Option<String>, Box<dyn Error>> {
+            Ok(None)
+        }
+
+        fn cancel(&self, _: &str) -> Result<bool, Box<dyn Error>> {
+            Ok(false)
+        }
+
+        fn work<F>(&self, _: F, _: &WorkOptions) -> Result<WorkSummary, Box<dyn Error>>
+            where F: Fn(String, Vec<String>) -> Result<String, Box<dyn Error>> + Send + Sync
+        {
+            Ok(WorkSummary::default())
+        }
+    }
+
+    fn submit<B: Backend>(queue: B, report: &str) -> String {
+        queue.enqueue(vec![report.to_string()], Duration::from_secs(10)).unwrap()
+    }
+
+    let recorder = Recorder { enqueued: Mutex::new(Vec::new()) };
+    submit(&recorder, "daily");
+    submit(Box::new(&recorder), "weekly");
+    assert!(*recorder.enqueued.lock().unwrap() ==
+            vec![vec!["daily".to_string()], vec!["weekly".to_string()]]);
+
+    // Shared backend is seen by both the code under test and the test
+    let queue = Arc::new(MemoryQueue::new());
+    let uuid = submit(queue.clone(), "monthly");
+    assert!(queue.status(&uuid).unwrap() == Status::QUEUED);
+}
+
+#[test]
+fn test_custom_store() {
+    #[derive(Default)]
+    struct Fake {
+        keys: Mutex<HashMap<String, String>>,
+    }
+
+    impl Store for Fake {
+        fn get(&self, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+            Ok(self.keys.lock().unwrap().get(key).cloned())
+        }
+    }
+
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-store");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        fall: false,
+                        infinite: false,
+                        ..Default::default()
+                    })
+        .unwrap();
+    let client = redis::Client::open("redis://localhost/").unwrap();
+    let conn = client.get_connection().unwrap();
+    let key = format!("test-store:{}", uuid);
+    let json: String = conn.get(&key).unwrap();
+    assert!(RedisStore::new(&conn).get(&key).unwrap() == Some(json.clone()));
+    assert!(RedisStore::new(&conn).get("test-store:missing").unwrap().is_none());
+
+    // Reads run on the fake alone, nothing listens on the queue url
+    let fake = Arc::new(Fake::default());
+    fake.keys.lock().unwrap().insert(key, json);
+    fake.keys.lock().unwrap().insert("test-store:worker:w1:current".to_string(), uuid.clone());
+    let faked = Queue::with_store("redis://localhost:6380/",
+                                  "test-store",
+                                  QueueOptions::default(),
+                                  fake.clone());
+    assert!(faked.status(&uuid).unwrap() == Status::FINISHED);
+    assert!(faked.result(&uuid).unwrap() == Some("ok".to_string()));
+    assert!(faked.result_state(&uuid).unwrap() == ResultState::Done(Some("ok".to_string())));
+    assert!(faked.current_job("w1").unwrap() == Some(uuid.clone()));
+    assert!(faked.status("missing").is_err());
+    assert!(faked.result_state("missing").unwrap() == ResultState::NotFound);
+    assert!(faked.resume().is_err());
+
+    // Pausing changes the queue, so it runs on Redis whatever the store
+    let faked = Queue::with_store("redis://localhost/",
+                                  "test-store",
+                                  QueueOptions::default(),
+                                  fake);
+    faked.pause().unwrap();
+    assert!(faked.is_paused().unwrap() && queue.is_paused().unwrap());
+    faked.resume().unwrap();
+    assert!(!faked.is_paused().unwrap() && !queue.is_paused().unwrap());
+}
+
+#[test]
+fn test_job_finished() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<Error>> {
+        sleep(Duration::from_millis(1000));
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-finished");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue(vec![], 10).unwrap();
+    queue.work(fn_ok,
+              Some(1),
+              Some(5),
+              Some(1),
+              Some(5),
+              Some(false),
+              Some(false))
+        .unwrap();
+
+    let status = queue.status(&uuid).unwrap();
+    assert!(status == Status::FINISHED);
+}
+
+#[test]
+fn test_memory_job_finished() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = MemoryQueue::new();
+    let uuid = queue.enqueue(vec![], Duration::from_secs(10)).unwrap();
+    queue.work(fn_ok,
+               &WorkOptions {
+                   wait: Duration::from_secs(1),
+                   timeout: Duration::from_secs(5),
+                   freq: 1,
+                   expire: Duration::from_secs(5),
+                   fall: false,
+                   infinite: false,
+                   ..Default::default()
+               })
+        .unwrap();
+
+    assert!(queue.status(&uuid).unwrap() == Status::FINISHED);
+}
+
+#[test]
+fn test_job_result() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<Error>> {
+        sleep(Duration::from_millis(1000));
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-result");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue(vec![], 10).unwrap();
+    queue.work(fn_ok,
+              Some(1),
+              Some(5),
+              Some(1),
+              Some(5),
+              Some(false),
+              Some(false))
+        .unwrap();
+
+    let res = queue.result(&uuid).unwrap().unwrap();
+    assert!(res == "ok");
+}
+
+#[test]
+fn test_memory_job_result() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = MemoryQueue::new();
+    let uuid = queue.enqueue(vec![], Duration::from_secs(10)).unwrap();
+    queue.work(fn_ok,
+               &WorkOptions {
+                   wait: Duration::from_secs(1),
+                   timeout: Duration::from_secs(5),
+                   freq: 1,
+                   expire: Duration::from_secs(5),
+                   fall: false,
+                   infinite: false,
+                   ..Default::default()
+               })
+        .unwrap();
+
+    assert!(queue.result(&uuid).unwrap().unwrap() == "ok");
+}
+
+#[test]
+fn test_job_result_truncated() {
+    fn fn_long(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("x".repeat(100))
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-result-truncated");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    queue.work_with(fn_long,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        timeout: Duration::from_secs(5),
+                        expire: Duration::from_secs(5),
+                        fall: false,
+                        infinite: false,
+                        max_result: Some(10),
+                        ..Default::default()
+                    })
+        .unwrap();
+
+    let res = queue.result(&uuid).unwrap().unwrap();
+    assert!(res == "xxxxxxx...");
+    assert!(queue.result_truncated(&uuid).unwrap());
+
+    // Limits too small for the marker cut the marker as well
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    queue.work_with(fn_long,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        freq: 10,
+                        fall: false,
+                        infinite: false,
+                        max_result: Some(2),
+                        ..Default::default()
+                    })
+        .unwrap();
+    assert!(queue.result(&uuid).unwrap() == Some("..".to_string()));
+    assert!(queue.result_truncated(&uuid).unwrap());
+}
+
+#[test]
+fn test_job_take_result() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("token".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-take-result");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        timeout: Duration::from_secs(5),
+                        freq: 10,
+                        expire: Duration::from_secs(5),
+                        fall: false,
+                        infinite: false,
+                        ..Default::default()
+                    })
+        .unwrap();
+
+    assert!(queue.take_result(&uuid).unwrap() == Some("token".to_string()));
+    assert!(queue.take_result(&uuid).unwrap().is_none());
+    assert!(!queue.exists(&uuid).unwrap());
+}
+
+#[test]
+fn test_job_failed() {
+    fn fn_err(_: String, _: Vec<String>) -> Result<String, Box<Error>> {
+        sleep(Duration::from_millis(1000));
+        Err(From::from("err"))
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-failed");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue(vec![], 10).unwrap();
+    queue.work(fn_err,
+              Some(1),
+              Some(5),
+              Some(1),
+              Some(5),
+              Some(false),
+              Some(false))
+        .unwrap();
+
+    let status = queue.status(&uuid).unwrap();
+    assert!(status == Status::FAILED);
+}
+
+#[test]
+fn test_memory_job_failed() {
+    fn fn_err(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Err(From::from("err"))
+    }
+
+    let queue = MemoryQueue::new();
+    let uuid = queue.enqueue(vec![], Duration::from_secs(10)).unwrap();
+    queue.work(fn_err,
+               &WorkOptions {
+                   wait: Duration::from_secs(1),
+                   timeout: Duration::from_secs(5),
+                   freq: 1,
+                   expire: Duration::from_secs(5),
+                   fall: false,
+                   infinite: false,
+                   ..Default::default()
+               })
+        .unwrap();
+
+    assert!(queue.status(&uuid).unwrap() == Status::FAILED);
+}
+
+#[test]
+fn test_job_keepalive() {
+    fn fn_ok(uuid: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        let client = redis::Client::open("redis://localhost/")?;
+        let conn = client.get_connection()?;
+        let key = format!("test-keepalive:{}", uuid);
+
+        // Job outlives the time to live its key has when it starts
+        let _: () = conn.expire(&key, 2)?;
+        sleep(Duration::from_millis(3000));
+        let exists: bool = conn.exists(&key)?;
+        if !exists {
+            return Err("expired while running".into());
+        }
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-keepalive");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        timeout: Duration::from_secs(5),
+                        freq: 10,
+                        expire: Duration::from_secs(5),
+                        fall: false,
+                        infinite: false,
+                        ..Default::default()
+                    })
+        .unwrap();
+
+    assert!(queue.status(&uuid).unwrap() == Status::FINISHED);
+}
+
+#[test]
+fn test_job_current() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        sleep(Duration::from_millis(1000));
+        Ok("ok".to_string())
+    }
+
+    let queue = Arc::new(Queue::new("redis://localhost/", "test-current"));
+    Queue::drop(&queue).unwrap();
+
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    assert!(queue.current_job("worker-1").unwrap().is_none());
+
+    let worker = {
+        let queue = queue.clone();
+        thread::spawn(move || {
+            queue.work(fn_ok,
+                       &WorkOptions {
+                           wait: Duration::from_secs(1),
+                           timeout: Duration::from_secs(5),
+                           freq: 10,
+                           expire: Duration::from_secs(5),
+                           fall: false,
+                           infinite: false,
+                           worker_id: Some("worker-1".to_string()),
+                           ..Default::default()
+                       })
+                .unwrap()
+        })
+    };
+
+    sleep(Duration::from_millis(500));
+    assert!(queue.current_job("worker-1").unwrap() == Some(uuid.clone()));
+    worker.join().unwrap();
+    assert!(queue.current_job("worker-1").unwrap().is_none());
+    assert!(queue.status(&uuid).unwrap() == Status::FINISHED);
+}
+
+#[test]
+fn test_job_lost() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<Error>> {
+        sleep(Duration::from_millis(10000));
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-lost");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue(vec![], 10).unwrap();
+    queue.work(fn_ok,
+              Some(1),
+              Some(5),
+              Some(1),
+              Some(5),
+              Some(false),
+              Some(false))
+        .unwrap();
+
+    let status = queue.status(&uuid).unwrap();
+    assert!(status == Status::LOST);
+}
+
+#[test]
+fn test_memory_job_lost() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        sleep(Duration::from_millis(10000));
+        Ok("ok".to_string())
+    }
+
+    let queue = MemoryQueue::new();
+    let uuid = queue.enqueue(vec![], Duration::from_secs(10)).unwrap();
+    let started = Instant::now();
+    queue.work(fn_ok,
+               &WorkOptions {
+                   wait: Duration::from_secs(1),
+                   timeout: Duration::from_millis(300),
+                   freq: 10,
+                   expire: Duration::from_secs(5),
+                   fall: false,
+                   infinite: false,
+                   ..Default::default()
+               })
+        .unwrap();
+
+    assert!(queue.status(&uuid).unwrap() == Status::LOST);
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn test_clock_elapsed() {
+    // Wall clock stopped, e.g. set back by the system, while time goes on
+    struct StoppedClock;
+
+    impl Clock for StoppedClock {
+        fn now(&self) -> Duration {
+            Duration::from_secs(1_000_000)
+        }
+
+        fn elapsed(&self) -> Duration {
+            SystemClock.elapsed()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            SystemClock.sleep(duration)
+        }
+    }
+
+    fn fn_slow(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        sleep(Duration::from_millis(3000));
+        Ok("ok".to_string())
+    }
+
+    let before = SystemClock.elapsed();
+    sleep(Duration::from_millis(10));
+    assert!(SystemClock.elapsed() >= before + Duration::from_millis(10));
+
+    // Timeout is counted on the elapsed time, not on the wall clock
+    let queue = MemoryQueue::with_clock(Arc::new(StoppedClock));
+    let uuid = queue.enqueue(vec![], Duration::from_secs(10)).unwrap();
+    let started = Instant::now();
+    let summary = queue.work(fn_slow,
+                             &WorkOptions {
+                                 wait: Duration::from_secs(1),
+                                 timeout: Duration::from_secs(1),
+                                 freq: 10,
+                                 fall: false,
+                                 infinite: false,
+                                 ..Default::default()
+                             })
+        .unwrap();
+    assert!(summary.lost == 1);
+    assert!(queue.status(&uuid).unwrap() == Status::LOST);
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn test_mock_clock() {
+    fn fn_slow(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        sleep(Duration::from_millis(10000));
+        Ok("ok".to_string())
+    }
+
+    let clock = Arc::new(MockClock::new(Duration::from_secs(1_000_000)));
+    let queue = Arc::new(MemoryQueue::with_clock(clock.clone()));
+    let uuid = queue.enqueue(vec![], Duration::from_secs(10)).unwrap();
+
+    // Job is lost once the clock has been advanced past its timeout, not after real 5 seconds
+    let started = Instant::now();
+    let worker = {
+        let queue = queue.clone();
+        thread::spawn(move || {
+            queue.work(fn_slow,
+                       &WorkOptions {
+                           wait: Duration::from_secs(1),
+                           timeout: Duration::from_secs(5),
+                           freq: 1,
+                           fall: false,
+                           infinite: false,
+                           ..Default::default()
+                       })
+                .unwrap()
+        })
+    };
+    while !worker.is_finished() {
+        clock.advance(Duration::from_secs(1));
+        sleep(Duration::from_millis(1));
+    }
+    assert!(worker.join().unwrap().lost == 1);
+    assert!(queue.status(&uuid).unwrap() == Status::LOST);
+    assert!(started.elapsed() < Duration::from_secs(2));
+
+    // Job expires by the clock as well
+    let uuid = queue.enqueue(vec![], Duration::from_secs(10)).unwrap();
+    clock.advance(Duration::from_secs(10));
+    assert!(queue.status(&uuid).is_err());
+
+    // Delayed job is due by the queue clock
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-mock-clock",
+                                    QueueOptions { clock: clock.clone(), ..Default::default() });
+    queue.drop().unwrap();
+    let uuid = queue.enqueue_delayed(vec![],
+                                     Duration::from_secs(10),
+                                     Duration::from_secs(60))
+        .unwrap();
+    assert!(queue.promote().unwrap().is_empty());
+    clock.advance(Duration::from_secs(60));
+    assert!(queue.promote().unwrap() == vec![uuid]);
+}
+
+#[test]
+fn test_job_fall() {
+    fn fn_slow(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        sleep(Duration::from_millis(1500));
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-fall");
+    queue.drop().unwrap();
+
+    let lost = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let queued = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let err = queue.work_with(fn_slow,
+                              &WorkOptions {
+                                  wait: Duration::from_secs(1),
+                                  timeout: Duration::from_secs(1),
+                                  freq: 10,
+                                  expire: Duration::from_secs(5),
+                                  on_fall: FallPolicy::Error,
+                                  ..Default::default()
+                              })
+        .unwrap_err();
+    match err.downcast_ref::<RjqError>() {
+        Some(RjqError::JobLost { uuid, args }) => assert!(*uuid == lost && args.is_empty()),
+        _ => panic!("unexpected error {}", err),
+    }
+    assert!(err.to_string().contains(&lost));
+    assert!(queue.status(&lost).unwrap() == Status::LOST);
+    assert!(queue.status(&queued).unwrap() == Status::QUEUED);
+
+    let fallen = Arc::new(Mutex::new(Vec::new()));
+    let callback = {
+        let fallen = fallen.clone();
+        FallPolicy::Callback(Arc::new(move |uuid: &str| {
+            fallen.lock().unwrap().push(uuid.to_string())
+        }))
+    };
+    queue.work_with(fn_slow,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        timeout: Duration::from_secs(1),
+                        freq: 10,
+                        expire: Duration::from_secs(5),
+                        infinite: false,
+                        on_fall: callback,
+                        ..Default::default()
+                    })
+        .unwrap();
+    assert!(*fallen.lock().unwrap() == vec![queued]);
+}
+
+#[test]
+fn test_job_lost_late_result() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        sleep(Duration::from_millis(2000));
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-lost-late");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        timeout: Duration::from_secs(1),
+                        expire: Duration::from_secs(10),
+                        fall: false,
+                        infinite: false,
+                        ..Default::default()
+                    })
+        .unwrap();
+    assert!(queue.status(&uuid).unwrap() == Status::LOST);
+
+    // Result arriving after the timeout is discarded, the job stays lost
+    sleep(Duration::from_millis(2000));
+    assert!(queue.status(&uuid).unwrap() == Status::LOST);
+    assert!(queue.result(&uuid).unwrap().is_none());
+}
+
+#[test]
+fn test_job_lost_requeue() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        sleep(Duration::from_millis(3000));
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-lost-requeue");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let work = || {
+        queue.work_with(fn_ok,
+                        &WorkOptions {
+                            wait: Duration::from_secs(1),
+                            timeout: Duration::from_secs(1),
+                            freq: 10,
+                            expire: Duration::from_secs(5),
+                            fall: false,
+                            infinite: false,
+                            lost: LostPolicy::Requeue { max_attempts: 2 },
+                            ..Default::default()
+                        })
+            .unwrap()
+    };
+
+    work();
+    assert!(queue.status(&uuid).unwrap() == Status::QUEUED);
+
+    work();
+    assert!(queue.status(&uuid).unwrap() == Status::LOST);
+}
+
+#[test]
+fn test_job_attempts() {
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    // Flaky dependency, the first attempt hangs past the timeout
+    fn fn_flaky(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        if CALLS.fetch_add(1, Ordering::SeqCst) == 0 {
+            sleep(Duration::from_millis(500));
+        }
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-attempts");
+    queue.drop().unwrap();
+
+    let (uuid, completed) = queue.enqueue_await(vec![], Duration::from_secs(10)).unwrap();
+    let info = queue.job(&uuid).unwrap().unwrap();
+    assert!(info.status == Status::QUEUED);
+    assert!(info.attempts == 0);
+    assert!(info.max_attempts.is_none());
+
+    queue.work_with(fn_flaky,
+                    &WorkOptions {
+                        timeout: Duration::from_millis(100),
+                        wait: Duration::from_secs(1),
+                        freq: 20,
+                        fall: false,
+                        max_jobs: Some(2),
+                        lost: LostPolicy::Requeue { max_attempts: 3 },
+                        ..Default::default()
+                    })
+        .unwrap();
+
+    let info = queue.job(&uuid).unwrap().unwrap();
+    assert!(info.uuid == uuid);
+    assert!(info.status == Status::FINISHED);
+    assert!(info.result == Some("ok".to_string()));
+    assert!(info.attempts == 2);
+    assert!(info.max_attempts == Some(3));
+    // Completion tells the attempts as well, the requeued one didn't complete the job
+    let outcome = completed.recv().unwrap();
+    assert!(outcome.status == Status::FINISHED && outcome.attempts == 2);
+
+    assert!(queue.job("missing").unwrap().is_none());
+}
+
+#[test]
+fn test_job_attempts_shared() {
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    fn fn_hang(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        sleep(Duration::from_millis(300));
+        Ok("ok".to_string())
+    }
+
+    // Two worker instances, the way two processes of a pool see the queue
+    let first = Queue::new("redis://localhost/", "test-attempts-shared");
+    let second = Queue::new("redis://localhost/", "test-attempts-shared");
+    first.drop().unwrap();
+    let _: () = first.with_connection(|conn| conn.del("test-attempts-shared:dead")).unwrap();
+    let work = |queue: &Queue, worker: &str, max_attempts: usize| {
+        queue.work_with(fn_hang,
+                        &WorkOptions {
+                            timeout: Duration::from_millis(100),
+                            wait: Duration::from_secs(1),
+                            freq: 20,
+                            fall: false,
+                            max_jobs: Some(1),
+                            worker_id: Some(worker.to_string()),
+                            lost: LostPolicy::Requeue { max_attempts },
+                            ..Default::default()
+                        })
+            .unwrap()
+    };
+
+    // The second worker makes the last attempt
+    let uuid = first.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    work(&first, "first", 2);
+    assert!(second.status(&uuid).unwrap() == Status::QUEUED);
+    assert!(work(&second, "second", 2).lost == 1);
+    let info = first.job(&uuid).unwrap().unwrap();
+    assert!(info.status == Status::LOST && info.attempts == 2);
+    assert!(first.dead().unwrap() == vec![uuid.clone()]);
+    assert!(CALLS.load(Ordering::SeqCst) == 2);
+
+    // Attempts used up by a worker allowing more aren't run again
+    let exhausted = first.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    work(&first, "first", 3);
+    assert!(work(&second, "second", 1).lost == 1);
+    let info = second.job(&exhausted).unwrap().unwrap();
+    assert!(info.status == Status::LOST && info.attempts == 1);
+    assert!(first.dead().unwrap() == vec![uuid, exhausted]);
+    assert!(CALLS.load(Ordering::SeqCst) == 3);
+}
+
+#[test]
+fn test_job_info() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-job-info");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_tagged(vec!["a".to_string()],
+                                    Duration::from_secs(10),
+                                    vec!["tenant-1".to_string()])
+        .unwrap();
+    let info = queue.job_info(&uuid).unwrap().unwrap();
+    assert!(info.status == Status::QUEUED && info.args == vec!["a".to_string()]);
+    assert!(info.tags == vec!["tenant-1".to_string()] && info.kind.is_none());
+    assert!(info.owner.is_none() && info.started_at.is_none() && info.enqueued_at.is_some());
+    assert!(info.ttl.unwrap() > Duration::from_secs(5));
+    assert!(queue.job(&uuid).unwrap().unwrap().tags == info.tags);
+
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        freq: 10,
+                        expire: Duration::from_secs(3),
+                        max_jobs: Some(1),
+                        worker_id: Some("worker-1".to_string()),
+                        ..Default::default()
+                    })
+        .unwrap();
+    let info = queue.job_info(&uuid).unwrap().unwrap();
+    assert!(info.status == Status::FINISHED && info.result == Some("ok".to_string()));
+    assert!(info.owner == Some("worker-1".to_string()) && info.started_at.is_some());
+    assert!(info.ttl.unwrap() <= Duration::from_secs(3));
+
+    let json = serde_json::to_value(&info).unwrap();
+    assert!(json["status"] == "FINISHED" && json["owner"] == "worker-1");
+    assert!(queue.job_info("missing").unwrap().is_none());
+}
+
+#[test]
+fn test_job_sizes() {
+    let args = vec!["abc".to_string(), "d\"\n".to_string()];
+    for args_list in [false, true] {
+        let queue = Queue::with_options("redis://localhost/",
+                                        "test-job-sizes",
+                                        QueueOptions { args_list, ..Default::default() });
+        queue.drop().unwrap();
+
+        // Escaping grows the stored arguments beyond the payload unless they are stored apart
+        let uuid = queue.enqueue_for(args.clone(), Duration::from_secs(10)).unwrap();
+        let json: String =
+            queue.with_connection(|conn| conn.get(format!("test-job-sizes:{}", uuid))).unwrap();
+        let info = queue.job(&uuid).unwrap().unwrap();
+        assert!(info.payload_bytes == 6);
+        if args_list {
+            assert!(info.stored_bytes == json.len() + 6);
+        } else {
+            assert!(info.stored_bytes == json.len());
+            assert!(json.contains(r#""d\"\n""#));
+        }
+    }
+}
+
+#[test]
+fn test_queue_replicas() {
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-replicas",
+                                    QueueOptions {
+                                        replicas: 1,
+                                        replicas_timeout: Duration::from_millis(10),
+                                        ..Default::default()
+                                    });
+    queue.drop().unwrap();
+
+    // Test server has no replicas, the job is written but not acknowledged
+    let err = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap_err();
+    assert!(matches!(err.downcast_ref::<RjqError>(),
+                     Some(RjqError::Replication { acked: 0, replicas: 1 })));
+    let client = redis::Client::open("redis://localhost/").unwrap();
+    let conn = client.get_connection().unwrap();
+    let ready: usize = conn.llen("test-replicas:uuids").unwrap();
+    assert_eq!(ready, 1);
+
+    let queue = Queue::new("redis://localhost/", "test-replicas");
+    assert!(queue.enqueue_for(vec![], Duration::from_secs(10)).is_ok());
+}
+
+#[test]
+fn test_queue_defaults() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        sleep(Duration::from_millis(2000));
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-defaults",
+                                    QueueOptions {
+                                        expire: Duration::from_secs(7),
+                                        timeout: Duration::from_secs(1),
+                                        retries: 1,
+                                        ..Default::default()
+                                    });
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_default(vec![]).unwrap();
+    let ttl = queue.ttl(&uuid).unwrap().unwrap();
+    assert!(ttl > 0 && ttl <= 7);
+
+    let options = queue.work_options();
+    assert!(options.timeout == Duration::from_secs(1) && options.expire == Duration::from_secs(7));
+    assert!(options.lost == LostPolicy::Requeue { max_attempts: 2 });
+
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        freq: 10,
+                        fall: false,
+                        infinite: false,
+                        ..queue.work_options()
+                    })
+        .unwrap();
+    assert!(queue.status(&uuid).unwrap() == Status::QUEUED);
+}
+
+#[test]
+fn test_job_cancelled() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-cancelled");
+    queue.drop().unwrap();
+
+    let cancelled = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let finished = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    assert!(queue.cancel(&cancelled).unwrap());
+    assert!(!queue.cancel(&cancelled).unwrap());
+    assert!(queue.status(&cancelled).unwrap() == Status::CANCELLED);
+
+    let summary = queue.work_with(fn_ok,
+                                  &WorkOptions {
+                                      wait: Duration::from_secs(1),
+                                      timeout: Duration::from_secs(5),
+                                      expire: Duration::from_secs(5),
+                                      fall: false,
+                                      max_jobs: Some(1),
+                                      ..Default::default()
+                                  })
+        .unwrap();
+
+    assert!(summary ==
+            WorkSummary {
+                processed: 1,
+                finished: 1,
+                cancelled: 1,
+                exit: WorkerExit::MaxJobs,
+                ..Default::default()
+            });
+    assert!(queue.status(&finished).unwrap() == Status::FINISHED);
+    assert!(!queue.cancel(&finished).unwrap());
+}
+
+#[test]
+fn test_memory_job_cancelled() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = MemoryQueue::new();
+    let cancelled = queue.enqueue(vec![], Duration::from_secs(10)).unwrap();
+    let finished = queue.enqueue(vec![], Duration::from_secs(10)).unwrap();
+    assert!(queue.cancel(&cancelled).unwrap());
+    assert!(!queue.cancel(&cancelled).unwrap());
+    assert!(queue.status(&cancelled).unwrap() == Status::CANCELLED);
+
+    let summary = queue.work(fn_ok,
+                             &WorkOptions {
+                                 wait: Duration::from_secs(1),
+                                 timeout: Duration::from_secs(5),
+                                 expire: Duration::from_secs(5),
+                                 fall: false,
+                                 max_jobs: Some(1),
+                                 ..Default::default()
+                             })
+        .unwrap();
+
+    assert!(summary ==
+            WorkSummary {
+                processed: 1,
+                finished: 1,
+                cancelled: 1,
+                exit: WorkerExit::MaxJobs,
+                ..Default::default()
+            });
+    assert!(queue.status(&finished).unwrap() == Status::FINISHED);
+    assert!(!queue.cancel(&finished).unwrap());
+}
+
+#[test]
+fn test_job_reserved() {
+    let queue = Queue::new("redis://localhost/", "test-reserved");
+    queue.drop().unwrap();
+
+    assert!(queue.reserve_for(Duration::from_secs(1)).unwrap().is_none());
+
+    let finished = queue.enqueue_for(vec!["a".to_string()], Duration::from_secs(10)).unwrap();
+    let failed = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+
+    let job = queue.reserve_for(Duration::from_secs(1)).unwrap().unwrap();
+    assert!(job.uuid() == finished);
+    assert!(job.args() == ["a".to_string()]);
+    assert!(queue.status(&finished).unwrap() == Status::RUNNING);
+    job.complete("ok".to_string()).unwrap();
+
+    let job = queue.reserve_for(Duration::from_secs(1)).unwrap().unwrap();
+    assert!(job.uuid() == failed);
+    job.fail("failed".into()).unwrap();
+
+    assert!(queue.status(&finished).unwrap() == Status::FINISHED);
+    assert!(queue.result(&finished).unwrap() == Some("ok".to_string()));
+    assert!(queue.status(&failed).unwrap() == Status::FAILED);
+    assert!(queue.result(&failed).unwrap() == Some("failed".to_string()));
+    assert!(queue.reserve_for(Duration::from_secs(1)).unwrap().is_none());
+
+    // Pooled connection is held by the reserved job, waits longer than the timeout are cut
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-reserved",
+                                    QueueOptions {
+                                        connections: ConnectionStrategy::Pooled(1),
+                                        op_timeout: Some(Duration::from_millis(200)),
+                                        ..Default::default()
+                                    });
+    let started = Instant::now();
+    assert!(queue.reserve_for(Duration::from_secs(1)).unwrap().is_none());
+    assert!(started.elapsed() >= Duration::from_millis(900));
+
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let job = queue.reserve_for(Duration::from_secs(1)).unwrap().unwrap();
+    assert!(job.uuid() == uuid);
+    job.complete("ok".to_string()).unwrap();
+    assert!(queue.status(&uuid).unwrap() == Status::FINISHED);
+}
+
+#[test]
+fn test_job_listed_with_key() {
+    let queue = Queue::new("redis://localhost/", "test-listed");
+    queue.drop().unwrap();
+
+    let uuids: Vec<String> = (0..20)
+        .map(|_| queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap())
+        .collect();
+
+    let client = redis::Client::open("redis://localhost/").unwrap();
+    let conn = client.get_connection().unwrap();
+    let listed: Vec<String> = conn.lrange("test-listed:uuids", 0, -1).unwrap();
+    assert!(listed == uuids);
+    for uuid in listed.iter() {
+        let exists: bool = conn.exists(format!("test-listed:{}", uuid)).unwrap();
+        assert!(exists);
+    }
+}
+
+#[test]
+fn test_job_corrupt() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-corrupt");
+    queue.drop().unwrap();
+
+    let client = redis::Client::open("redis://localhost/").unwrap();
+    let conn = client.get_connection().unwrap();
+    let _: () = conn.del("test-corrupt:dead").unwrap();
+    let _: () = conn.set_ex("test-corrupt:corrupt", "{not json", 10).unwrap();
+    let _: () = conn.rpush("test-corrupt:uuids", "corrupt").unwrap();
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+
+    let summary = queue.work_with(fn_ok,
+                                  &WorkOptions {
+                                      wait: Duration::from_secs(1),
+                                      timeout: Duration::from_secs(5),
+                                      expire: Duration::from_secs(5),
+                                      fall: false,
+                                      max_jobs: Some(2),
+                                      ..Default::default()
+                                  })
+        .unwrap();
+
+    assert!(summary ==
+            WorkSummary {
+                processed: 2,
+                finished: 1,
+                failed: 1,
+                exit: WorkerExit::MaxJobs,
+                ..Default::default()
+            });
+    assert!(queue.dead().unwrap() == vec!["corrupt".to_string()]);
+    assert!(queue.dead_len().unwrap() == 1);
+    assert!(queue.status(&uuid).unwrap() == Status::FINISHED);
+
+    assert!(queue.flush_dead().unwrap() == 1);
+    assert!(queue.dead_len().unwrap() == 0);
+    let exists: bool = conn.exists("test-corrupt:corrupt").unwrap();
+    assert!(!exists);
+    assert!(queue.flush_dead().unwrap() == 0);
+}
+
+#[test]
+fn test_work_partition() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-partition",
+                                    QueueOptions {
+                                        shards: 4,
+                                        ..Default::default()
+                                    });
+    queue.drop().unwrap();
+
+    for _ in 0..40 {
+        queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    }
+    let work = |partition| {
+        queue.work_with(fn_ok,
+                        &WorkOptions {
+                            wait: Duration::from_millis(50),
+                            freq: 100,
+                            infinite: false,
+                            partition: Some(partition),
+                            ..Default::default()
+                        })
+    };
+    assert!(work((2, 2)).is_err());
+    assert!(work((0, 3)).is_err());
+
+    let client = redis::Client::open("redis://localhost/").unwrap();
+    let conn = client.get_connection().unwrap();
+    let lens = || -> Vec<usize> {
+        (0..4).map(|o| conn.llen(format!("test-partition:uuids:{}", o)).unwrap()).collect()
+    };
+    let before = lens();
+    while work((1, 2)).unwrap().processed > 0 {}
+
+    // Partition 1 of 2 is made of shards 1 and 3, the others are left alone
+    assert!(lens() == vec![before[0], 0, before[2], 0]);
+}
+
+#[test]
+fn test_work_freq() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        sleep(Duration::from_millis(100));
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-freq");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let options = |freq| {
+        WorkOptions {
+            wait: Duration::from_secs(1),
+            freq,
+            infinite: false,
+            ..Default::default()
+        }
+    };
+
+    // Zero checks per second is rejected before a job is taken
+    assert!(queue.work_with(fn_ok, &options(0)).is_err());
+    assert!(MemoryQueue::new().work(fn_ok, &options(0)).is_err());
+    assert!(queue.status(&uuid).unwrap() == Status::QUEUED);
+
+    // and more than a thousand are checked once per millisecond
+    let summary = queue.work_with(fn_ok, &options(100_000)).unwrap();
+    assert!(summary.finished == 1);
+    assert!(queue.result(&uuid).unwrap().unwrap() == "ok");
+}
+
+#[test]
+fn test_job_sharded() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-sharded",
+                                    QueueOptions {
+                                        shards: 4,
+                                        ..Default::default()
+                                    });
+    queue.drop().unwrap();
+
+    let mut uuids: Vec<String> = (0..40)
+        .map(|_| queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap())
+        .collect();
+    uuids.push(queue.enqueue_delayed(vec![], Duration::from_secs(10), Duration::from_secs(0))
+        .unwrap());
+
+    let client = redis::Client::open("redis://localhost/").unwrap();
+    let conn = client.get_connection().unwrap();
+    let mut listed = 0;
+    for shard in 0..4 {
+        let len: usize = conn.llen(format!("test-sharded:uuids:{}", shard)).unwrap();
+        assert!(len > 0);
+        listed += len;
+    }
+    assert!(listed == 40);
+
+    let summary = queue.work_with(fn_ok,
+                                  &WorkOptions {
+                                      wait: Duration::from_secs(1),
+                                      timeout: Duration::from_secs(5),
+                                      freq: 100,
+                                      expire: Duration::from_secs(5),
+                                      fall: false,
+                                      max_jobs: Some(41),
+                                      ..Default::default()
+                                  })
+        .unwrap();
+
+    assert!(summary.finished == 41);
+    for uuid in uuids.iter() {
+        assert!(queue.status(uuid).unwrap() == Status::FINISHED);
+    }
+}
+
+#[test]
+fn test_job_pretty() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-pretty",
+                                    QueueOptions {
+                                        pretty: true,
+                                        ..Default::default()
+                                    });
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let client = redis::Client::open("redis://localhost/").unwrap();
+    let conn = client.get_connection().unwrap();
+    let json: String = conn.get(format!("test-pretty:{}", uuid)).unwrap();
+    assert!(json.contains('\n'));
+
+    // Compact jobs written by another producer are read as well
+    let compact = Queue::new("redis://localhost/", "test-pretty")
+        .enqueue_for(vec![], Duration::from_secs(10))
+        .unwrap();
+
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        timeout: Duration::from_secs(5),
+                        freq: 100,
+                        expire: Duration::from_secs(5),
+                        fall: false,
+                        max_jobs: Some(2),
+                        ..Default::default()
+                    })
+        .unwrap();
+    assert!(queue.status(&uuid).unwrap() == Status::FINISHED);
+    assert!(queue.status(&compact).unwrap() == Status::FINISHED);
+}
+
+#[test]
+fn test_job_claimed() {
+    let queue = Queue::new("redis://localhost/", "test-claimed");
+    queue.drop().unwrap();
+
+    let client = redis::Client::open("redis://localhost/").unwrap();
+    let conn = client.get_connection().unwrap();
+    let _: () = conn.del("test-claimed:processing").unwrap();
+
+    let uuids: Vec<String> = (0..3)
+        .map(|_| queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap())
+        .collect();
+
+    // Worker crashes right after reserving a job
+    std::mem::forget(queue.reserve_for(Duration::from_secs(1)).unwrap().unwrap());
+
+    // Every job is either still ready, or claimed and marked as running
+    let ready: Vec<String> = conn.lrange("test-claimed:uuids", 0, -1).unwrap();
+    let processing: Vec<String> = conn.lrange("test-claimed:processing", 0, -1).unwrap();
+    assert!(ready == uuids[1..].to_vec());
+    assert!(processing == vec![uuids[0].clone()]);
+
+    let json: String = conn.get(format!("test-claimed:{}", uuids[0])).unwrap();
+    assert!(json.contains("\"started_at\"") && json.contains("\"owner\""));
+    assert!(queue.status(&uuids[0]).unwrap() == Status::RUNNING);
+
+    let job = queue.reserve_for(Duration::from_secs(1)).unwrap().unwrap();
+    assert!(job.uuid() == uuids[1]);
+    job.complete("ok".to_string()).unwrap();
+    let processing: Vec<String> = conn.lrange("test-claimed:processing", 0, -1).unwrap();
+    assert!(processing == vec![uuids[0].clone()]);
+
+    // A worker blocked on the empty ready list gets a job as soon as it is enqueued, moved to
+    // the processing list and marked as running
+    let _: () = conn.del(&["test-claimed:uuids", "test-claimed:processing"][..]).unwrap();
+    let producer = {
+        let queue = queue.clone();
+        thread::spawn(move || {
+            sleep(Duration::from_millis(200));
+            queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap()
+        })
+    };
+    let started = Instant::now();
+    let job = queue.reserve_for(Duration::from_secs(5)).unwrap().unwrap();
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(job.uuid() == producer.join().unwrap());
+    let processing: Vec<String> = conn.lrange("test-claimed:processing", 0, -1).unwrap();
+    assert!(processing == vec![job.uuid().to_string()]);
+    assert!(queue.status(job.uuid()).unwrap() == Status::RUNNING);
+    job.complete("ok".to_string()).unwrap();
+}
+
+#[test]
+fn test_iter() {
+    let queue = Queue::new("redis://localhost/", "test-iter");
+    queue.drop().unwrap();
+
+    let uuids: Vec<String> = (0..3)
+        .map(|_| queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap())
+        .collect();
+
+    // A job dropped without being completed is put back and yielded again
+    let mut jobs = queue.iter(Duration::from_secs(1));
+    let job = jobs.next().unwrap().unwrap();
+    assert!(job.uuid() == uuids[0]);
+    drop(job);
+    assert!(queue.status(&uuids[0]).unwrap() == Status::QUEUED);
+
+    let mut seen = Vec::new();
+    for job in queue.iter(Duration::from_secs(1)) {
+        let job = job.unwrap();
+        seen.push(job.uuid().to_string());
+        job.complete("ok".to_string()).unwrap();
+    }
+    assert!(seen == uuids);
+    for uuid in uuids.iter() {
+        assert!(queue.status(uuid).unwrap() == Status::FINISHED);
+    }
+
+    // A failed reservation ends the iterator
+    let unreachable = Queue::new("redis://localhost:6380/", "test-iter");
+    let mut jobs = unreachable.iter(Duration::from_secs(1));
+    assert!(jobs.next().unwrap().is_err());
+    assert!(jobs.next().is_none());
+}
+
+#[test]
+fn test_job_prefetch() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-prefetch");
+    queue.drop().unwrap();
+
+    let client = redis::Client::open("redis://localhost/").unwrap();
+    let conn = client.get_connection().unwrap();
+    let _: () = conn.del("test-prefetch:processing").unwrap();
+
+    let uuids: Vec<String> = (0..5)
+        .map(|_| queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap())
+        .collect();
+    let work = |max_jobs| {
+        queue.work_with(fn_ok,
+                        &WorkOptions {
+                            wait: Duration::from_secs(1),
+                            timeout: Duration::from_secs(5),
+                            freq: 10,
+                            expire: Duration::from_secs(5),
+                            fall: false,
+                            max_jobs: Some(max_jobs),
+                            prefetch: 2,
+                            ..Default::default()
+                        })
+            .unwrap()
+    };
+
+    // Jobs prefetched but not started are put back in order when the worker stops
+    work(1);
+    let ready: Vec<String> = conn.lrange("test-prefetch:uuids", 0, -1).unwrap();
+    let processing: usize = conn.llen("test-prefetch:processing").unwrap();
+    assert!(ready == uuids[1..].to_vec());
+    assert!(processing == 0);
+
+    // Job prefetched by a worker which died is recovered
+    let uuid: String = conn.lpop("test-prefetch:uuids").unwrap();
+    let _: () = conn.rpush("test-prefetch:processing", &uuid).unwrap();
+    assert!(queue.recover().unwrap() == 1);
+    assert!(queue.recover().unwrap() == 0);
+
+    work(4);
+    for uuid in uuids.iter() {
+        assert!(queue.status(uuid).unwrap() == Status::FINISHED);
+    }
+    let processing: usize = conn.llen("test-prefetch:processing").unwrap();
+    assert!(processing == 0);
+}
+
+#[test]
+fn test_job_kinds() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-kinds");
+    queue.drop().unwrap();
+
+    let email = queue.enqueue_kind("email", vec![], Duration::from_secs(10)).unwrap();
+    let render = queue.enqueue_kind("render", vec![], Duration::from_secs(10)).unwrap();
+    let plain = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+
+    let summary = queue.work_with(fn_ok,
+                                  &WorkOptions {
+                                      wait: Duration::from_secs(1),
+                                      timeout: Duration::from_secs(5),
+                                      expire: Duration::from_secs(5),
+                                      fall: false,
+                                      infinite: false,
+                                      kinds: Some(vec!["render".to_string()]),
+                                      ..Default::default()
+                                  })
+        .unwrap();
+    assert!(summary.finished == 1);
+
+    assert!(queue.status(&email).unwrap() == Status::QUEUED);
+    assert!(queue.status(&render).unwrap() == Status::FINISHED);
+    assert!(queue.status(&plain).unwrap() == Status::QUEUED);
+
+    queue.drop().unwrap();
+    assert!(queue.ttl(&email).unwrap().is_none());
+}
+
+#[test]
+fn test_job_kinds_fair() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-kinds-fair");
+    queue.drop().unwrap();
+
+    let emails: Vec<String> = (0..2)
+        .map(|_| queue.enqueue_kind("email", vec![], Duration::from_secs(10)).unwrap())
+        .collect();
+    let renders: Vec<String> = (0..2)
+        .map(|_| queue.enqueue_kind("render", vec![], Duration::from_secs(10)).unwrap())
+        .collect();
+
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        timeout: Duration::from_secs(5),
+                        freq: 10,
+                        expire: Duration::from_secs(5),
+                        fall: false,
+                        max_jobs: Some(2),
+                        kinds: Some(vec!["email".to_string(), "render".to_string()]),
+                        fair: true,
+                        ..Default::default()
+                    })
+        .unwrap();
+
+    assert!(queue.status(&emails[0]).unwrap() == Status::FINISHED);
+    assert!(queue.status(&emails[1]).unwrap() == Status::QUEUED);
+    assert!(queue.status(&renders[0]).unwrap() == Status::FINISHED);
+    assert!(queue.status(&renders[1]).unwrap() == Status::QUEUED);
+
+    queue.drop().unwrap();
+}
+
+#[test]
+fn test_latency_percentiles() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        sleep(Duration::from_millis(200));
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-latency");
+    queue.drop().unwrap();
+    let client = redis::Client::open("redis://localhost/").unwrap();
+    let conn = client.get_connection().unwrap();
+    let _: () = conn.del("test-latency:durations").unwrap();
+    assert!(queue.latency_percentiles().unwrap() == LatencyStats::default());
+
+    for _ in 0..3 {
+        queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    }
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        freq: 100,
+                        fall: false,
+                        max_jobs: Some(3),
+                        ..Default::default()
+                    })
+        .unwrap();
+
+    let stats = queue.latency_percentiles().unwrap();
+    assert!(stats.count == 3);
+    assert!(stats.p50 >= Duration::from_millis(200) && stats.p50 < Duration::from_millis(1000));
+    assert!(stats.p50 <= stats.p95 && stats.p95 <= stats.p99);
+}
+
+#[test]
+fn test_lifo() {
+    fn fn_ok(uuid: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok(uuid)
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-lifo");
+    queue.drop().unwrap();
+
+    let uuids: Vec<String> = (0..3)
+        .map(|_| queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap())
+        .collect();
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        freq: 10,
+                        fall: false,
+                        infinite: false,
+                        lifo: true,
+                        ..Default::default()
+                    })
+        .unwrap();
+    assert!(queue.status(&uuids[2]).unwrap() == Status::FINISHED);
+    assert!(queue.status(&uuids[0]).unwrap() == Status::QUEUED);
+
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        freq: 10,
+                        fall: false,
+                        infinite: false,
+                        lifo: true,
+                        prefetch: 1,
+                        ..Default::default()
+                    })
+        .unwrap();
+    assert!(queue.status(&uuids[1]).unwrap() == Status::FINISHED);
+    assert!(queue.status(&uuids[0]).unwrap() == Status::QUEUED);
+}
+
+#[test]
+fn test_enqueue_front() {
+    fn fn_ok(uuid: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok(uuid)
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-enqueue-front");
+    queue.drop().unwrap();
+
+    let first = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let second = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let urgent = queue.enqueue_front(vec![], Duration::from_secs(10)).unwrap();
+    let listed: Vec<String> =
+        queue.with_connection(|conn| conn.lrange("test-enqueue-front:uuids", 0, -1)).unwrap();
+    assert!(listed == vec![urgent.clone(), first.clone(), second.clone()]);
+
+    let options = WorkOptions {
+        wait: Duration::from_secs(1),
+        freq: 10,
+        fall: false,
+        infinite: false,
+        ..Default::default()
+    };
+    assert!(queue.work_with(fn_ok, &options).unwrap().exit == WorkerExit::Once);
+    assert!(queue.status(&urgent).unwrap() == Status::FINISHED);
+    assert!(queue.status(&first).unwrap() == Status::QUEUED);
+    queue.work_with(fn_ok, &options).unwrap();
+    assert!(queue.status(&first).unwrap() == Status::FINISHED);
+    assert!(queue.status(&second).unwrap() == Status::QUEUED);
+}
+
+#[test]
+fn test_work_prioritized() {
+    fn fn_ok(uuid: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok(uuid)
+    }
+
+    let queues = vec![Queue::new("redis://localhost/", "test-prioritized-urgent"),
+                      Queue::new("redis://localhost/", "test-prioritized-normal"),
+                      Queue::new("redis://localhost/", "test-prioritized-bulk")];
+    for queue in queues.iter() {
+        queue.drop().unwrap();
+    }
+    let (urgent, normal, bulk) = (&queues[0], &queues[1], &queues[2]);
+
+    let bulk_jobs: Vec<String> =
+        (0..2).map(|_| bulk.enqueue_for(vec![], Duration::from_secs(10)).unwrap()).collect();
+    let normal_jobs: Vec<String> =
+        (0..2).map(|_| normal.enqueue_for(vec![], Duration::from_secs(10)).unwrap()).collect();
+    let urgent_jobs: Vec<String> =
+        (0..2).map(|_| urgent.enqueue_for(vec![], Duration::from_secs(10)).unwrap()).collect();
+
+    let options = |max_jobs| {
+        WorkOptions {
+            wait: Duration::from_secs(1),
+            freq: 10,
+            fall: false,
+            max_jobs: Some(max_jobs),
+            ..Default::default()
+        }
+    };
+    let summary = Queue::work_prioritized(&queues, fn_ok, &options(2)).unwrap();
+    assert!(summary.finished == 2);
+    for uuid in urgent_jobs.iter() {
+        assert!(urgent.status(uuid).unwrap() == Status::FINISHED);
+    }
+    for uuid in normal_jobs.iter() {
+        assert!(normal.status(uuid).unwrap() == Status::QUEUED);
+    }
+
+    // An urgent job enqueued after the lower ones still goes first
+    let late = urgent.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    Queue::work_prioritized(&queues, fn_ok, &options(1)).unwrap();
+    assert!(urgent.status(&late).unwrap() == Status::FINISHED);
+    assert!(normal.status(&normal_jobs[0]).unwrap() == Status::QUEUED);
+
+    Queue::work_prioritized(&queues, fn_ok, &options(3)).unwrap();
+    for uuid in normal_jobs.iter() {
+        assert!(normal.status(uuid).unwrap() == Status::FINISHED);
+    }
+    assert!(bulk.status(&bulk_jobs[0]).unwrap() == Status::FINISHED);
+    assert!(bulk.status(&bulk_jobs[1]).unwrap() == Status::QUEUED);
+
+    // Jobs of a paused queue are passed over
+    urgent.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    urgent.pause().unwrap();
+    Queue::work_prioritized(&queues, fn_ok, &options(1)).unwrap();
+    assert!(bulk.status(&bulk_jobs[1]).unwrap() == Status::FINISHED);
+    urgent.resume().unwrap();
+    Queue::work_prioritized(&queues, fn_ok, &options(1)).unwrap();
+
+    // A worker blocked on all the empty queues takes a job enqueued to a lower one meanwhile
+    let producer = {
+        let bulk = bulk.clone();
+        thread::spawn(move || {
+            sleep(Duration::from_millis(200));
+            bulk.enqueue_for(vec![], Duration::from_secs(10)).unwrap()
+        })
+    };
+    let started = Instant::now();
+    let blocking = WorkOptions { wait: Duration::from_secs(5), ..options(1) };
+    let summary = Queue::work_prioritized(&queues, fn_ok, &blocking).unwrap();
+    assert!(summary.finished == 1 && started.elapsed() < Duration::from_secs(2));
+    assert!(bulk.status(&producer.join().unwrap()).unwrap() == Status::FINISHED);
+
+    let other = Queue::new("redis://localhost:6380/", "test-prioritized-other");
+    let mixed = vec![Queue::new("redis://localhost/", "test-prioritized-urgent"), other];
+    assert!(Queue::work_prioritized(&mixed, fn_ok, &options(1)).is_err());
+    assert!(Queue::work_prioritized(&[], fn_ok, &options(1)).is_err());
+}
+
+#[test]
+fn test_work_defer() {
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+    fn fn_defer(ctx: JobContext, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        if RUNS.fetch_add(1, Ordering::SeqCst) == 0 {
+            return Err(RjqError::Defer(Duration::from_millis(200)).into());
+        }
+        Ok(ctx.attempt.to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-work-defer");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let options = WorkOptions {
+        wait: Duration::from_secs(1),
+        max_jobs: Some(1),
+        ..Default::default()
+    };
+    let summary = queue.work_ctx(fn_defer, &options).unwrap();
+    assert!(summary.deferred == 1 && summary.failed == 0);
+    // Scheduled rather than listed, with the attempt taken back
+    let job = queue.job(&uuid).unwrap().unwrap();
+    assert!(job.status == Status::QUEUED && job.attempts == 0);
+    assert!(queue.promote().unwrap().is_empty());
+
+    let summary = queue.work_ctx(fn_defer, &options).unwrap();
+    assert!(summary.finished == 1);
+    assert!(queue.result(&uuid).unwrap() == Some("1".to_string()));
+}
+
+#[test]
+fn test_work_ctx() {
+    static STOPPED: AtomicBool = AtomicBool::new(false);
+
+    fn fn_ctx(ctx: JobContext, args: Vec<String>) -> Result<String, Box<dyn Error>> {
+        if args.first().is_some_and(|o| o == "slow") {
+            while !ctx.is_cancelled() {
+                sleep(Duration::from_millis(10));
+            }
+            STOPPED.store(true, Ordering::SeqCst);
+            return Ok("late".to_string());
+        }
+        if ctx.attempt < 2 {
+            return Err("optimistic path failed".into());
+        }
+        let enqueued_at = ctx.enqueued_at.unwrap_or_default().as_millis();
+        Ok(format!("{} {} {}", ctx.attempt, ctx.tags.join(","), enqueued_at))
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-work-ctx");
+    queue.drop().unwrap();
+
+    let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    let tags = vec!["tenant-1".to_string(), "import".to_string()];
+    let uuid = queue.enqueue_tagged(vec![], Duration::from_secs(10), tags).unwrap();
+    let options = WorkOptions {
+        wait: Duration::from_secs(1),
+        timeout: Duration::from_secs(1),
+        freq: 10,
+        fall: false,
+        infinite: false,
+        lost: LostPolicy::Requeue { max_attempts: 2 },
+        ..Default::default()
+    };
+    queue.work_ctx(fn_ctx, &options).unwrap();
+    assert!(queue.status(&uuid).unwrap() == Status::FAILED);
+    assert!(queue.retry_failed().unwrap() == 1);
+    queue.work_ctx(fn_ctx, &options).unwrap();
+    let result = queue.result(&uuid).unwrap().unwrap();
+    let fields: Vec<&str> = result.split(' ').collect();
+    assert!(fields[..2] == ["2", "tenant-1,import"]);
+    let enqueued_at = Duration::from_millis(fields[2].parse().unwrap());
+    assert!(enqueued_at + Duration::from_millis(1) >= before && enqueued_at < before + Duration::from_secs(5));
+
+    // A job past its timeout is told it is cancelled
+    let slow = queue.enqueue_for(vec!["slow".to_string()], Duration::from_secs(10)).unwrap();
+    queue.work_ctx(fn_ctx, &WorkOptions { lost: LostPolicy::Lost, ..options }).unwrap();
+    assert!(queue.status(&slow).unwrap() == Status::LOST);
+    sleep(Duration::from_millis(200));
+    assert!(STOPPED.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_work_weighted() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let high = Queue::new("redis://localhost/", "test-weighted-high");
+    let low = Queue::new("redis://localhost/", "test-weighted-low");
+    for queue in [&high, &low] {
+        queue.drop().unwrap();
+        for _ in 0..40 {
+            queue.enqueue_for(vec![], Duration::from_secs(30)).unwrap();
+        }
+    }
+
+    let options = WorkOptions {
+        wait: Duration::from_secs(1),
+        freq: 100,
+        max_jobs: Some(40),
+        ..Default::default()
+    };
+    let queues = vec![(high.clone(), 3.0), (low.clone(), 1.0)];
+    let summary = Queue::work_weighted(&queues, fn_ok, &options).unwrap();
+    assert!(summary.finished == 40);
+    // About a quarter of the claims go to the low queue, which strict priority would starve
+    let left: usize = low.with_connection(|conn| conn.llen("test-weighted-low:uuids")).unwrap();
+    assert!((20..=38).contains(&left));
+
+    let zero = vec![(high.clone(), 0.0), (low.clone(), 0.0)];
+    assert!(Queue::work_weighted(&zero, fn_ok, &options).is_err());
+    let negative = vec![(high.clone(), 1.0), (low.clone(), -1.0)];
+    assert!(Queue::work_weighted(&negative, fn_ok, &options).is_err());
+    for queue in [&high, &low] {
+        queue.drop().unwrap();
+    }
+}
+
+#[test]
+fn test_work_prioritized_fifo() {
+    fn fn_ok(uuid: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok(uuid)
+    }
+
+    let queues = vec![Queue::new("redis://localhost/", "test-prioritized-fifo-high"),
+                      Queue::new("redis://localhost/", "test-prioritized-fifo-low")];
+    for queue in queues.iter() {
+        queue.drop().unwrap();
+    }
+
+    let low = queues[1].enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let high: Vec<String> = (0..5)
+        .map(|_| queues[0].enqueue_for(vec![], Duration::from_secs(10)).unwrap())
+        .collect();
+
+    // Jobs of the same priority are taken oldest first
+    let options = WorkOptions {
+        wait: Duration::from_secs(1),
+        freq: 10,
+        fall: false,
+        max_jobs: Some(1),
+        ..Default::default()
+    };
+    for (i, uuid) in high.iter().enumerate() {
+        Queue::work_prioritized(&queues, fn_ok, &options).unwrap();
+        assert!(queues[0].status(uuid).unwrap() == Status::FINISHED);
+        for later in high[i + 1..].iter() {
+            assert!(queues[0].status(later).unwrap() == Status::QUEUED);
+        }
+        assert!(queues[1].status(&low).unwrap() == Status::QUEUED);
+    }
+}
+
+#[test]
+fn test_work_controlled() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Arc::new(Queue::new("redis://localhost/", "test-work-controlled"));
+    Queue::drop(&queue).unwrap();
+
+    let (tx, rx) = channel();
+    tx.send(WorkerCommand::Pause).unwrap();
+    let worker = {
+        let queue = queue.clone();
+        thread::spawn(move || {
+            queue.work_controlled(fn_ok,
+                                  &WorkOptions {
+                                      wait: Duration::from_millis(50),
+                                      freq: 20,
+                                      fall: false,
+                                      ..Default::default()
+                                  },
+                                  &rx)
+                .unwrap()
+        })
+    };
+
+    // A paused worker leaves jobs queued until resumed
+    let first = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    sleep(Duration::from_millis(300));
+    assert!(queue.status(&first).unwrap() == Status::QUEUED);
+    tx.send(WorkerCommand::Resume).unwrap();
+    let started = Instant::now();
+    while queue.status(&first).unwrap() != Status::FINISHED {
+        assert!(started.elapsed() < Duration::from_secs(5));
+        sleep(Duration::from_millis(50));
+    }
+
+    // Draining processes the jobs left and stops the worker once there are none
+    let second = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    tx.send(WorkerCommand::Drain).unwrap();
+    let summary = worker.join().unwrap();
+    assert!(summary.finished == 2);
+    assert!(summary.exit == WorkerExit::Drained);
+    assert!(queue.status(&second).unwrap() == Status::FINISHED);
+
+    // Stop is taken before any job
+    let (tx, rx) = channel();
+    tx.send(WorkerCommand::Stop).unwrap();
+    let third = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let summary = queue.work_controlled(fn_ok, &WorkOptions::default(), &rx).unwrap();
+    assert!(summary.processed == 0);
+    assert!(summary.exit == WorkerExit::Stopped);
+    assert!(queue.status(&third).unwrap() == Status::QUEUED);
+}
+
+#[test]
+fn test_work_dry_run() {
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    fn fn_count(_: String, args: Vec<String>) -> Result<String, Box<dyn Error>> {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        match args.first() {
+            Some(o) if o == "fail" => Err("failed".into()),
+            _ => Ok("ok".to_string()),
+        }
+    }
+
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-dry-run",
+                                    QueueOptions { args_list: true, ..Default::default() });
+    queue.drop().unwrap();
+
+    let ok = queue.enqueue_for(vec!["ok".to_string()], Duration::from_secs(10)).unwrap();
+    let failed = queue.enqueue_for(vec!["fail".to_string()], Duration::from_secs(10)).unwrap();
+    let cancelled = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    queue.cancel(&cancelled).unwrap();
+    let options = WorkOptions {
+        wait: Duration::from_secs(1),
+        freq: 10,
+        fall: false,
+        max_jobs: Some(2),
+        worker_id: Some("dry".to_string()),
+        dry_run: true,
+        ..Default::default()
+    };
+    let summary = queue.work_with(fn_count, &options).unwrap();
+    assert!(summary.finished == 1 && summary.failed == 1);
+    assert!(CALLS.load(Ordering::SeqCst) == 2);
+
+    // Nothing is written back, the jobs are left queued in their order
+    for uuid in [&ok, &failed] {
+        let job = queue.job(uuid).unwrap().unwrap();
+        assert!(job.status == Status::QUEUED && job.result.is_none() && job.attempts == 0);
+    }
+    assert!(queue.current_job("dry").unwrap().is_none());
+    let listed: Vec<String> =
+        queue.with_connection(|conn| conn.lrange("test-dry-run:uuids", 0, -1)).unwrap();
+    assert!(listed == vec![cancelled.clone(), ok.clone(), failed.clone()]);
+    let processing: usize =
+        queue.with_connection(|conn| conn.llen("test-dry-run:processing")).unwrap();
+    assert!(processing == 0);
+
+    let summary = queue.work_with(fn_count, &WorkOptions { dry_run: false, ..options.clone() })
+        .unwrap();
+    assert!(summary.cancelled == 1 && summary.finished == 1 && summary.failed == 1);
+    assert!(queue.status(&ok).unwrap() == Status::FINISHED);
+    assert!(queue.status(&failed).unwrap() == Status::FAILED);
+
+    // An expired job at the head is dropped rather than gone round until the wait is over
+    queue.enqueue_for(vec![], Duration::from_millis(10)).unwrap();
+    sleep(Duration::from_millis(50));
+    let ok = queue.enqueue_for(vec!["ok".to_string()], Duration::from_secs(10)).unwrap();
+    let summary = queue.work_with(fn_count, &WorkOptions { max_jobs: Some(1), ..options }).unwrap();
+    assert!(summary.finished == 1);
+    let listed: Vec<String> =
+        queue.with_connection(|conn| conn.lrange("test-dry-run:uuids", 0, -1)).unwrap();
+    assert!(listed == vec![ok]);
+}
+
+#[test]
+fn test_client_name() {
+    static CLIENTS: Mutex<String> = Mutex::new(String::new());
+
+    fn fn_list(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        let queue = Queue::new("redis://localhost/", "test-client-name");
+        let list: String =
+            queue.with_connection(|conn| redis::cmd("CLIENT").arg("LIST").query(conn))?;
+        *CLIENTS.lock().unwrap() = list;
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-client-name",
+                                    QueueOptions {
+                                        connections: ConnectionStrategy::Shared,
+                                        client_name: Some("producer 1".to_string()),
+                                        ..Default::default()
+                                    });
+    queue.drop().unwrap();
+
+    // Spaces are refused by Redis and replaced
+    let name: Option<String> =
+        queue.with_connection(|conn| redis::cmd("CLIENT").arg("GETNAME").query(conn)).unwrap();
+    assert!(name == Some("producer_1".to_string()));
+
+    let options = WorkOptions {
+        wait: Duration::from_secs(1),
+        infinite: false,
+        worker_id: Some("w1".to_string()),
+        ..Default::default()
+    };
+    queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    queue.work_with(fn_list, &options).unwrap();
+    assert!(CLIENTS.lock().unwrap().contains("name=rjq:test-client-name:w1"));
+
+    queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let options = WorkOptions { client_name: Some("billing-7".to_string()), ..options };
+    queue.work_with(fn_list, &options).unwrap();
+    assert!(CLIENTS.lock().unwrap().contains("name=billing-7"));
+}
+
+#[test]
+fn test_max_jobs() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-max-jobs");
+    queue.drop().unwrap();
+
+    let mut uuids = Vec::new();
+    for _ in 0..5 {
+        uuids.push(queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap());
+    }
+    let summary = queue.work_with(fn_ok,
+                                  &WorkOptions {
+                                      wait: Duration::from_secs(1),
+                                      timeout: Duration::from_secs(5),
+                                      freq: 10,
+                                      expire: Duration::from_secs(5),
+                                      fall: false,
+                                      infinite: true,
+                                      max_jobs: Some(3),
+                                      ..Default::default()
+                                  })
+        .unwrap();
+
+    assert!(summary ==
+            WorkSummary {
+                processed: 3,
+                finished: 3,
+                exit: WorkerExit::MaxJobs,
+                ..Default::default()
+            });
+    for uuid in uuids[..3].iter() {
+        assert!(queue.status(uuid).unwrap() == Status::FINISHED);
+    }
+    for uuid in uuids[3..].iter() {
+        assert!(queue.status(uuid).unwrap() == Status::QUEUED);
+    }
+}
+
+#[test]
+fn test_connect_retry() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    // Nothing listens on the port, every attempt fails
+    let queue = Queue::new("redis://localhost:1/", "test-connect-retry");
+    let started = Instant::now();
+    let result = queue.work_with(fn_ok,
+                                 &WorkOptions {
+                                     connect_retry: Some(Retry {
+                                         attempts: 3,
+                                         initial: Duration::from_millis(100),
+                                         max: Duration::from_millis(150),
+                                         multiplier: 2,
+                                     }),
+                                     ..Default::default()
+                                 });
+    assert!(result.is_err());
+    assert!(started.elapsed() >= Duration::from_millis(250));
+}
+
+#[test]
+fn test_idle_backoff() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-backoff");
+    queue.drop().unwrap();
+
+    let producer = thread::spawn(|| {
+        let queue = Queue::new("redis://localhost/", "test-backoff");
+        sleep(Duration::from_millis(3000));
+        queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap()
+    });
+
+    let started = Instant::now();
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        timeout: Duration::from_secs(5),
+                        expire: Duration::from_secs(5),
+                        fall: false,
+                        max_jobs: Some(1),
+                        backoff: Some(Backoff {
+                            after: 1,
+                            initial: Duration::from_millis(500),
+                            max: Duration::from_millis(2000),
+                            multiplier: 4,
+                        }),
+                        ..Default::default()
+                    })
+        .unwrap();
+
+    // Polls: 1s empty + 0.5s sleep, 1s empty + 2s sleep, then the job enqueued at 3s is found
+    assert!(started.elapsed() >= Duration::from_millis(4500));
+    let uuid = producer.join().unwrap();
+    assert!(queue.status(&uuid).unwrap() == Status::FINISHED);
+}
+
+#[test]
+fn test_pause_resume() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-pause");
+    queue.drop().unwrap();
+    queue.resume().unwrap();
+
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+
+    queue.pause().unwrap();
+    assert!(queue.is_paused().unwrap());
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        timeout: Duration::from_secs(5),
+                        freq: 1,
+                        expire: Duration::from_secs(5),
+                        fall: false,
+                        infinite: false,
+                        ..Default::default()
+                    })
+        .unwrap();
+    assert!(queue.status(&uuid).unwrap() == Status::QUEUED);
+
+    queue.resume().unwrap();
+    assert!(!queue.is_paused().unwrap());
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        timeout: Duration::from_secs(5),
+                        freq: 1,
+                        expire: Duration::from_secs(5),
+                        fall: false,
+                        infinite: false,
+                        ..Default::default()
+                    })
+        .unwrap();
+    assert!(queue.status(&uuid).unwrap() == Status::FINISHED);
+}
+
+#[test]
+fn test_job_unique() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-unique");
+    queue.drop().unwrap();
+
+    let args = vec!["a".to_string(), "b".to_string()];
+    let uuid = queue.enqueue_unique(args.clone(), Duration::from_secs(10)).unwrap();
+    assert!(queue.enqueue_unique(args.clone(), Duration::from_secs(10)).unwrap() == uuid);
+    assert!(queue.enqueue_unique(vec!["a".to_string()], Duration::from_secs(10)).unwrap() != uuid);
+
+    queue.work_with(fn_ok,
+                    &WorkOptions {
+                        wait: Duration::from_secs(1),
+                        timeout: Duration::from_secs(5),
+                        freq: 1,
+                        expire: Duration::from_secs(5),
+                        fall: false,
+                        infinite: false,
+                        ..Default::default()
+                    })
+        .unwrap();
+    assert!(queue.status(&uuid).unwrap() == Status::FINISHED);
+
+    assert!(queue.enqueue_unique(args, Duration::from_secs(10)).unwrap() != uuid);
+    assert!(queue.enqueue_unique(vec!["b".to_string()], Duration::from_secs(0)).is_err());
+}
+
+#[test]
+fn test_job_unique_stale() {
+    let queue = Arc::new(Queue::new("redis://localhost/", "test-unique-stale"));
+    Queue::drop(&queue).unwrap();
+    let stale = queue.enqueue_unique(vec!["a".to_string()], Duration::from_secs(10)).unwrap();
+    let _: () = queue.with_connection(|conn| conn.del(format!("test-unique-stale:{}", stale)))
+        .unwrap();
+
+    // Producers racing past a lock which has outlived its job enqueue it once
+    let handles: Vec<_> = (0..10)
+        .map(|_| {
+            let queue = queue.clone();
+            thread::spawn(move || queue.enqueue_unique(vec!["a".to_string()],
+                                                       Duration::from_secs(10))
+                .unwrap())
+        })
+        .collect();
+    let uuids: HashSet<String> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    assert!(uuids.len() == 1 && !uuids.contains(&stale));
+    let ready: usize = queue.with_connection(|conn| conn.llen("test-unique-stale:uuids")).unwrap();
+    assert!(ready == 2);
+}
+
+#[test]
+fn test_job_unique_delayed() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Arc::new(Queue::new("redis://localhost/", "test-unique-delayed"));
+    Queue::drop(&queue).unwrap();
+    let client = redis::Client::open("redis://localhost/").unwrap();
+    let conn = client.get_connection().unwrap();
+
+    let handles: Vec<_> = (0..10)
+        .map(|_| {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                queue.enqueue_unique_delayed(vec!["a".to_string()],
+                                             Duration::from_secs(10),
+                                             Duration::from_secs(1)).unwrap()
+            })
+        })
+        .collect();
+    let uuids: HashSet<String> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    assert!(uuids.len() == 1);
+    let scheduled: usize = conn.zcard("test-unique-delayed:scheduled").unwrap();
+    assert!(scheduled == 1);
+
+    sleep(Duration::from_millis(1100));
+    queue.work(fn_ok,
+               &WorkOptions {
+                   wait: Duration::from_secs(1),
+                   timeout: Duration::from_secs(5),
+                   freq: 10,
+                   expire: Duration::from_secs(5),
+                   fall: false,
+                   infinite: false,
+                   ..Default::default()
+               })
+        .unwrap();
+    let uuid = uuids.into_iter().next().unwrap();
+    assert!(queue.status(&uuid).unwrap() == Status::FINISHED);
+    assert!(queue.enqueue_unique_delayed(vec!["a".to_string()],
+                                         Duration::from_secs(10),
+                                         Duration::from_secs(1)).unwrap() != uuid);
+}
+
+#[test]
+fn test_connection_strategy() {
+    let pooled = Arc::new(Queue::with_options("redis://localhost/",
+                                              "test-pooled",
+                                              QueueOptions {
+                                                  connections: ConnectionStrategy::Pooled(2),
+                                                  ..Default::default()
+                                              }));
+    Queue::drop(&pooled).unwrap();
+
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let queue = pooled.clone();
+            thread::spawn(move || {
+                (0..5)
+                    .map(|_| queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap())
+                    .collect::<Vec<String>>()
+            })
+        })
+        .collect();
+    for handle in handles {
+        for uuid in handle.join().unwrap() {
+            assert!(pooled.status(&uuid).unwrap() == Status::QUEUED);
+        }
+    }
+
+    // An error reply closes the connection, the next call gets a working one
+    let shared = Queue::with_options("redis://localhost/",
+                                     "test-shared",
+                                     QueueOptions {
+                                         connections: ConnectionStrategy::Shared,
+                                         ..Default::default()
+                                     });
+    let client = redis::Client::open("redis://localhost/").unwrap();
+    let conn = client.get_connection().unwrap();
+    let _: () = conn.del("test-shared:wrongtype").unwrap();
+    let _: () = conn.rpush("test-shared:wrongtype", "a").unwrap();
+    assert!(shared.status("wrongtype").is_err());
+    let uuid = shared.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    assert!(shared.status(&uuid).unwrap() == Status::QUEUED);
+
+    // A connection which has been idle is checked before reuse, one closed meanwhile is replaced
+    let idle = Queue::with_options("redis://localhost/",
+                                   "test-pooled",
+                                   QueueOptions {
+                                       connections: ConnectionStrategy::Pooled(1),
+                                       client_name: Some("test-pooled-idle".to_string()),
+                                       ..Default::default()
+                                   });
+    let uuid = idle.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let list: String = redis::cmd("CLIENT").arg("LIST").query(&conn).unwrap();
+    let id = list.lines()
+        .find(|o| o.contains("name=test-pooled-idle"))
+        .and_then(|o| o.split(' ').next())
+        .and_then(|o| o.strip_prefix("id="))
+        .unwrap();
+    let killed: usize = redis::cmd("CLIENT").arg("KILL").arg("ID").arg(id).query(&conn).unwrap();
+    assert!(killed == 1);
+    sleep(Duration::from_millis(1100));
+    assert!(idle.status(&uuid).unwrap() == Status::QUEUED);
+}
+
+#[test]
+fn test_statuses_results() {
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-statuses",
+                                    QueueOptions {
+                                        connections: ConnectionStrategy::Pooled(3),
+                                        ..Default::default()
+                                    });
+    queue.drop().unwrap();
+
+    let queued = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let finished = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let _: () = queue.with_connection(|conn| {
+            conn.set(format!("test-statuses:{}", finished),
+                     format!("{{\"uuid\":\"{}\",\"status\":\"FINISHED\",\"args\":[],\
+                              \"result\":\"done\"}}",
+                             finished))
+        })
+        .unwrap();
+    assert!(queue.statuses(&[]).unwrap().is_empty());
+
+    // Large enough to be split over the pooled connections, order is kept across the batches
+    let mut uuids: Vec<String> = (0..1500).map(|o| format!("missing-{}", o)).collect();
+    uuids[0] = finished.clone();
+    uuids[700] = queued.clone();
+    uuids[1499] = finished.clone();
+    let statuses = queue.statuses(&uuids).unwrap();
+    assert!(statuses.len() == 1500);
+    assert!(statuses[0] == Some(Status::FINISHED));
+    assert!(statuses[700] == Some(Status::QUEUED));
+    assert!(statuses[1499] == Some(Status::FINISHED));
+    assert!(statuses.iter().filter(|o| o.is_some()).count() == 3);
+    let results = queue.results(&uuids).unwrap();
+    assert!(results[0] == Some("done".to_string()));
+    assert!(results[700].is_none());
+    assert!(results[1499] == Some("done".to_string()));
+
+    let single = Queue::new("redis://localhost/", "test-statuses");
+    assert!(single.statuses(&uuids).unwrap() == statuses);
+    assert!(single.results(&[queued, finished]).unwrap() == vec![None, Some("done".to_string())]);
+}
+
+#[test]
+fn test_pinned_jobs() {
+    let clock = Arc::new(MockClock::new(Duration::from_secs(1_000_000)));
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-pinned",
+                                    QueueOptions {
+                                        pin: true,
+                                        clock: clock.clone(),
+                                        args_list: true,
+                                        ..Default::default()
+                                    });
+    queue.drop().unwrap();
+    assert!(queue.eviction_policy().unwrap() == "noeviction");
+
+    let queued = queue.enqueue_for(vec!["a".to_string()], Duration::from_secs(10)).unwrap();
+    let cancelled = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    assert!(queue.cancel(&cancelled).unwrap());
+    assert!(queue.ttl(&queued).unwrap() == Some(-1));
+    assert!(queue.ttl(&cancelled).unwrap() == Some(-1));
+    let args_key = format!("test-pinned:{}:args", queued);
+    let args_ttl: i64 = queue.with_connection(|conn| redis::cmd("TTL").arg(&args_key).query(conn))
+        .unwrap();
+    assert!(args_ttl == -1);
+    queue.compact().unwrap();
+    assert!(queue.exists(&queued).unwrap());
+
+    // Pinned jobs are only deleted once past their deadline on the queue clock
+    clock.advance(Duration::from_secs(11));
+    assert!(queue.compact().unwrap() == 2);
+    assert!(!queue.exists(&queued).unwrap());
+    assert!(!queue.exists(&cancelled).unwrap());
+    let deadlines: usize = queue.with_connection(|conn| conn.zcard("test-pinned:deadlines"))
+        .unwrap();
+    assert!(deadlines == 0);
+}
+
+#[test]
+fn test_pinned_delayed() {
+    let clock = Arc::new(MockClock::new(Duration::from_secs(1_000_000)));
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-pinned-delayed",
+                                    QueueOptions {
+                                        pin: true,
+                                        clock: clock.clone(),
+                                        args_list: true,
+                                        ..Default::default()
+                                    });
+    queue.drop().unwrap();
+
+    let delayed = queue.enqueue_delayed(vec!["a".to_string()],
+                                        Duration::from_secs(10),
+                                        Duration::from_secs(5))
+        .unwrap();
+    let unique = queue.enqueue_unique_delayed(vec!["b".to_string()],
+                                              Duration::from_secs(10),
+                                              Duration::from_secs(5))
+        .unwrap();
+    queue.schedule_recurring(vec![], Duration::from_secs(10), Duration::from_secs(60)).unwrap();
+    let promoted = queue.promote().unwrap();
+    assert!(promoted.len() == 1);
+    let args_key = format!("test-pinned-delayed:{}:args", delayed);
+    let args_ttl: i64 = queue.with_connection(|conn| redis::cmd("TTL").arg(&args_key).query(conn))
+        .unwrap();
+    assert!(args_ttl == -1);
+    for uuid in [&delayed, &unique, &promoted[0]] {
+        assert!(queue.ttl(uuid).unwrap() == Some(-1));
+    }
+
+    // Recurring run is due at once, delayed jobs once their delay and expiration have passed
+    clock.advance(Duration::from_secs(11));
+    queue.compact().unwrap();
+    assert!(!queue.exists(&promoted[0]).unwrap());
+    assert!(queue.exists(&delayed).unwrap() && queue.exists(&unique).unwrap());
+    clock.advance(Duration::from_secs(5));
+    queue.compact().unwrap();
+    assert!(!queue.exists(&delayed).unwrap() && !queue.exists(&unique).unwrap());
+}
+
+#[test]
+fn test_with_connection() {
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-with-connection",
+                                    QueueOptions {
+                                        connections: ConnectionStrategy::Pooled(1),
+                                        ..Default::default()
+                                    });
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let ready: usize = queue.with_connection(|conn| conn.llen("test-with-connection:uuids"))
+        .unwrap();
+    assert!(ready == 1);
+
+    // Redis errors are returned and the pooled connection is still usable afterwards
+    let _: () = queue.with_connection(|conn| conn.set("test-with-connection:string", "a")).unwrap();
+    let failed: Result<usize, _> =
+        queue.with_connection(|conn| conn.llen("test-with-connection:string"));
+    assert!(failed.is_err());
+    assert!(queue.status(&uuid).unwrap() == Status::QUEUED);
+    let _: () = queue.with_connection(|conn| conn.del("test-with-connection:string")).unwrap();
+}
+
+#[test]
+fn test_shared_queue_enqueue() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Queue>();
+
+    let queue = Arc::new(Queue::new("redis://localhost/", "test-shared"));
+    Queue::drop(&queue).unwrap();
+
+    let handles: Vec<_> = (0..50)
+        .map(|_| {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                (0..10)
+                    .map(|_| queue.enqueue_for(vec![], Duration::from_secs(30)).unwrap())
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    let uuids: Vec<String> = handles.into_iter().flat_map(|h| h.join().unwrap()).collect();
+
+    assert!(uuids.len() == 500);
+    assert!(uuids.iter().collect::<HashSet<_>>().len() == 500);
+    for uuid in uuids.iter() {
+        assert!(queue.status(uuid).unwrap() == Status::QUEUED);
+    }
+}
+
+#[test]
+fn test_stream_job_finished() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        sleep(Duration::from_millis(1000));
+        Ok("ok".to_string())
+    }
+
+    let queue = StreamQueue::new("redis://localhost/", "test-stream-finished", "workers");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue(vec![], Duration::from_secs(10)).unwrap();
+    assert!(queue.status(&uuid).unwrap() == Status::QUEUED);
+
+    let options = WorkOptions {
+        wait: Duration::from_secs(1),
+        timeout: Duration::from_secs(5),
+        expire: Duration::from_secs(5),
+        fall: false,
+        infinite: false,
+        ..Default::default()
+    };
+    let summary = queue.work_with(fn_ok, "worker", &options).unwrap();
+    assert!(summary.processed == 1);
+    assert!(summary.finished == 1);
+
+    let status = queue.status(&uuid).unwrap();
+    assert!(status == Status::FINISHED);
+    assert!(queue.result(&uuid).unwrap().unwrap() == "ok");
+    assert!(queue.pending().unwrap() == 0);
+}
+
+#[test]
+fn test_stream_fall_error() {
+    fn fn_slow(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        sleep(Duration::from_millis(2000));
+        Ok("ok".to_string())
+    }
+
+    let queue = StreamQueue::new("redis://localhost/", "test-stream-fall-error", "workers");
+    queue.drop().unwrap();
+
+    let uuid = queue.enqueue(vec![], Duration::from_secs(10)).unwrap();
+    let options = WorkOptions {
+        wait: Duration::from_secs(1),
+        timeout: Duration::from_secs(1),
+        expire: Duration::from_secs(5),
+        on_fall: FallPolicy::Error,
+        ..Default::default()
+    };
+    let err = queue.work_with(fn_slow, "worker", &options).unwrap_err();
+    match err.downcast_ref::<RjqError>() {
+        Some(RjqError::JobLost { uuid: lost, .. }) => assert!(*lost == uuid),
+        _ => panic!("unexpected error {}", err),
+    }
+    assert!(queue.status(&uuid).unwrap() == Status::LOST);
+    assert!(queue.pending().unwrap() == 0);
+}
+
+#[test]
+fn test_stream_at_most_once() {
+    fn fn_pending(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        let queue = StreamQueue::new("redis://localhost/", "test-stream-at-most-once", "workers");
+        Ok(queue.pending()?.to_string())
+    }
+
+    let queue = StreamQueue::new("redis://localhost/", "test-stream-at-most-once", "workers");
+    queue.drop().unwrap();
+
+    let options = WorkOptions {
+        wait: Duration::from_secs(1),
+        timeout: Duration::from_secs(5),
+        freq: 10,
+        expire: Duration::from_secs(5),
+        fall: false,
+        infinite: false,
+        ..Default::default()
+    };
+
+    // Entry is still pending while the job runs
+    let uuid = queue.enqueue(vec![], Duration::from_secs(10)).unwrap();
+    queue.work_with(fn_pending, "worker", &options).unwrap();
+    assert!(queue.result(&uuid).unwrap().unwrap() == "1");
+
+    // and acknowledged before the job runs at most once
+    let queue =
+        StreamQueue::at_most_once("redis://localhost/", "test-stream-at-most-once", "workers");
+    let uuid = queue.enqueue(vec![], Duration::from_secs(10)).unwrap();
+    queue.work_with(fn_pending, "worker", &options).unwrap();
+    assert!(queue.status(&uuid).unwrap() == Status::FINISHED);
+    assert!(queue.result(&uuid).unwrap().unwrap() == "0");
+    assert!(queue.pending().unwrap() == 0);
+}
+
+#[test]
+fn test_stream_redelivered() {
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        RUNS.fetch_add(1, Ordering::SeqCst);
+        Ok("ok".to_string())
+    }
+
+    let queue = StreamQueue::new("redis://localhost/", "test-stream-redelivered", "workers");
+    queue.drop().unwrap();
+
+    let options = WorkOptions {
+        wait: Duration::from_millis(100),
+        timeout: Duration::from_secs(1),
+        expire: Duration::from_secs(2),
+        fall: false,
+        infinite: false,
+        ..Default::default()
+    };
+    // Group is made by the first worker
+    assert!(queue.work_with(fn_ok, "worker", &options).unwrap().processed == 0);
+
+    // A worker reads both entries and dies before acknowledging them, one job is gone since
+    let gone = queue.enqueue(vec![], Duration::from_secs(10)).unwrap();
+    let uuid = queue.enqueue(vec![], Duration::from_secs(10)).unwrap();
+    let client = redis::Client::open("redis://localhost/").unwrap();
+    let conn = client.get_connection().unwrap();
+    let _: redis::Value = redis::cmd("XREADGROUP").arg("GROUP")
+        .arg("workers")
+        .arg("dead")
+        .arg("COUNT")
+        .arg(2)
+        .arg("STREAMS")
+        .arg("test-stream-redelivered:stream")
+        .arg(">")
+        .query(&conn)
+        .unwrap();
+    let _: () = conn.del(format!("test-stream-redelivered:{}", gone)).unwrap();
+    assert!(queue.pending().unwrap() == 2);
+
+    // Entries are left to their consumer for the timeout and half the expiration
+    assert!(queue.work_with(fn_ok, "worker", &options).unwrap().processed == 0);
+    assert!(queue.status(&uuid).unwrap() == Status::QUEUED);
+
+    // then claimed by another one, the job is processed exactly once
+    sleep(Duration::from_millis(2100));
+    let summary = queue.work_with(fn_ok, "worker", &options).unwrap();
+    assert!(summary.processed == 1 && summary.finished == 1);
+    assert!(queue.status(&uuid).unwrap() == Status::FINISHED);
+    assert!(queue.pending().unwrap() == 0);
+
+    assert!(queue.work_with(fn_ok, "other", &options).unwrap().processed == 0);
+    assert!(RUNS.load(Ordering::SeqCst) == 1);
+
+    // An entry of a job which isn't queued any more is acknowledged without running it
+    let _: String = redis::cmd("XADD").arg("test-stream-redelivered:stream")
+        .arg("*")
+        .arg("uuid")
+        .arg(&uuid)
+        .query(&conn)
+        .unwrap();
+    assert!(queue.work_with(fn_ok, "worker", &options).unwrap().processed == 0);
+    assert!(queue.pending().unwrap() == 0);
+    assert!(RUNS.load(Ordering::SeqCst) == 1);
+}
+
+#[test]
+fn test_enqueue_validator() {
+    let validator = |args: &[String]| {
+        if args.iter().any(|o| o.is_empty()) {
+            Err("empty argument".to_string())
+        } else {
+            Ok(())
+        }
+    };
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-validator",
+                                    QueueOptions {
+                                        validator: Some(Arc::new(validator)),
+                                        ..Default::default()
+                                    });
+    queue.drop().unwrap();
+
+    let err = queue.enqueue_for(vec!["a".to_string(), "".to_string()], Duration::from_secs(10))
+        .unwrap_err();
+    assert!(matches!(err.downcast_ref::<RjqError>(),
+                     Some(RjqError::Invalid(ref o)) if o == "empty argument"));
+    assert!(queue.enqueue_unique(vec!["".to_string()], Duration::from_secs(10)).is_err());
+    assert!(queue.enqueue_delayed(vec!["".to_string()],
+                                  Duration::from_secs(10),
+                                  Duration::from_secs(10)).is_err());
+    let keys: Vec<String> = queue.with_connection(|conn| conn.keys("test-validator:*")).unwrap();
+    assert!(keys.is_empty());
+
+    let uuid = queue.enqueue_for(vec!["a".to_string()], Duration::from_secs(10)).unwrap();
+    assert!(queue.status(&uuid).unwrap() == Status::QUEUED);
+}
+
+#[test]
+fn test_enqueue_payload_too_large() {
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-payload",
+                                    QueueOptions { max_payload: 8, ..Default::default() });
+    queue.drop().unwrap();
+
+    let err = queue.enqueue_for(vec!["abcd".to_string(), "efghi".to_string()],
+                                Duration::from_secs(10))
+        .unwrap_err();
+    assert!(matches!(err.downcast_ref::<RjqError>(),
+                     Some(&RjqError::PayloadTooLarge { size: 9, limit: 8 })));
+    assert!(queue.enqueue_value(serde_json::Value::String("abcdefgh".to_string()),
+                                Duration::from_secs(10)).is_err());
+    let keys: Vec<String> = queue.with_connection(|conn| conn.keys("test-payload:*")).unwrap();
+    assert!(keys.is_empty());
+
+    let uuid = queue.enqueue_for(vec!["abcd".to_string(), "efgh".to_string()],
+                                 Duration::from_secs(10))
+        .unwrap();
+    assert!(queue.status(&uuid).unwrap() == Status::QUEUED);
+}
+
+#[test]
+fn test_enqueue_blocking() {
+    let queue = Arc::new(Queue::new("redis://localhost/", "test-enqueue-blocking"));
+    Queue::drop(&queue).unwrap();
+
+    let expire = Duration::from_secs(10);
+    let first = queue.enqueue_blocking(vec![], expire, 2, Duration::from_millis(100)).unwrap();
+    queue.enqueue_kind("images", vec![], Duration::from_secs(10)).unwrap();
+
+    // Jobs of all kinds count towards the limit
+    let err = queue.enqueue_blocking(vec![], expire, 2, Duration::from_millis(300)).unwrap_err();
+    assert!(matches!(err.downcast_ref::<RjqError>(),
+                     Some(&RjqError::QueueFull { len: 2, max_len: 2 })));
+
+    let worker = queue.clone();
+    let consumer = thread::spawn(move || {
+        sleep(Duration::from_millis(300));
+        let job = worker.reserve_for(Duration::from_secs(1)).unwrap().unwrap();
+        job.complete("ok".to_string()).unwrap();
+    });
+    let started = Instant::now();
+    let uuid = queue.enqueue_blocking(vec![], expire, 2, Duration::from_secs(5)).unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(200));
+    consumer.join().unwrap();
+    assert!(queue.status(&first).unwrap() == Status::FINISHED);
+    assert!(queue.status(&uuid).unwrap() == Status::QUEUED);
+}
+
+#[test]
+fn test_dedupe() {
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-dedupe",
+                                    QueueOptions { dedupe: true, ..Default::default() });
+    queue.drop().unwrap();
+    let _: () = queue.with_connection(|conn| conn.del("test-dedupe:processing")).unwrap();
+
+    // A queued job left on the processing list while still listed as ready
+    let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    let _: () = queue.with_connection(|conn| conn.rpush("test-dedupe:processing", &uuid)).unwrap();
+    assert!(queue.recover().unwrap() == 1);
+    let listed: Vec<String> =
+        queue.with_connection(|conn| conn.lrange("test-dedupe:uuids", 0, -1)).unwrap();
+    assert!(listed == vec![uuid.clone()]);
+
+    let queue = Queue::new("redis://localhost/", "test-dedupe");
+    let _: () = queue.with_connection(|conn| conn.rpush("test-dedupe:processing", &uuid)).unwrap();
+    assert!(queue.recover().unwrap() == 1);
+    let listed: Vec<String> =
+        queue.with_connection(|conn| conn.lrange("test-dedupe:uuids", 0, -1)).unwrap();
+    assert!(listed == vec![uuid.clone(), uuid]);
+    queue.drop().unwrap();
+}
+
+#[test]
+fn test_stats() {
+    fn fn_args(_: String, args: Vec<String>) -> Result<String, Box<dyn Error>> {
+        match args.first() {
+            Some(o) if o == "fail" => Err("failed".into()),
+            _ => Ok("ok".to_string()),
+        }
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-stats");
+    queue.drop().unwrap();
+    let _: () = queue.with_connection(|conn| conn.del(&["test-stats:counts", "test-stats:dead"]))
+        .unwrap();
+
+    let expire = Duration::from_secs(10);
+    queue.enqueue_for(vec!["ok".to_string()], expire).unwrap();
+    queue.enqueue_for(vec!["fail".to_string()], expire).unwrap();
+    let cancelled = queue.enqueue_for(vec![], expire).unwrap();
+    queue.cancel(&cancelled).unwrap();
+    queue.enqueue_for(vec![], expire).unwrap();
+    let _: () = queue.with_connection(|conn| conn.rpush("test-stats:dead", "corrupt")).unwrap();
+    let options = WorkOptions {
+        wait: Duration::from_secs(1),
+        fall: false,
+        max_jobs: Some(2),
+        ..Default::default()
+    };
+    queue.work_with(fn_args, &options).unwrap();
+
+    let exact = QueueStats {
+        queued: 1,
+        finished: 1,
+        failed: 1,
+        cancelled: 1,
+        dead: 1,
+        ..Default::default()
+    };
+    assert!(queue.stats().unwrap() == exact);
+    // The cancelled job is still on the ready list until a worker skips it
+    assert!(queue.stats_estimate().unwrap() == QueueStats { queued: 2, ..exact });
+}
+
+#[test]
+fn test_queue_rename() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let old = Queue::new("redis://localhost/", "test-rename-old");
+    let new = Queue::new("redis://localhost/", "test-rename-new");
+    let keys: Vec<String> = old.with_connection(|conn| conn.keys("test-rename-*")).unwrap();
+    if !keys.is_empty() {
+        old.with_connection(|conn| conn.del::<_, ()>(keys)).unwrap();
+    }
+
+    let plain = old.enqueue_for(vec!["a".to_string()], Duration::from_secs(10)).unwrap();
+    let unique = old.enqueue_unique(vec!["b".to_string()], Duration::from_secs(10)).unwrap();
+    let kinded = old.enqueue_kind("images", vec![], Duration::from_secs(10)).unwrap();
+
+    // Job keys, the uniqueness lock, ready lists and the kinds set are moved
+    assert!(old.rename("test-rename-new").unwrap() == 7);
+    assert!(!old.exists(&plain).unwrap());
+    assert!(new.status(&plain).unwrap() == Status::QUEUED);
+    assert!(new.args(&plain).unwrap() == Some(vec!["a".to_string()]));
+    assert!(new.enqueue_unique(vec!["b".to_string()], Duration::from_secs(10)).unwrap() == unique);
+
+    let options = WorkOptions {
+        wait: Duration::from_secs(1),
+        freq: 10,
+        fall: false,
+        max_jobs: Some(1),
+        kinds: Some(vec!["images".to_string()]),
+        ..Default::default()
+    };
+    new.work_with(fn_ok, &options).unwrap();
+    assert!(new.status(&kinded).unwrap() == Status::FINISHED);
+
+    // Nothing is renamed onto existing keys
+    let left = old.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    assert!(old.rename("test-rename-new").is_err());
+    assert!(old.status(&left).unwrap() == Status::QUEUED);
+    assert!(old.rename("test-rename-new:nested").is_err());
+    assert!(old.rename("test-rename-old").is_err());
+}
+
+#[test]
+fn test_enqueue_stream() {
+    let queue = Queue::with_options("redis://localhost/",
+                                    "test-enqueue-stream",
+                                    QueueOptions { max_payload: 8, ..Default::default() });
+    queue.drop().unwrap();
+
+    let batch: Vec<Vec<String>> = (0..250).map(|o| vec![o.to_string()]).collect();
+    let rx = queue.enqueue_stream(batch, Duration::from_secs(10)).unwrap();
+    let first = rx.recv().unwrap().unwrap();
+    assert!(queue.status(&first).unwrap() == Status::QUEUED);
+    let mut uuids = vec![first];
+    uuids.extend(rx.iter().map(|o| o.unwrap()));
+    assert!(uuids.len() == 250);
+    assert!(queue.args(&uuids[249]).unwrap() == Some(vec!["249".to_string()]));
+    let listed: Vec<String> =
+        queue.with_connection(|conn| conn.lrange("test-enqueue-stream:uuids", 0, -1)).unwrap();
+    assert!(listed == uuids);
+
+    // A batch with an invalid job isn't written at all
+    let batch = vec![vec!["a".to_string()], vec!["too large".to_string()]];
+    assert!(queue.enqueue_stream(batch, Duration::from_secs(10)).is_err());
+    let listed: usize =
+        queue.with_connection(|conn| conn.llen("test-enqueue-stream:uuids")).unwrap();
+    assert!(listed == 250);
+}
+
+#[test]
+fn test_queue_names() {
+    for name in ["", "a:uuids", ":", "a:"] {
+        let queue = Queue::try_new("redis://localhost/", name, QueueOptions::default());
+        assert!(matches!(queue, Err(RjqError::InvalidName(_))), "{}", name);
+    }
+
+    // Names equal to key suffixes or holding glob characters don't overlap with other keys
+    for name in ["uuids", "test-names-*?[]", "test-names-uuids"] {
+        let queue = Queue::try_new("redis://localhost/", name, QueueOptions::default()).unwrap();
+        queue.drop().unwrap();
+        let uuid = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+        assert!(queue.status(&uuid).unwrap() == Status::QUEUED);
+        assert!(queue.compact().unwrap() == 0);
+        queue.drop().unwrap();
+        assert!(!queue.exists(&uuid).unwrap());
+    }
+}
+
+#[test]
+fn test_job_result_bytes() {
+    fn fn_png(_: String, _: Vec<String>) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(vec![0x89, b'P', b'N', b'G', 0x00, 0xff])
+    }
+
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let queue = Queue::new("redis://localhost/", "test-result-bytes");
+    queue.drop().unwrap();
+
+    let options = WorkOptions {
+        wait: Duration::from_secs(1),
+        freq: 10,
+        fall: false,
+        infinite: false,
+        ..Default::default()
+    };
+    let binary = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    queue.work_bytes(fn_png, &options).unwrap();
+    assert!(queue.status(&binary).unwrap() == Status::FINISHED);
+    assert!(queue.result_bytes(&binary).unwrap() == Some(vec![0x89, b'P', b'N', b'G', 0x00, 0xff]));
+    assert!(queue.result(&binary).unwrap().is_none());
+
+    let text = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    queue.work_with(fn_ok, &options).unwrap();
+    assert!(queue.result_bytes(&text).unwrap() == Some(b"ok".to_vec()));
+
+    // Cut to the maximum length like text results
+    let cut = queue.enqueue_for(vec![], Duration::from_secs(10)).unwrap();
+    queue.work_bytes(fn_png, &WorkOptions { max_result: Some(4), ..options.clone() }).unwrap();
+    assert!(queue.result_bytes(&cut).unwrap() == Some(b"\x89PNG".to_vec()));
+    assert!(queue.result_truncated(&cut).unwrap());
+
+    assert!(queue.clear_results().unwrap() == 3);
+    assert!(queue.result_bytes(&binary).unwrap().is_none());
+}
+
+#[test]
+fn test_job_move_to() {
+    fn fn_ok(_: String, _: Vec<String>) -> Result<String, Box<dyn Error>> {
+        Ok("ok".to_string())
+    }
+
+    let source = Queue::new("redis://localhost/", "test-move-source");
+    let target = Queue::new("redis://localhost/", "test-move-target");
+    source.drop().unwrap();
+    target.drop().unwrap();
+
+    let uuid = source.enqueue_for(vec!["a".to_string()], Duration::from_secs(10)).unwrap();
+    assert!(source.move_to(&uuid, &target).unwrap());
+    assert!(!source.exists(&uuid).unwrap());
+    assert!(target.status(&uuid).unwrap() == Status::QUEUED);
+    assert!(target.args(&uuid).unwrap() == Some(vec!["a".to_string()]));
+    assert!(target.ttl(&uuid).unwrap().is_some_and(|o| o > 0 && o <= 10));
+    assert!(!source.move_to(&uuid, &target).unwrap());
+
+    // Running jobs stay where they are
+    let running = source.enqueue_with_status(vec![],
+                                             Duration::from_secs(10),
+                                             Status::RUNNING)
+        .unwrap();
+    assert!(!source.move_to(&running, &target).unwrap());
+    assert!(source.status(&running).unwrap() == Status::RUNNING);
+
+    let options = WorkOptions {
+        wait: Duration::from_secs(1),
+        fall: false,
+        infinite: false,
+        ..Default::default()
+    };
+    assert!(source.work_with(fn_ok, &options).unwrap().processed == 0);
+    assert!(target.work_with(fn_ok, &options).unwrap().finished == 1);
+    assert!(target.result(&uuid).unwrap().unwrap() == "ok");
+}
+
+#[test]
+fn test_server_version() {
+    let queue = Queue::new("redis://localhost/", "test-server-version");
+
+    let version = queue.server_version().unwrap();
+    let info: String = queue.with_connection(|conn| redis::cmd("INFO").arg("server").query(conn))
+        .unwrap();
+    let (major, minor, patch) = version;
+    assert!(info.contains(&format!("redis_version:{}.{}.{}", major, minor, patch)));
+
+    // Probed once, clones share it
+    assert!(queue.clone().server_version().unwrap() == version);
 }